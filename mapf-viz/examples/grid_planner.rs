@@ -28,7 +28,7 @@ use iced::{
 };
 use iced_native;
 use mapf::{
-    trace::NoTrace,
+    trace::{RecordingTrace, TraceLogError},
     planner::make_planner,
     node::{Weighted, Informed, PartialKeyed, Agent},
     expander::{Constrain, Constrainable, Solvable, SolutionOf},
@@ -42,7 +42,7 @@ use mapf::{
             self, Rotation,
             timed_position::{Waypoint, DifferentialDriveLineFollow},
             graph_search::{
-                DirectedTimeVariantExpander, DirectedTimeInvariantExpander, StartSE2, GoalSE2, LinearSE2Policy,
+                DirectedTimeVariantExpander, DirectedTimeInvariantExpander, StartSE2, GoalSE2, LinearSE2Policy, KeySE2,
                 FreeSpaceTimeInvariantExpander, FreeSpaceTimeVariantExpander,
             },
         },
@@ -58,9 +58,12 @@ use mapf_viz::{
 use mapf_viz::spatial_layers;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::{fs::File, io::BufWriter, path::PathBuf};
 
 type Visibility = mapf::occupancy::Visibility<SparseGrid>;
 type SearchNode = se2::graph_search::Node<GraphKey, 100>;
+/// Recorded searches can be replayed with the search_replay example
+type SearchTrace = RecordingTrace<KeySE2<GraphKey, 100>, i64>;
 
 pub(crate) struct Minimum<T: Clone, F: Fn(&T, &T) -> std::cmp::Ordering> {
     value: Option<T>,
@@ -177,6 +180,8 @@ fn draw_trajectory(
 struct EndpointSelector<Message> {
     pub agent_radius: f64,
     pub cell_size: f64,
+    /// The location of the bottom left corner of cell (0, 0)
+    pub origin: Point,
     pub invalid_color: iced::Color,
     pub start_color: iced::Color,
     pub goal_color: iced::Color,
@@ -213,6 +218,7 @@ impl<Message> EndpointSelector<Message> {
     fn new(
         agent_radius: f64,
         cell_size: f64,
+        origin: Point,
         invalid_color: iced::Color,
         start_color: iced::Color,
         goal_color: iced::Color,
@@ -220,6 +226,7 @@ impl<Message> EndpointSelector<Message> {
         Self{
             agent_radius,
             cell_size,
+            origin,
             invalid_color,
             start_color,
             goal_color,
@@ -239,6 +246,10 @@ impl<Message> EndpointSelector<Message> {
         }
     }
 
+    fn cell_center(&self, cell: &Cell) -> Point {
+        cell.to_center_point(self.cell_size) + self.origin.coords
+    }
+
     fn endpoint_cell(&self, choice: Endpoint) -> &Option<Cell> {
         match choice {
             Endpoint::Start => &self.start_cell,
@@ -272,8 +283,8 @@ impl<Message> EndpointSelector<Message> {
         self.start_sees_goal = false;
         if self.start_valid && self.goal_valid {
             if let (Some(cell_start), Some(cell_goal)) = (self.start_cell, self.goal_cell) {
-                let p_start = cell_start.to_center_point(self.cell_size);
-                let p_goal = cell_goal.to_center_point(self.cell_size);
+                let p_start = self.cell_center(&cell_start);
+                let p_goal = self.cell_center(&cell_goal);
                 self.start_sees_goal = visibility.grid().is_sweep_occupied(
                     p_start, p_goal, 2.0*self.agent_radius
                 ).is_none();
@@ -310,7 +321,10 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
                         self.pressed = true;
                         let endpoint = if self.shift.state() == Toggle::On { Endpoint::Goal } else { Endpoint::Start };
                         *self.endpoint_cell_mut(endpoint) = Some(
-                            Cell::from_point([p.x as f64, p.y as f64].into(), self.cell_size)
+                            Cell::from_point(
+                                Point::new(p.x as f64, p.y as f64) - self.origin.coords,
+                                self.cell_size,
+                            )
                         );
 
                         return (
@@ -350,7 +364,7 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
         ] {
             let radius = self.agent_radius as f32;
             if let Some(cell) = cell {
-                let p = cell.to_center_point(self.cell_size);
+                let p = self.cell_center(&cell);
                 draw_agent(frame, p, angle, self.agent_radius as f32, color);
                 if !valid {
                     frame.stroke(
@@ -369,9 +383,9 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
                 (self.goal_cell, &self.goal_visibility, self.goal_color)
             ] {
                 if let Some(cell) = cell_opt {
-                    let p = cell.to_center_point(self.cell_size);
+                    let p = self.cell_center(&cell);
                     for v_cell in visible {
-                        let p_v = v_cell.to_center_point(self.cell_size);
+                        let p_v = self.cell_center(v_cell);
                         frame.stroke(
                             &Path::line(
                                 [p.x as f32, p.y as f32].into(),
@@ -389,8 +403,8 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
 
             if self.start_sees_goal {
                 if let (Some(cell_s), Some(cell_g)) = (self.start_cell, self.goal_cell) {
-                    let p_start = cell_s.to_center_point(self.cell_size);
-                    let p_goal = cell_g.to_center_point(self.cell_size);
+                    let p_start = self.cell_center(&cell_s);
+                    let p_goal = self.cell_center(&cell_g);
                     frame.stroke(
                         &Path::line(
                             [p_start.x as f32, p_start.y as f32].into(),
@@ -416,7 +430,7 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
         if self.show_details {
             for cell_opt in [self.start_cell, self.goal_cell] {
                 if let Some(cell) = cell_opt {
-                    let p = cell.to_center_point(self.cell_size);
+                    let p = self.cell_center(&cell);
                     let r = self.agent_radius as f32;
                     let delta = iced::Vector::new(r, -r);
                     let p = iced::Point::new(p.x as f32, p.y as f32) + delta;
@@ -438,7 +452,7 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
         let mut zone = InclusionZone::Empty;
         for endpoint in [self.start_cell, self.goal_cell] {
             if let Some(cell) = endpoint {
-                let p = cell.to_center_point(self.cell_size);
+                let p = self.cell_center(&cell);
                 for v in [[1.0, 1.0], [-1.0, -1.0]] {
                     let v: Vector = v.into();
                     let r = p + self.agent_radius*v;
@@ -454,6 +468,8 @@ impl SpatialCanvasProgram<Message> for EndpointSelector<Message> {
 #[derive(Debug, Clone)]
 struct SolutionVisual<Message> {
     pub cell_size: f64,
+    /// The location of the bottom left corner of cell (0, 0)
+    pub origin: Point,
     pub agent_radius: f32,
     pub path_color: iced::Color,
     pub solution: Option<Trajectory<Waypoint>>,
@@ -465,7 +481,7 @@ struct SolutionVisual<Message> {
 }
 
 impl SolutionVisual<Message> {
-    fn new(cell_size: f64, agent_radius: f32, path_color: iced::Color) -> Self {
+    fn new(cell_size: f64, origin: Point, agent_radius: f32, path_color: iced::Color) -> Self {
 
         let t_obs = Trajectory::from_iter([
             se2::timed_position::Waypoint::new(TimePoint::from_secs_f64(0.0), 10.0, 0.0, 180_f64.to_radians()),
@@ -477,6 +493,7 @@ impl SolutionVisual<Message> {
 
         Self{
             cell_size,
+            origin,
             agent_radius,
             path_color,
             solution: None,
@@ -488,6 +505,10 @@ impl SolutionVisual<Message> {
         }
     }
 
+    fn cell_center(&self, cell: &Cell) -> Point {
+        cell.to_center_point(self.cell_size) + self.origin.coords
+    }
+
     fn reset_time(&mut self) {
         self.tick_start = Some(TimePoint::from_std_instant(std::time::Instant::now()));
         self.now = Some(Duration::zero());
@@ -595,7 +616,8 @@ impl SpatialCanvasProgram<Message> for SolutionVisual<Message> {
             for (i, wp) in trajectory.iter().enumerate() {
                 sequence.entry(
                     Cell::from_point(
-                        Point::from(wp.position.translation.vector), self.cell_size
+                        Point::from(wp.position.translation.vector) - self.origin.coords,
+                        self.cell_size,
                     )
                 ).or_default().push(i.to_string());
             }
@@ -603,7 +625,7 @@ impl SpatialCanvasProgram<Message> for SolutionVisual<Message> {
             let r = self.agent_radius / 2_f32.sqrt();
             let delta = iced::Vector::new(r, r);
             for (cell, seq) in sequence {
-                let p = cell.to_center_point(self.cell_size);
+                let p = self.cell_center(&cell);
                 let p = iced::Point::new(p.x as f32, p.y as f32) + delta;
                 if bound.contains(p) {
                     hud.at(
@@ -618,7 +640,7 @@ impl SpatialCanvasProgram<Message> for SolutionVisual<Message> {
         }
 
         for (cell, v) in &self.vertex_lookup {
-            let p = cell.to_center_point(self.cell_size);
+            let p = self.cell_center(cell);
             let p = iced::Point::new(p.x as f32, p.y as f32);
             if bound.contains(p) {
                 hud.at(
@@ -654,7 +676,11 @@ struct App {
     node_list_scroll: scrollable::State,
     debug_text_scroll: scrollable::State,
     show_details: KeyToggler,
-    progress: Option<Progress<ObsAvoidance, a_star::Algorithm, BasicOptions, GoalSE2<GraphKey>, NoTrace>>,
+    progress: Option<Progress<ObsAvoidance, a_star::Algorithm, BasicOptions, GoalSE2<GraphKey>, SearchTrace>>,
+    last_trace: Option<SearchTrace>,
+    trace_input: text_input::State,
+    trace_path: String,
+    save_trace_button: button::State,
     step_progress: button::State,
     expander: Option<Arc<ObsAvoidance>>,
     debug_on: bool,
@@ -699,7 +725,7 @@ impl App {
 
     fn is_valid_endpoint(&self, endpoint: Endpoint) -> bool {
         if let Some(cell) = self.endpoint_selector().endpoint_cell(endpoint) {
-            let p = cell.to_center_point(self.grid().cell_size());
+            let p = self.grid().cell_center(cell);
             if self.grid().is_square_occupied(p, 2.0*self.visibility().agent_radius()).is_some() {
                 return false;
             } else {
@@ -717,7 +743,7 @@ impl App {
         endpoint_selector.agent_radius = radius as f64;
         for endpoint in [Endpoint::Start, Endpoint::Goal] {
             if let Some(cell) = endpoint_selector.endpoint_cell(endpoint) {
-                let p = cell.to_center_point(endpoint_selector.cell_size);
+                let p = endpoint_selector.cell_center(cell);
                 let valid = self.canvas.program.layers.1.grid().is_square_occupied(p, 2.0*endpoint_selector.agent_radius).is_none();
                 endpoint_selector.set_endpoint_valid(endpoint, valid);
             }
@@ -735,6 +761,7 @@ impl App {
             self.debug_step_count += 1;
             if let PlanningStatus::Solved(solution) = progress.step().unwrap() {
                 println!("Solution: {:#?}", solution);
+                self.last_trace = Some(progress.trace().clone());
                 self.canvas.program.layers.3.solution = solution.motion().clone();
                 self.debug_node_selected = None;
                 self.progress = None;
@@ -758,11 +785,32 @@ impl App {
         }
     }
 
+    /// Save the trace of the search that is in progress, or else the trace of
+    /// the last search that finished.
+    fn save_trace(&self) {
+        let trace = match self.progress.as_ref().map(|p| p.trace()).or(self.last_trace.as_ref()) {
+            Some(trace) => trace,
+            None => {
+                println!("No search has been traced yet");
+                return;
+            }
+        };
+
+        let path = PathBuf::from(&self.trace_path);
+        let result = File::create(&path)
+            .map_err(TraceLogError::from)
+            .and_then(|file| trace.write_json_lines(BufWriter::new(file)));
+
+        match result {
+            Ok(()) => println!("Saved {} events to [{}]", trace.events().len(), path.display()),
+            Err(err) => println!("Unable to save trace [{}]: {err}", path.display()),
+        }
+    }
+
     fn generate_plan(&mut self) {
         self.canvas.program.layers.3.solution = None;
         let endpoints = &self.canvas.program.layers.2;
         let visibility = self.canvas.program.layers.1.visibility();
-        let cell_size = endpoints.cell_size;
         if let (Some(start_cell), Some(goal_cell)) = (endpoints.start_cell, endpoints.goal_cell) {
             if start_cell == goal_cell {
                 // No plan is needed
@@ -773,14 +821,14 @@ impl App {
                 let mut vertices = Vec::new();
                 let mut edges: Vec<Vec<usize>> = Vec::new();
                 self.canvas.program.layers.3.vertex_lookup.clear();
-                vertices.push(start_cell.to_center_point(cell_size));
-                vertices.push(goal_cell.to_center_point(cell_size));
+                vertices.push(endpoints.cell_center(&start_cell));
+                vertices.push(endpoints.cell_center(&goal_cell));
                 let mut get_vertex_index =
                     |cell: &Cell| {
                         *self.canvas.program.layers.3.vertex_lookup.entry(*cell).or_insert_with(
                             || {
                                 let index = vertices.len();
-                                vertices.push(cell.to_center_point(cell_size));
+                                vertices.push(endpoints.cell_center(cell));
                                 index
                             }
                         )
@@ -847,7 +895,7 @@ impl App {
                 //         orientation: None,
                 //     },
                 // ).unwrap();
-                let mut progress = planner.plan(
+                let mut progress = planner.trace(
                     &StartSE2{
                         vertex: start_cell,
                         orientation: Rotation::new(0_f64),
@@ -856,6 +904,7 @@ impl App {
                         vertex: goal_cell,
                         orientation: None,
                     },
+                    SearchTrace::default(),
                 ).unwrap();
 
                 self.debug_step_count = 0;
@@ -867,7 +916,9 @@ impl App {
 
                     self.expander = Some(expander);
                 } else {
-                    match progress.solve().unwrap() {
+                    let status = progress.solve().unwrap();
+                    self.last_trace = Some(progress.trace().clone());
+                    match status {
                         PlanningStatus::Solved(solution) => {
                             println!("Solution: {:#?}", solution);
                             self.canvas.program.layers.3.solution = solution.motion().clone();
@@ -904,13 +955,15 @@ impl Application for App {
     fn new(_flags: Self::Flags) -> (Self, Command<Self::Message>) {
         let cell_size = 1.0_f32;
         let robot_radius = 0.75_f32;
+        let grid = SparseGrid::new(cell_size as f64);
+        let origin = grid.origin();
 
         let mut canvas = SpatialCanvas::new(
             GridLayers{
                 layers: (
                     InfiniteGrid::new(cell_size),
                     SparseGridOccupancyVisual::new(
-                        SparseGrid::new(cell_size as f64),
+                        grid,
                         robot_radius,
                         None,
                         Some(Box::new(|| { Message::OccupancyChanged })),
@@ -918,11 +971,12 @@ impl Application for App {
                     EndpointSelector::new(
                         robot_radius as f64,
                         cell_size as f64,
+                        origin,
                         Self::default_invalid_color(),
                         Self::default_endpoint_color(Endpoint::Start),
                         Self::default_endpoint_color(Endpoint::Goal),
                     ),
-                    SolutionVisual::new(cell_size as f64, robot_radius, Self::default_solution_color()),
+                    SolutionVisual::new(cell_size as f64, origin, robot_radius, Self::default_solution_color()),
                 )
             }
         );
@@ -940,6 +994,10 @@ impl Application for App {
                 debug_text_scroll: scrollable::State::new(),
                 show_details: KeyToggler::for_key(keyboard::KeyCode::LAlt),
                 progress: None,
+                last_trace: None,
+                trace_input: text_input::State::default(),
+                trace_path: "search_trace.jsonl".to_owned(),
+                save_trace_button: button::State::new(),
                 step_progress: button::State::new(),
                 expander: None,
                 debug_on: false,
@@ -1036,7 +1094,7 @@ impl Application for App {
                 for endpoint in [Endpoint::Start, Endpoint::Goal] {
                     let cell_opt = self.canvas.program.layers.2.endpoint_cell(endpoint);
                     if let Some(cell) = cell_opt {
-                        let p = cell.to_center_point(self.grid().cell_size());
+                        let p = self.grid().cell_center(cell);
                         let valid = self.grid().is_square_occupied(p, 2.0*self.visibility().agent_radius()).is_none();
                         self.endpoint_selector_mut().set_endpoint_valid(endpoint, valid);
                         self.recalculate_visibility();
@@ -1054,6 +1112,12 @@ impl Application for App {
             },
             Message::StepProgress => {
                 self.step_progress();
+            },
+            Message::TracePathChanged(value) => {
+                self.trace_path = value;
+            },
+            Message::SaveTrace => {
+                self.save_trace();
             }
             Message::Tick => {
                 if self.canvas.program.layers.3.tick() {
@@ -1112,6 +1176,27 @@ impl Application for App {
                 .width(Length::Fill)
             );
 
+        let trace_row = Row::new()
+            .spacing(20)
+            .align_items(Alignment::Center)
+            .width(Length::Fill)
+            .push(
+                TextInput::new(
+                    &mut self.trace_input,
+                    "Trace file",
+                    &mut self.trace_path,
+                    Message::TracePathChanged,
+                )
+                .padding(10)
+                .width(Length::Fill)
+            )
+            .push(
+                Button::new(
+                    &mut self.save_trace_button,
+                    iced::Text::new("Save Trace")
+                ).on_press(Message::SaveTrace)
+            );
+
         let instruction_row = Row::<Message>::new()
             .spacing(40)
             .align_items(Alignment::Start)
@@ -1123,6 +1208,7 @@ impl Application for App {
 
         content = content
             .push(file_row)
+            .push(trace_row)
             .push(instruction_row);
 
         if self.debug_on {
@@ -1201,6 +1287,8 @@ enum Message {
     OccupancyChanged,
     DebugNodeSelected(usize),
     StepProgress,
+    TracePathChanged(String),
+    SaveTrace,
     Tick,
 }
 
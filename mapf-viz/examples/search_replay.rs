@@ -0,0 +1,384 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use iced::{
+    Application, Alignment, Element, Length, Command, Column, Row, Text, Container,
+    slider::{self, Slider},
+    text_input::{self, TextInput},
+    button::{self, Button},
+    executor, keyboard,
+};
+use iced_native;
+use mapf::{
+    trace::{RecordingTrace, TraceLogError},
+    motion::se2::graph_search::KeySE2,
+    occupancy::{Grid, SparseGrid, Cell, RosMap},
+};
+use mapf_viz::{
+    SparseGridOccupancyVisual, InfiniteGrid, SearchReplayVisual,
+    spatial_canvas::SpatialCanvas,
+};
+use mapf_viz::spatial_layers;
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+/// The key used by the searches of the grid_planner example
+type SearchKey = KeySE2<Cell, 100>;
+type ReplayVisual<Message> = SearchReplayVisual<Message, SearchKey>;
+
+spatial_layers!(ReplayLayers<Message>: InfiniteGrid, SparseGridOccupancyVisual, ReplayVisual);
+
+/// Place each search key at the center of its cell in the given grid
+fn position_of(grid: &SparseGrid) -> Box<dyn Fn(&SearchKey) -> Option<mapf::occupancy::Point>> {
+    let (cell_size, origin) = (grid.cell_size(), grid.origin());
+    Box::new(move |key: &SearchKey| {
+        Some(Cell::from(*key).to_center_point(cell_size) + origin.coords)
+    })
+}
+
+#[derive(Debug, Default)]
+struct Flags {
+    trace: Option<PathBuf>,
+    map: Option<PathBuf>,
+}
+
+struct App {
+    canvas: SpatialCanvas<Message, ReplayLayers>,
+    reset_view_button: button::State,
+    load_button: button::State,
+    play_button: button::State,
+    step_slider: slider::State,
+    trace_input: text_input::State,
+    trace_path: String,
+    playing: bool,
+    status: String,
+}
+
+impl App {
+    const ROBOT_RADIUS: f32 = 0.75;
+
+    fn replay(&self) -> &ReplayVisual<Message> {
+        &self.canvas.program.layers.2
+    }
+
+    fn replay_mut(&mut self) -> &mut ReplayVisual<Message> {
+        &mut self.canvas.program.layers.2
+    }
+
+    fn load_map(&mut self, path: &PathBuf) {
+        match RosMap::load(path) {
+            Ok(map) => {
+                let grid = map.to_sparse_grid(false);
+                let position_of = position_of(&grid);
+                let mut occupancy = SparseGridOccupancyVisual::new(
+                    grid, Self::ROBOT_RADIUS, None, None,
+                );
+                // The map is only shown for reference, so it cannot be edited
+                occupancy.cell_toggler = None;
+                occupancy.corner_select_toggler = None;
+                self.canvas.program.layers.1 = occupancy;
+                self.replay_mut().set_position_of(position_of);
+                self.canvas.cache.clear();
+            },
+            Err(err) => {
+                self.status = format!("Unable to load map [{}]: {err}", path.display());
+                println!("{}", self.status);
+            }
+        }
+    }
+
+    fn load_trace(&mut self, path: &PathBuf) {
+        let trace = File::open(path)
+            .map_err(TraceLogError::from)
+            .and_then(|file| RecordingTrace::<SearchKey, i64>::read_json_lines(BufReader::new(file)));
+
+        match trace {
+            Ok(trace) => {
+                let events = trace.into_events();
+                self.status = format!("Loaded {} events from [{}]", events.len(), path.display());
+                self.replay_mut().set_events(events);
+                self.playing = false;
+                self.canvas.fit_to_bounds();
+                self.canvas.cache.clear();
+            },
+            Err(err) => {
+                self.status = format!("Unable to load trace [{}]: {err}", path.display());
+                println!("{}", self.status);
+            }
+        }
+    }
+
+    fn set_step(&mut self, step: usize) {
+        self.replay_mut().set_step(step);
+        self.canvas.cache.clear();
+    }
+}
+
+impl Application for App {
+    type Message = Message;
+    type Executor = executor::Default;
+    type Flags = Flags;
+
+    fn new(flags: Self::Flags) -> (Self, Command<Self::Message>) {
+        let cell_size = 1.0_f32;
+        let grid = SparseGrid::new(cell_size as f64);
+        let position_of = position_of(&grid);
+
+        let mut occupancy = SparseGridOccupancyVisual::new(
+            grid, Self::ROBOT_RADIUS, None, None,
+        );
+        occupancy.cell_toggler = None;
+        occupancy.corner_select_toggler = None;
+
+        let mut canvas = SpatialCanvas::new(
+            ReplayLayers{
+                layers: (
+                    InfiniteGrid::new(cell_size),
+                    occupancy,
+                    ReplayVisual::new(position_of, Self::ROBOT_RADIUS/2.0),
+                )
+            }
+        );
+        canvas.zoom = 20.0;
+
+        let mut app = Self{
+            canvas,
+            reset_view_button: button::State::new(),
+            load_button: button::State::new(),
+            play_button: button::State::new(),
+            step_slider: slider::State::new(),
+            trace_input: text_input::State::default(),
+            trace_path: String::new(),
+            playing: false,
+            status: String::new(),
+        };
+
+        if let Some(map) = &flags.map {
+            app.load_map(map);
+        }
+
+        if let Some(trace) = &flags.trace {
+            app.trace_path = trace.display().to_string();
+            app.load_trace(trace);
+        }
+
+        (app, Command::none())
+    }
+
+    fn title(&self) -> String {
+        "Search Replay".to_owned()
+    }
+
+    fn update(
+        &mut self,
+        message: Self::Message
+    ) -> Command<Self::Message> {
+        match message {
+            Message::TracePathChanged(value) => {
+                self.trace_path = value;
+            },
+            Message::LoadTrace => {
+                let path = PathBuf::from(&self.trace_path);
+                self.load_trace(&path);
+            },
+            Message::ResetView => {
+                self.canvas.fit_to_bounds();
+            },
+            Message::TogglePlay => {
+                if !self.playing && self.replay().step() >= self.replay().last_step() {
+                    // Start over if the replay already reached the end
+                    self.set_step(0);
+                }
+                self.playing = !self.playing;
+            },
+            Message::StepSlide(value) => {
+                self.playing = false;
+                self.set_step(value as usize);
+            },
+            Message::EventOccurred(event) => {
+                if let iced_native::Event::Keyboard(keyboard::Event::KeyPressed{key_code, ..}) = event {
+                    match key_code {
+                        keyboard::KeyCode::Space => {
+                            return self.update(Message::TogglePlay);
+                        },
+                        keyboard::KeyCode::Right => {
+                            self.playing = false;
+                            let step = self.replay().step() + 1;
+                            self.set_step(step);
+                        },
+                        keyboard::KeyCode::Left => {
+                            self.playing = false;
+                            let step = self.replay().step().saturating_sub(1);
+                            self.set_step(step);
+                        },
+                        _ => {
+                            // Do nothing
+                        }
+                    }
+                }
+            },
+            Message::Tick => {
+                if self.playing {
+                    self.playing = self.replay_mut().advance();
+                    self.canvas.cache.clear();
+                }
+            }
+        }
+
+        Command::none()
+    }
+
+    fn subscription(&self) -> iced::Subscription<Message> {
+        iced_native::Subscription::batch([
+            iced_native::subscription::events().map(Message::EventOccurred),
+            iced::time::every(std::time::Duration::from_millis(50)).map(|_|{ Message::Tick }),
+        ])
+    }
+
+    fn view(&mut self) -> Element<Self::Message> {
+        let mut content = Column::new()
+            .spacing(20)
+            .align_items(Alignment::Start)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        let replay = &self.canvas.program.layers.2;
+        let step = replay.step();
+        let last_step = replay.last_step();
+        let current = match replay.current() {
+            Some((key, f)) => format!("Expanding {key:?} with f = {:.3}", f as f64 / 1e9),
+            None => String::new(),
+        };
+        let solution = match replay.solution() {
+            Some((_, f)) => format!("Solution found with cost {:.3}", f as f64 / 1e9),
+            None => String::new(),
+        };
+        let counts = format!(
+            "Open: {}    Closed: {}    Rejected: {}",
+            replay.open_count(),
+            replay.closed_count(),
+            replay.rejected_count(),
+        );
+
+        let file_row = Row::new()
+            .spacing(20)
+            .align_items(Alignment::Center)
+            .width(Length::Fill)
+            .push(
+                Button::new(
+                    &mut self.reset_view_button,
+                    iced::Text::new("Reset View")
+                ).on_press(Message::ResetView)
+            )
+            .push(
+                TextInput::new(
+                    &mut self.trace_input,
+                    "Trace file (JSON Lines)",
+                    &mut self.trace_path,
+                    Message::TracePathChanged
+                )
+                .on_submit(Message::LoadTrace)
+                .padding(10)
+                .width(Length::Fill)
+            )
+            .push(
+                Button::new(
+                    &mut self.load_button,
+                    iced::Text::new("Load Trace")
+                ).on_press(Message::LoadTrace)
+            )
+            .push(Text::new(&self.status));
+
+        let timeline_row = Row::new()
+            .spacing(20)
+            .align_items(Alignment::Center)
+            .width(Length::Fill)
+            .push(
+                Button::new(
+                    &mut self.play_button,
+                    iced::Text::new(if self.playing { "Pause" } else { "Play" })
+                ).on_press(Message::TogglePlay)
+            )
+            .push(
+                Slider::new(
+                    &mut self.step_slider,
+                    0..=(last_step as u32),
+                    step as u32,
+                    Message::StepSlide,
+                )
+            )
+            .push(Text::new(format!("Step {step} / {last_step}")));
+
+        let info_row = Row::<Message>::new()
+            .spacing(40)
+            .align_items(Alignment::Start)
+            .width(Length::Shrink)
+            .push(Text::new(counts))
+            .push(Text::new(current))
+            .push(Text::new(solution));
+
+        let instruction_row = Row::<Message>::new()
+            .spacing(40)
+            .align_items(Alignment::Start)
+            .width(Length::Shrink)
+            .push(Text::new("Space: Play/Pause"))
+            .push(Text::new("Left/Right: Step backward/forward"))
+            .push(Text::new("Middle click: Pan view"))
+            .push(Text::new("Scroll: Zoom"));
+
+        content = content
+            .push(file_row)
+            .push(timeline_row)
+            .push(info_row)
+            .push(instruction_row)
+            .push(self.canvas.view());
+
+        Container::new(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(5)
+            .center_x()
+            .center_y()
+            .into()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    TracePathChanged(String),
+    LoadTrace,
+    ResetView,
+    TogglePlay,
+    StepSlide(u32),
+    EventOccurred(iced_native::Event),
+    Tick,
+}
+
+/// Usage: search_replay [trace.jsonl] [map.yaml]
+///
+/// The trace should be recorded by a RecordingTrace from a search whose keys
+/// are KeySE2<Cell, 100>, like the searches of the grid_planner example,
+/// which can save its traces with its "Save Trace" button. The map is an
+/// optional ROS occupancy map to draw underneath the search.
+fn main() -> iced::Result {
+    let mut args = std::env::args().skip(1);
+    let flags = Flags{
+        trace: args.next().map(PathBuf::from),
+        map: args.next().map(PathBuf::from),
+    };
+
+    App::run(iced::Settings::with_flags(flags))
+}
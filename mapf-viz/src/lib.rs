@@ -27,6 +27,9 @@ pub use grid::InfiniteGrid;
 pub mod toggle;
 pub use toggle::{Toggle, Toggler};
 
+pub mod search_replay;
+pub use search_replay::SearchReplayVisual;
+
 #[cfg(test)]
 mod tests {
     #[test]
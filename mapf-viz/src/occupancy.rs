@@ -118,16 +118,16 @@ impl<Message, G: Grid> OccupancyVisual<Message, G> {
 
     fn toggle(&mut self, p: iced::Point) -> bool {
         if let Some(cell_toggler) = &self.cell_toggler {
-            let cell = Cell::from_point(
-                [p.x as f64, p.y as f64].into(),
-                self.occupancy.grid().cell_size(),
-            );
+            let cell = self
+                .occupancy
+                .grid()
+                .cell_of(&Point::new(p.x as f64, p.y as f64));
             match cell_toggler.state() {
                 Toggle::On => {
-                    return self.occupancy.change_cells(&[(cell, true)].into());
+                    return !self.occupancy.change_cells(&[(cell, true)].into()).is_empty();
                 }
                 Toggle::Off => {
-                    return self.occupancy.change_cells(&[(cell, false)].into());
+                    return !self.occupancy.change_cells(&[(cell, false)].into()).is_empty();
                 }
                 Toggle::NoChange => {
                     return false;
@@ -144,7 +144,7 @@ impl<Message, G: Grid> OccupancyVisual<Message, G> {
         let p = Point::new(p.x as f64, p.y as f64);
 
         for (cell, _) in self.occupancy.iter_points() {
-            let p_cell = cell.to_center_point(self.grid().cell_size());
+            let p_cell = self.grid().cell_center(cell);
             let dist = (p_cell - p).norm();
             if dist <= r {
                 if let Some((_, old_dist)) = closest {
@@ -214,10 +214,11 @@ impl<Message, G: Grid> SpatialCanvasProgram<Message> for OccupancyVisual<Message
     }
 
     fn draw_in_space(&self, frame: &mut Frame, _: Rectangle, _: Cursor) {
-        let cell_size = self.occupancy.grid().cell_size();
+        let grid = self.occupancy.grid();
+        let cell_size = grid.cell_size();
         let robot_radius = self.occupancy.agent_radius() as f32;
-        for cell in self.occupancy.grid().occupied_cells() {
-            let p = cell.to_bottom_left_point(cell_size);
+        for cell in grid.occupied_cells() {
+            let p = grid.cell_bottom_left(&cell);
             frame.fill_rectangle(
                 [p.x as f32, p.y as f32].into(),
                 iced::Size::new(cell_size as f32, cell_size as f32),
@@ -227,7 +228,7 @@ impl<Message, G: Grid> SpatialCanvasProgram<Message> for OccupancyVisual<Message
 
         if self.show_visibility_graph {
             for (cell, _) in self.occupancy.iter_points() {
-                let p = cell.to_center_point(cell_size);
+                let p = grid.cell_center(cell);
                 let color = self
                     .special_visibility_color
                     .get(cell)
@@ -240,8 +241,8 @@ impl<Message, G: Grid> SpatialCanvasProgram<Message> for OccupancyVisual<Message
             }
 
             for (cell_i, cell_j) in self.occupancy.iter_edges() {
-                let p_i = cell_i.to_center_point(cell_size);
-                let p_j = cell_j.to_center_point(cell_size);
+                let p_i = grid.cell_center(cell_i);
+                let p_j = grid.cell_center(cell_j);
                 frame.stroke(
                     &Path::line(
                         [p_i.x as f32, p_i.y as f32].into(),
@@ -334,7 +335,7 @@ impl<Message, G: Grid> SpatialCanvasProgram<Message> for OccupancyVisual<Message
         }
 
         for (cell, _) in self.occupancy.iter_points() {
-            let p = cell.to_center_point(self.occupancy.grid().cell_size());
+            let p = self.occupancy.grid().cell_center(cell);
             let r = self.occupancy.agent_radius() as f32 / 2_f32.sqrt();
             let delta = iced::Vector::new(r, -r);
             let p = iced::Point::new(p.x as f32, p.y as f32) + delta;
@@ -351,7 +352,7 @@ impl<Message, G: Grid> SpatialCanvasProgram<Message> for OccupancyVisual<Message
         let mut zone = InclusionZone::Empty;
         let r = self.occupancy.agent_radius() as f32;
         for (cell, _) in self.occupancy.iter_points() {
-            let p = cell.to_center_point(self.occupancy.grid().cell_size());
+            let p = self.occupancy.grid().cell_center(cell);
             let p: iced::Point = [p.x as f32, p.y as f32].into();
             let d = iced::Vector::new(r, r);
             zone.include(p + d);
@@ -0,0 +1,414 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+use super::spatial_canvas::{InclusionZone, SpatialCanvasProgram};
+use derivative::Derivative;
+use iced::{
+    canvas::{Cursor, Frame, Path, Stroke},
+    Rectangle,
+};
+use mapf::{
+    node::Key,
+    occupancy::Point,
+    trace::{TraceEvent, TraceEventKind},
+};
+use std::collections::HashMap;
+
+/// A layer that replays a search which was recorded by a
+/// [`mapf::trace::RecordingTrace`]. Nodes that have been expanded (the closed
+/// frontier) are filled in with a color that reflects their f-value, and nodes
+/// that are still waiting in the queue (the open frontier) are outlined.
+///
+/// The layer does not know where the keys of the search are located, so it
+/// needs a function that maps each key to a point in space. Keys that cannot
+/// be mapped will not be drawn.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SearchReplayVisual<Message, K: Key> {
+    #[derivative(Debug = "ignore")]
+    events: Vec<TraceEvent<K, i64>>,
+
+    #[derivative(Debug = "ignore")]
+    position_of: Box<dyn Fn(&K) -> Option<Point>>,
+
+    /// The step that is currently being shown
+    step: usize,
+    /// How many events have been replayed to reach the current step
+    replayed: usize,
+    /// The range of f-values that get expanded over the whole search
+    f_range: Option<(i64, i64)>,
+
+    #[derivative(Debug = "ignore")]
+    open: HashMap<K, ReplayNode<K>>,
+
+    #[derivative(Debug = "ignore")]
+    closed: HashMap<K, ReplayNode<K>>,
+
+    #[derivative(Debug = "ignore")]
+    rejected: Vec<K>,
+
+    #[derivative(Debug = "ignore")]
+    current: Option<ReplayNode<K>>,
+
+    #[derivative(Debug = "ignore")]
+    solution: Option<ReplayNode<K>>,
+
+    // NOTE: After changing one of the public fields below, you must clear the
+    // cache of the SpatialCanvas that this program belongs to before the change
+    // will be rendered.
+    pub node_radius: f32,
+    pub low_f_color: iced::Color,
+    pub high_f_color: iced::Color,
+    pub open_color: iced::Color,
+    pub rejected_color: iced::Color,
+    pub current_color: iced::Color,
+    pub solution_color: iced::Color,
+    pub tree_color: iced::Color,
+    pub show_tree: bool,
+
+    _msg: std::marker::PhantomData<Message>,
+}
+
+#[derive(Debug, Clone)]
+struct ReplayNode<K> {
+    key: K,
+    parent: Option<K>,
+    f: i64,
+}
+
+impl<K: Clone> ReplayNode<K> {
+    fn new(event: &TraceEvent<K, i64>) -> Option<Self> {
+        Some(Self {
+            key: event.key.clone()?,
+            parent: event.parent.clone(),
+            f: event.cost + event.heuristic,
+        })
+    }
+}
+
+impl<Message, K: Key> SearchReplayVisual<Message, K> {
+    pub fn new(position_of: Box<dyn Fn(&K) -> Option<Point>>, node_radius: f32) -> Self {
+        Self {
+            events: Vec::new(),
+            position_of,
+            step: 0,
+            replayed: 0,
+            f_range: None,
+            open: HashMap::new(),
+            closed: HashMap::new(),
+            rejected: Vec::new(),
+            current: None,
+            solution: None,
+            node_radius,
+            low_f_color: iced::Color::from_rgb8(0x1F, 0x77, 0xB4),
+            high_f_color: iced::Color::from_rgb8(0xD6, 0x27, 0x28),
+            open_color: iced::Color::from_rgb8(0x2C, 0xA0, 0x2C),
+            rejected_color: iced::Color::from_rgb(1.0, 0.0, 0.0),
+            current_color: iced::Color::from_rgb8(230, 166, 33),
+            solution_color: iced::Color::from_rgb(1.0, 0.1, 1.0),
+            tree_color: iced::Color::from_rgba(0.5, 0.5, 0.5, 0.5),
+            show_tree: true,
+            _msg: Default::default(),
+        }
+    }
+
+    /// Replace the events that are being replayed. The replay will be reset to
+    /// step 0.
+    pub fn set_events(&mut self, events: Vec<TraceEvent<K, i64>>) {
+        self.f_range = None;
+        for event in &events {
+            if event.kind == TraceEventKind::ExpandedFrom {
+                let f = event.cost + event.heuristic;
+                self.f_range = Some(match self.f_range {
+                    Some((low, high)) => (low.min(f), high.max(f)),
+                    None => (f, f),
+                });
+            }
+        }
+
+        self.events = events;
+        self.reset();
+    }
+
+    pub fn events(&self) -> &[TraceEvent<K, i64>] {
+        &self.events
+    }
+
+    /// Change how keys get mapped to points in space.
+    pub fn set_position_of(&mut self, position_of: Box<dyn Fn(&K) -> Option<Point>>) {
+        self.position_of = position_of;
+    }
+
+    /// The step that is currently being shown.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// The last step of the recorded search.
+    pub fn last_step(&self) -> usize {
+        self.events.last().map(|e| e.step).unwrap_or(0)
+    }
+
+    /// Jump to any step of the search. Moving forward only replays the events
+    /// in between, while moving backward replays the search from the start.
+    pub fn set_step(&mut self, step: usize) {
+        let step = step.min(self.last_step());
+        if step < self.step {
+            self.reset();
+        }
+
+        if step != self.step {
+            self.current = None;
+        }
+
+        self.step = step;
+        while let Some(event) = self.events.get(self.replayed) {
+            if event.step > step {
+                break;
+            }
+
+            let event = event.clone();
+            self.replay(&event);
+            self.replayed += 1;
+        }
+    }
+
+    /// Move forward by one step. Returns false if the search was already at
+    /// its last step.
+    pub fn advance(&mut self) -> bool {
+        if self.step >= self.last_step() {
+            return false;
+        }
+
+        self.set_step(self.step + 1);
+        return true;
+    }
+
+    /// Number of nodes in the open frontier at the current step
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Number of nodes in the closed frontier at the current step
+    pub fn closed_count(&self) -> usize {
+        self.closed.len()
+    }
+
+    /// Number of nodes that were rejected by a closed set or a constraint up
+    /// to the current step
+    pub fn rejected_count(&self) -> usize {
+        self.rejected.len()
+    }
+
+    /// The key and f-value of the node that was expanded in the current step
+    pub fn current(&self) -> Option<(&K, i64)> {
+        self.current.as_ref().map(|n| (&n.key, n.f))
+    }
+
+    /// The key and f-value of the node that satisfied the goal, if the replay
+    /// has reached it
+    pub fn solution(&self) -> Option<(&K, i64)> {
+        self.solution.as_ref().map(|n| (&n.key, n.f))
+    }
+
+    fn reset(&mut self) {
+        self.step = 0;
+        self.replayed = 0;
+        self.open.clear();
+        self.closed.clear();
+        self.rejected.clear();
+        self.current = None;
+        self.solution = None;
+        self.set_step(0);
+    }
+
+    fn replay(&mut self, event: &TraceEvent<K, i64>) {
+        let node = match ReplayNode::new(event) {
+            Some(node) => node,
+            None => return,
+        };
+
+        match event.kind {
+            TraceEventKind::ExpandedTo => {
+                if !self.closed.contains_key(&node.key) {
+                    let keep = self
+                        .open
+                        .get(&node.key)
+                        .filter(|existing| existing.f <= node.f)
+                        .is_some();
+
+                    if !keep {
+                        self.open.insert(node.key.clone(), node);
+                    }
+                }
+            }
+            TraceEventKind::ExpandedFrom => {
+                self.open.remove(&node.key);
+                self.closed.insert(node.key.clone(), node.clone());
+                self.current = Some(node);
+            }
+            TraceEventKind::SolutionFoundFrom => {
+                self.solution = Some(node);
+            }
+            TraceEventKind::ClosedRejected | TraceEventKind::ConstraintRejected => {
+                self.rejected.push(node.key);
+            }
+            TraceEventKind::Pruned => {
+                // A memory-bounded search forgot this node, so it is no longer
+                // waiting in the queue.
+                self.open.remove(&node.key);
+            }
+        }
+    }
+
+    fn f_color(&self, f: i64) -> iced::Color {
+        let s = match self.f_range {
+            Some((low, high)) if low < high => {
+                ((f - low) as f32 / (high - low) as f32).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        };
+
+        let (a, b) = (self.low_f_color, self.high_f_color);
+        iced::Color::from_rgba(
+            a.r + s * (b.r - a.r),
+            a.g + s * (b.g - a.g),
+            a.b + s * (b.b - a.b),
+            a.a + s * (b.a - a.a),
+        )
+    }
+
+    fn point_of(&self, key: &K) -> Option<iced::Point> {
+        (self.position_of)(key).map(|p| iced::Point::new(p.x as f32, p.y as f32))
+    }
+
+    fn draw_tree_edge(
+        &self,
+        frame: &mut Frame,
+        node: &ReplayNode<K>,
+        color: iced::Color,
+        width: f32,
+    ) {
+        if let Some(parent) = &node.parent {
+            if let (Some(p0), Some(p1)) = (self.point_of(parent), self.point_of(&node.key)) {
+                frame.stroke(
+                    &Path::line(p0, p1),
+                    Stroke {
+                        color,
+                        width,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl<Message: std::fmt::Debug, K: Key> SpatialCanvasProgram<Message>
+    for SearchReplayVisual<Message, K>
+{
+    fn draw_in_space(&self, frame: &mut Frame, _: Rectangle, _: Cursor) {
+        let r = self.node_radius;
+        let line_width = r / 4_f32;
+
+        if self.show_tree {
+            for node in self.closed.values().chain(self.open.values()) {
+                self.draw_tree_edge(frame, node, self.tree_color, line_width);
+            }
+        }
+
+        // Draw the expanded nodes with the highest f-values first so that the
+        // most promising nodes end up on top where several keys share a point.
+        let mut closed: Vec<&ReplayNode<K>> = self.closed.values().collect();
+        closed.sort_by(|a, b| b.f.cmp(&a.f));
+        for node in closed {
+            if let Some(p) = self.point_of(&node.key) {
+                frame.fill(&Path::circle(p, r), self.f_color(node.f));
+            }
+        }
+
+        for node in self.open.values() {
+            if let Some(p) = self.point_of(&node.key) {
+                frame.stroke(
+                    &Path::circle(p, r),
+                    Stroke {
+                        color: self.open_color,
+                        width: line_width,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        for key in &self.rejected {
+            if let Some(p) = self.point_of(key) {
+                let d = r / 2_f32;
+                for [dx, dy] in [[d, d], [d, -d]] {
+                    frame.stroke(
+                        &Path::line(
+                            iced::Point::new(p.x - dx, p.y - dy),
+                            iced::Point::new(p.x + dx, p.y + dy),
+                        ),
+                        Stroke {
+                            color: self.rejected_color,
+                            width: line_width / 2_f32,
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+
+        if let Some(current) = &self.current {
+            self.draw_tree_edge(frame, current, self.current_color, line_width);
+            if let Some(p) = self.point_of(&current.key) {
+                frame.stroke(
+                    &Path::circle(p, 1.5 * r),
+                    Stroke {
+                        color: self.current_color,
+                        width: line_width,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        if let Some(solution) = &self.solution {
+            // Trace the solution back through the closed set
+            let mut node = Some(solution);
+            while let Some(n) = node {
+                self.draw_tree_edge(frame, n, self.solution_color, 2_f32 * line_width);
+                node = n.parent.as_ref().and_then(|parent| self.closed.get(parent));
+            }
+        }
+    }
+
+    fn estimate_bounds(&self) -> InclusionZone {
+        let mut zone = InclusionZone::Empty;
+        for event in &self.events {
+            if let Some(p) = event.key.as_ref().and_then(|k| self.point_of(k)) {
+                for [dx, dy] in [[1.0, 1.0], [-1.0, -1.0]] {
+                    zone.include(iced::Point::new(
+                        p.x + dx * self.node_radius,
+                        p.y + dy * self.node_radius,
+                    ));
+                }
+            }
+        }
+
+        return zone;
+    }
+}
@@ -111,30 +111,56 @@ where
     where
         E: Targeted<G>,
     {
-        if let Some(top) = memory.queue.pop().map(|x| x.0 .0) {
-            if goal.is_satisfied(&top) {
-                tracker.solution_found_from(&top);
-                let solution = memory
-                    .expander
-                    .make_solution(&top)
-                    .map_err(algorithm::StepError::Solve)?;
-                return Ok(Status::Solved(solution));
-            }
+        tracker.step_begin();
+        let result = expand_top(memory, goal, tracker);
+        tracker.step_end();
+        return result;
+    }
+}
 
-            if let CloseResult::Closed = memory.closed_set.close(&top) {
-                tracker.expanded_from(&top);
-                for next in memory.expander.expand(&top, goal) {
-                    let next = next.map_err(algorithm::StepError::Expansion)?;
-                    if let ClosedStatus::Open = memory.closed_set.status(next.as_ref()) {
-                        tracker.expanded_to(&next);
-                        memory.queue.push(Reverse(NodeCmp(next)));
-                    }
+type StepResultOf<E, G> = Result<
+    Status<<E as Solvable>::Solution>,
+    algorithm::StepError<NoError, ExpansionErrorOf<E, G>, SolveErrorOf<E>>,
+>;
+
+fn expand_top<N, E, G, T>(
+    memory: &mut Memory<N, E>,
+    goal: &G,
+    tracker: &mut T,
+) -> StepResultOf<E, G>
+where
+    N: Informed,
+    E: Expander<Node = N> + Closable + Solvable + Targeted<G>,
+    G: Goal<N>,
+    T: Trace<N>,
+{
+    if let Some(top) = memory.queue.pop().map(|x| x.0 .0) {
+        if goal.is_satisfied(&top) {
+            tracker.solution_found_from(&top);
+            let solution = memory
+                .expander
+                .make_solution(&top)
+                .map_err(algorithm::StepError::Solve)?;
+            return Ok(Status::Solved(solution));
+        }
+
+        if let CloseResult::Closed = memory.closed_set.close(&top) {
+            tracker.expanded_from(&top);
+            for next in memory.expander.expand(&top, goal) {
+                let next = next.map_err(algorithm::StepError::Expansion)?;
+                if let ClosedStatus::Open = memory.closed_set.status(next.as_ref()) {
+                    tracker.expanded_to(&next);
+                    memory.queue.push(Reverse(NodeCmp(next)));
+                } else {
+                    tracker.closed_rejected(&next);
                 }
             }
-
-            return Ok(Status::Incomplete);
+        } else {
+            tracker.closed_rejected(&top);
         }
 
-        return Ok(Status::Impossible);
+        return Ok(Status::Incomplete);
     }
+
+    return Ok(Status::Impossible);
 }
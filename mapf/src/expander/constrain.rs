@@ -15,7 +15,11 @@
  *
 */
 
-use crate::{error::Error, expander::traits::*};
+use crate::{
+    error::Error,
+    expander::traits::*,
+    trace::{SharedTrace, Trace},
+};
 use std::{marker::PhantomData, sync::Arc};
 use thiserror::Error as ThisError;
 
@@ -35,9 +39,46 @@ pub trait ReversibleConstraint {
     fn reverse(&self) -> Result<Self::Reverse, Self::ReversalError>;
 }
 
+/// Reports a node that was rejected by a constraint.
+type RejectionTrace<N> = Arc<dyn Fn(&N) + Send + Sync>;
+
 pub struct Constrain<E: Expander, C> {
     base: E,
     constrain_with: C,
+    rejection_trace: Option<RejectionTrace<E::Node>>,
+}
+
+impl<E: Expander, C> Constrain<E, C> {
+    /// Report every node that the constraint rejects to a trace. Share the
+    /// same trace with the planner to see the rejections alongside the rest of
+    /// the search events.
+    pub fn with_trace<T>(mut self, trace: SharedTrace<T>) -> Self
+    where
+        T: Trace<E::Node> + Send + 'static,
+    {
+        self.rejection_trace = Some(Arc::new(move |node: &E::Node| {
+            trace.lock().constraint_rejected(node);
+        }));
+        self
+    }
+
+    fn traced<Err>(
+        &self,
+        node: Arc<E::Node>,
+        constrain: impl FnOnce(Arc<E::Node>) -> Result<Option<Arc<E::Node>>, Err>,
+    ) -> Result<Option<Arc<E::Node>>, Err> {
+        let trace = match &self.rejection_trace {
+            Some(trace) => trace,
+            None => return constrain(node),
+        };
+
+        let result = constrain(node.clone())?;
+        if result.is_none() {
+            trace(&node);
+        }
+
+        Ok(result)
+    }
 }
 
 #[derive(ThisError, Debug)]
@@ -69,9 +110,11 @@ impl<E: Targeted<G>, C: TargetedConstraint<E::Node, G>, G: Goal<E::Node>> Target
             .map(|r| r.map_err(ConstrainErr::Base))
             .map(move |r| {
                 r.and_then(|n| {
-                    self.constrain_with
-                        .constrain(n, goal)
-                        .map_err(ConstrainErr::Constraint)
+                    self.traced(n, |n| {
+                        self.constrain_with
+                            .constrain(n, goal)
+                            .map_err(ConstrainErr::Constraint)
+                    })
                 })
             })
             .filter_map(|r| r.transpose())
@@ -94,9 +137,11 @@ where
             .map(|r| r.map_err(ConstrainErr::Base))
             .map(move |r| {
                 r.and_then(|n| {
-                    self.constrain_with
-                        .constrain(n, goal)
-                        .map_err(ConstrainErr::Constraint)
+                    self.traced(n, |n| {
+                        self.constrain_with
+                            .constrain(n, goal)
+                            .map_err(ConstrainErr::Constraint)
+                    })
                 })
             })
             .filter_map(|r| r.transpose())
@@ -131,6 +176,9 @@ impl<E: Reversible, C: ReversibleConstraint> Reversible for Constrain<E, C> {
         Ok(Constrain {
             base,
             constrain_with,
+            // The reverse expander produces a different type of node, so the
+            // rejection trace cannot be carried over.
+            rejection_trace: None,
         })
     }
 }
@@ -290,6 +338,7 @@ impl<E: Expander> Constrainable for E {
         Constrain {
             base: self,
             constrain_with,
+            rejection_trace: None,
         }
     }
 }
@@ -41,9 +41,11 @@ pub use trace::Trace;
 pub mod tree;
 
 pub mod directed;
+pub mod multi_level;
 pub mod motion;
 
 pub mod a_star;
+pub mod sma_star;
 
 pub mod error;
 pub mod occupancy;
@@ -18,8 +18,11 @@
 use crate::{
     error::NoError,
     expander::{AimlessConstraint, TargetedConstraint},
-    motion::{r2, se2, Duration, Interpolation, Motion, TimePoint, Trajectory},
-    node::Agent,
+    motion::{
+        obstacle_index::CircleObstacleIndex, r2, se2, Duration, Interpolation, Motion, TimePoint,
+        Trajectory,
+    },
+    node::{Agent, Key},
 };
 use nalgebra::Vector2;
 use std::sync::Arc;
@@ -44,136 +47,290 @@ fn compute_p0_v(
     (p0.coords, dp / dt)
 }
 
-fn time_within_range(t: f64, t_range: &(TimePoint, TimePoint)) -> Option<TimePoint> {
+/// A span of time during which two agents are in collision with each other.
+/// Both ends of the span are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionInterval {
+    /// The first moment that the agents are in contact.
+    pub begin: TimePoint,
+    /// The last moment that the agents are in contact.
+    pub end: TimePoint,
+}
+
+fn interval_within_range(
+    t_lower: f64,
+    t_upper: f64,
+    t_range: &(TimePoint, TimePoint),
+) -> Option<CollisionInterval> {
     let dt = (t_range.1 - t_range.0).as_secs_f64();
-    if 0.0 <= t && t <= dt {
-        return Some(t_range.0 + Duration::from_secs_f64(t));
+    if t_upper < t_lower || t_upper < 0.0 || dt < t_lower {
+        return None;
     }
 
-    return None;
+    // We use the exact bounds of the time range whenever the interval is
+    // clamped so that intervals from adjacent segments can be merged without
+    // any floating point drift.
+    let begin = if t_lower <= 0.0 {
+        t_range.0
+    } else {
+        t_range.0 + Duration::from_secs_f64(t_lower)
+    };
+
+    let end = if dt <= t_upper {
+        t_range.1
+    } else {
+        t_range.0 + Duration::from_secs_f64(t_upper)
+    };
+
+    return Some(CollisionInterval { begin, end });
 }
 
-fn detect_proximity(
+fn compute_segment_proximity(
     dist_squared: f64,
-    mut iter_a: impl Iterator<Item = r2::timed_position::Waypoint>,
-    mut iter_b: impl Iterator<Item = r2::timed_position::Waypoint>,
-) -> Option<TimePoint> {
-    let mut wp0_a_opt = iter_a.next();
-    let mut wp1_a_opt = iter_a.next();
-    let mut wp0_b_opt = iter_b.next();
-    let mut wp1_b_opt = iter_b.next();
-
-    while let (Some(wp0_a), Some(wp1_a), Some(wp0_b), Some(wp1_b)) =
-        (wp0_a_opt, wp1_a_opt, wp0_b_opt, wp1_b_opt)
-    {
-        if wp1_a.time < wp0_b.time {
-            wp0_a_opt = Some(wp1_a);
-            wp1_a_opt = iter_a.next();
-            continue;
+    wp0_a: &r2::timed_position::Waypoint,
+    wp1_a: &r2::timed_position::Waypoint,
+    wp0_b: &r2::timed_position::Waypoint,
+    wp1_b: &r2::timed_position::Waypoint,
+) -> Option<CollisionInterval> {
+    let t_range = compute_t_range(wp0_a, wp1_a, wp0_b, wp1_b);
+    let (p0_a, v_a) = compute_p0_v(wp0_a, wp1_a, &t_range);
+    let (p0_b, v_b) = compute_p0_v(wp0_b, wp1_b, &t_range);
+    let dp0 = p0_b - p0_a;
+    let dv = v_b - v_a;
+
+    // The squared distance between the agents is a*t^2 + b*t + c, so the
+    // agents are in proximity wherever that quadratic is not positive.
+    let a = dv.dot(&dv);
+    let b = 2.0 * dv.dot(&dp0);
+    let c = dp0.dot(&dp0) - dist_squared;
+    let dt = (t_range.1 - t_range.0).as_secs_f64();
+
+    if a.abs() < 1e-8 {
+        // The two motions have almost identical velocities
+        if b.abs() < 1e-8 {
+            // There is no relative motion
+            if c <= 0.0 {
+                return interval_within_range(0.0, dt, &t_range);
+            }
+
+            return None;
         }
 
-        if wp1_b.time < wp0_a.time {
-            wp0_b_opt = Some(wp1_b);
-            wp1_b_opt = iter_b.next();
-            continue;
+        let t = -c / b;
+        if b > 0.0 {
+            return interval_within_range(0.0, t, &t_range);
+        } else {
+            return interval_within_range(t, dt, &t_range);
         }
+    }
 
-        let t_range = compute_t_range(&wp0_a, &wp1_a, &wp0_b, &wp1_b);
-        let (p0_a, v_a) = compute_p0_v(&wp0_a, &wp1_a, &t_range);
-        let (p0_b, v_b) = compute_p0_v(&wp0_b, &wp1_b, &t_range);
-        let dp0 = p0_b - p0_a;
-        let dv = v_b - v_a;
-
-        let a = dv.dot(&dv);
-        let b = 2.0 * dv.dot(&dp0);
-        let c = dp0.dot(&dp0) - dist_squared;
-
-        if a.abs() < 1e-8 {
-            // The two motions have almost identical velocities or nearly
-            // orthogonal velocities
-            if b.abs() < 1e-8 {
-                // There is no relative motion
-                if c <= 0.0 {
-                    return Some(wp0_a.time.max(wp0_b.time));
-                }
+    let radicand = b.powi(2) - 4.0 * a * c;
+    if radicand < 0.0 {
+        return None;
+    }
+
+    let sqrt_radicand = radicand.sqrt();
+    let t_m = (-b - sqrt_radicand) / (2.0 * a);
+    let t_p = (-b + sqrt_radicand) / (2.0 * a);
+    return interval_within_range(t_m, t_p, &t_range);
+}
+
+/// Iterates through every interval of time where two piecewise linear
+/// trajectories come within a certain distance of each other. Intervals that
+/// continue across the boundary between segments are merged together, so every
+/// item produced by this iterator is a distinct span of contact.
+struct ProximityIntervals<A, B>
+where
+    A: Iterator<Item = r2::timed_position::Waypoint>,
+    B: Iterator<Item = r2::timed_position::Waypoint>,
+{
+    dist_squared: f64,
+    iter_a: A,
+    iter_b: B,
+    wp0_a: Option<r2::timed_position::Waypoint>,
+    wp1_a: Option<r2::timed_position::Waypoint>,
+    wp0_b: Option<r2::timed_position::Waypoint>,
+    wp1_b: Option<r2::timed_position::Waypoint>,
+    pending: Option<CollisionInterval>,
+}
+
+impl<A, B> ProximityIntervals<A, B>
+where
+    A: Iterator<Item = r2::timed_position::Waypoint>,
+    B: Iterator<Item = r2::timed_position::Waypoint>,
+{
+    fn new(dist_squared: f64, mut iter_a: A, mut iter_b: B) -> Self {
+        let wp0_a = iter_a.next();
+        let wp1_a = iter_a.next();
+        let wp0_b = iter_b.next();
+        let wp1_b = iter_b.next();
+        Self {
+            dist_squared,
+            iter_a,
+            iter_b,
+            wp0_a,
+            wp1_a,
+            wp0_b,
+            wp1_b,
+            pending: None,
+        }
+    }
+
+    /// Check the next pair of overlapping segments. Returns None when there
+    /// are no segment pairs left to check.
+    fn next_segment(&mut self) -> Option<Option<CollisionInterval>> {
+        while let (Some(wp0_a), Some(wp1_a), Some(wp0_b), Some(wp1_b)) =
+            (self.wp0_a, self.wp1_a, self.wp0_b, self.wp1_b)
+        {
+            if wp1_a.time < wp0_b.time {
+                self.wp0_a = Some(wp1_a);
+                self.wp1_a = self.iter_a.next();
+                continue;
+            }
+
+            if wp1_b.time < wp0_a.time {
+                self.wp0_b = Some(wp1_b);
+                self.wp1_b = self.iter_b.next();
+                continue;
+            }
+
+            let interval =
+                compute_segment_proximity(self.dist_squared, &wp0_a, &wp1_a, &wp0_b, &wp1_b);
+
+            let mut advance_a = false;
+            let mut advance_b = false;
+            if wp1_a.time < wp1_b.time {
+                advance_a = true;
+            } else if wp1_b.time < wp1_a.time {
+                advance_b = true;
             } else {
-                let t = -c / b;
-                if let Some(t) = time_within_range(t, &t_range) {
-                    return Some(t);
-                }
+                (advance_a, advance_b) = (true, true);
             }
-        } else {
-            let radicand = b.powi(2) - 4.0 * a * c;
-            if radicand >= 0.0 {
-                let sqrt_radicand = radicand.sqrt();
-                let t_m = (-b - sqrt_radicand) / (2.0 * a);
-                if let Some(t_m) = time_within_range(t_m, &t_range) {
-                    return Some(t_m);
-                }
 
-                let t_p = (-b + sqrt_radicand) / (2.0 * a);
-                if let Some(t_p) = time_within_range(t_p, &t_range) {
-                    return Some(t_p);
-                }
+            if advance_a {
+                self.wp0_a = Some(wp1_a);
+                self.wp1_a = self.iter_a.next();
             }
-        }
 
-        let tp0 = wp0_a.time.min(wp0_b.time);
-        let t0 = tp0.as_secs_f64();
-        if a * t0.powi(2) + b * t0 + c <= 0.0 {
-            return Some(tp0);
-        }
+            if advance_b {
+                self.wp0_b = Some(wp1_b);
+                self.wp1_b = self.iter_b.next();
+            }
 
-        let mut advance_a = false;
-        let mut advance_b = false;
-        if wp1_a.time < wp1_b.time {
-            advance_a = true;
-        } else if wp1_b.time < wp1_a.time {
-            advance_b = true;
-        } else {
-            (advance_a, advance_b) = (true, true);
+            return Some(interval);
         }
 
-        if advance_a {
-            wp0_a_opt = Some(wp1_a);
-            wp1_a_opt = iter_a.next();
-        }
+        return None;
+    }
+}
+
+impl<A, B> Iterator for ProximityIntervals<A, B>
+where
+    A: Iterator<Item = r2::timed_position::Waypoint>,
+    B: Iterator<Item = r2::timed_position::Waypoint>,
+{
+    type Item = CollisionInterval;
 
-        if advance_b {
-            wp0_b_opt = Some(wp1_b);
-            wp1_b_opt = iter_b.next();
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_segment() {
+                Some(Some(interval)) => {
+                    if let Some(pending) = &mut self.pending {
+                        if interval.begin <= pending.end {
+                            // This contact is a continuation of the one that
+                            // was found in the previous segment.
+                            pending.end = pending.end.max(interval.end);
+                            continue;
+                        }
+
+                        let finished = *pending;
+                        self.pending = Some(interval);
+                        return Some(finished);
+                    }
+
+                    self.pending = Some(interval);
+                }
+                Some(None) => {
+                    if self.pending.is_some() {
+                        return self.pending.take();
+                    }
+                }
+                None => {
+                    return self.pending.take();
+                }
+            }
         }
     }
+}
 
-    return None;
+/// Get every interval of time during which two sequences of linearly
+/// interpolated waypoints come within a certain proximity of each other. The
+/// proximity is given as a squared distance.
+pub(crate) fn detect_proximity_intervals(
+    dist_squared: f64,
+    iter_a: impl Iterator<Item = r2::timed_position::Waypoint>,
+    iter_b: impl Iterator<Item = r2::timed_position::Waypoint>,
+) -> impl Iterator<Item = CollisionInterval> {
+    ProximityIntervals::new(dist_squared, iter_a, iter_b)
 }
 
-pub fn detect_collision_circles_r2(
+/// Get every interval of time during which two circular agents following
+/// linear R2 trajectories are in collision.
+pub fn detect_collision_intervals_circles_r2<'a>(
     radius_a: f64,
-    trajectory_a: &r2::LinearTrajectory,
+    trajectory_a: &'a r2::LinearTrajectory,
     radius_b: f64,
-    trajectory_b: &r2::LinearTrajectory,
-) -> Option<TimePoint> {
-    detect_proximity(
+    trajectory_b: &'a r2::LinearTrajectory,
+) -> impl Iterator<Item = CollisionInterval> + 'a {
+    detect_proximity_intervals(
         (radius_a + radius_b).powi(2),
         trajectory_a.iter().map(|w| w.0),
         trajectory_b.iter().map(|w| w.0),
     )
 }
 
-pub fn detect_collision_circles_se2(
+/// Get every interval of time during which two circular agents following
+/// linear SE2 trajectories are in collision. Since the agents are circular,
+/// their orientations have no effect on the result.
+pub fn detect_collision_intervals_circles_se2<'a>(
     radius_a: f64,
-    trajectory_a: &se2::LinearTrajectory,
+    trajectory_a: &'a se2::LinearTrajectory,
     radius_b: f64,
-    trajectory_b: &se2::LinearTrajectory,
-) -> Option<TimePoint> {
-    detect_proximity(
+    trajectory_b: &'a se2::LinearTrajectory,
+) -> impl Iterator<Item = CollisionInterval> + 'a {
+    detect_proximity_intervals(
         (radius_a + radius_b).powi(2),
         trajectory_a.iter().map(|w| w.0.into()),
         trajectory_b.iter().map(|w| w.0.into()),
     )
 }
 
+/// Get the first moment that two circular agents following linear R2
+/// trajectories come into contact, if they ever do.
+pub fn detect_collision_circles_r2(
+    radius_a: f64,
+    trajectory_a: &r2::LinearTrajectory,
+    radius_b: f64,
+    trajectory_b: &r2::LinearTrajectory,
+) -> Option<TimePoint> {
+    detect_collision_intervals_circles_r2(radius_a, trajectory_a, radius_b, trajectory_b)
+        .next()
+        .map(|interval| interval.begin)
+}
+
+/// Get the first moment that two circular agents following linear SE2
+/// trajectories come into contact, if they ever do.
+pub fn detect_collision_circles_se2(
+    radius_a: f64,
+    trajectory_a: &se2::LinearTrajectory,
+    radius_b: f64,
+    trajectory_b: &se2::LinearTrajectory,
+) -> Option<TimePoint> {
+    detect_collision_intervals_circles_se2(radius_a, trajectory_a, radius_b, trajectory_b)
+        .next()
+        .map(|interval| interval.begin)
+}
+
 pub struct CircleCollisionConstraint {
     pub obstacles: Vec<(f64, se2::LinearTrajectory)>,
     pub agent_radius: f64,
@@ -208,3 +365,107 @@ where
         AimlessConstraint::constrain(self, node)
     }
 }
+
+/// A variant of CircleCollisionConstraint that uses a broad-phase index to
+/// only check the obstacle segments that pass near each motion. This should be
+/// preferred when there are many obstacles.
+pub struct IndexedCircleCollisionConstraint<K: Key> {
+    pub obstacles: Arc<CircleObstacleIndex<K>>,
+    pub agent_radius: f64,
+}
+
+impl<K, N> AimlessConstraint<N> for IndexedCircleCollisionConstraint<K>
+where
+    K: Key,
+    N: Agent<se2::timed_position::Waypoint, Trajectory<se2::timed_position::Waypoint>>,
+{
+    type ConstraintError = NoError;
+    fn constrain(&self, node: Arc<N>) -> Result<Option<std::sync::Arc<N>>, Self::ConstraintError> {
+        if let Some(trajectory) = node.action() {
+            if self
+                .obstacles
+                .detect_collision_circles_se2(self.agent_radius, trajectory)
+                .is_some()
+            {
+                return Ok(None);
+            }
+        }
+
+        return Ok(Some(node));
+    }
+}
+
+impl<K, N, G> TargetedConstraint<N, G> for IndexedCircleCollisionConstraint<K>
+where
+    K: Key,
+    N: Agent<se2::timed_position::Waypoint, Trajectory<se2::timed_position::Waypoint>>,
+{
+    type ConstraintError = NoError;
+    fn constrain(&self, node: Arc<N>, _: &G) -> Result<Option<Arc<N>>, Self::ConstraintError> {
+        AimlessConstraint::constrain(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use r2::timed_position::Waypoint as WaypointR2;
+
+    fn make_trajectory(waypoints: &[(f64, f64, f64)]) -> r2::LinearTrajectory {
+        r2::LinearTrajectory::from_iter(
+            waypoints
+                .iter()
+                .map(|(t, x, y)| WaypointR2::new(TimePoint::from_secs_f64(*t), *x, *y)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_head_on_collision_interval() {
+        let trajectory_a = make_trajectory(&[(0.0, 0.0, 0.0), (10.0, 10.0, 0.0)]);
+        let trajectory_b = make_trajectory(&[(0.0, 10.0, 0.0), (10.0, 0.0, 0.0)]);
+
+        let intervals: Vec<_> =
+            detect_collision_intervals_circles_r2(0.5, &trajectory_a, 0.5, &trajectory_b)
+                .collect();
+        assert_eq!(intervals.len(), 1);
+        assert_relative_eq!(intervals[0].begin.as_secs_f64(), 4.5, epsilon = 1e-6);
+        assert_relative_eq!(intervals[0].end.as_secs_f64(), 5.5, epsilon = 1e-6);
+
+        let first_contact =
+            detect_collision_circles_r2(0.5, &trajectory_a, 0.5, &trajectory_b).unwrap();
+        assert_eq!(first_contact, intervals[0].begin);
+    }
+
+    #[test]
+    fn test_interval_merges_across_segments() {
+        let trajectory_a = make_trajectory(&[(0.0, 0.0, 0.0), (5.0, 0.0, 0.0), (10.0, 0.0, 0.0)]);
+        let trajectory_b = make_trajectory(&[(0.0, -5.0, 0.0), (10.0, 5.0, 0.0)]);
+
+        let intervals: Vec<_> =
+            detect_collision_intervals_circles_r2(0.5, &trajectory_a, 0.5, &trajectory_b)
+                .collect();
+        assert_eq!(intervals.len(), 1);
+        assert_relative_eq!(intervals[0].begin.as_secs_f64(), 4.0, epsilon = 1e-6);
+        assert_relative_eq!(intervals[0].end.as_secs_f64(), 6.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_multiple_collision_intervals() {
+        let trajectory_a = make_trajectory(&[(0.0, 0.0, 0.0), (12.0, 0.0, 0.0)]);
+        let trajectory_b = make_trajectory(&[(0.0, -3.0, 0.0), (6.0, 3.0, 0.0), (12.0, -3.0, 0.0)]);
+
+        let intervals: Vec<_> =
+            detect_collision_intervals_circles_r2(0.5, &trajectory_a, 0.5, &trajectory_b)
+                .collect();
+        assert_eq!(intervals.len(), 2);
+        assert_relative_eq!(intervals[0].begin.as_secs_f64(), 2.0, epsilon = 1e-6);
+        assert_relative_eq!(intervals[0].end.as_secs_f64(), 4.0, epsilon = 1e-6);
+        assert_relative_eq!(intervals[1].begin.as_secs_f64(), 8.0, epsilon = 1e-6);
+        assert_relative_eq!(intervals[1].end.as_secs_f64(), 10.0, epsilon = 1e-6);
+
+        let far_trajectory = make_trajectory(&[(0.0, 0.0, 5.0), (12.0, 0.0, 5.0)]);
+        assert!(detect_collision_circles_r2(0.5, &far_trajectory, 0.5, &trajectory_b).is_none());
+    }
+}
@@ -411,6 +411,14 @@ impl<P: Policy> Aimless for Expander<P> {
     }
 }
 
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "NodeCostOf<P>: serde::Serialize, P::Waypoint: serde::Serialize",
+        deserialize = "NodeCostOf<P>: serde::Deserialize<'de>, P::Waypoint: serde::Deserialize<'de>"
+    ))
+)]
 pub struct Solution<P: Policy> {
     cost: NodeCostOf<P>,
     motion: Option<Trajectory<P::Waypoint>>,
@@ -435,6 +443,10 @@ impl<P: Policy> Clone for Solution<P> {
 }
 
 impl<P: Policy> Solution<P> {
+    pub(crate) fn new(cost: NodeCostOf<P>, motion: Option<Trajectory<P::Waypoint>>) -> Self {
+        Self { cost, motion }
+    }
+
     pub fn cost(&self) -> &NodeCostOf<P> {
         &self.cost
     }
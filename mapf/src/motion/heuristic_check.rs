@@ -0,0 +1,378 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    graph::{Edge, Graph},
+    heuristic::{Heuristic, Uninformed},
+    motion::{
+        graph_search::{BuiltinNode, Expander, Policy},
+        reach::NoReach,
+        trajectory::CostCalculator,
+        Extrapolator, PositionedWaypoint, TimePoint, Waypoint,
+    },
+    node::{KeyedSet, PartialKeyedClosedSet, Weighted},
+    tree::Tree,
+};
+use num::Zero;
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+
+/// A pair of vertices where the heuristic estimated a higher cost than the
+/// true cost of the cheapest path between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overestimate<K, C> {
+    pub from: K,
+    pub to: K,
+    /// The estimate given by the heuristic. None means the heuristic claimed
+    /// that the goal is unreachable.
+    pub estimate: Option<C>,
+    /// The true cost of the cheapest path.
+    pub actual: C,
+}
+
+/// An edge where the heuristic violates consistency, i.e. for some goal the
+/// estimate from the start of the edge is greater than the cost of the edge
+/// plus the estimate from the end of the edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency<K, C> {
+    pub from: K,
+    pub via: K,
+    pub goal: K,
+    /// The estimate from the start of the edge to the goal. None means the
+    /// heuristic claimed that the goal is unreachable.
+    pub estimate_from: Option<C>,
+    /// The cost of moving along the edge.
+    pub edge_cost: C,
+    /// The estimate from the end of the edge to the goal.
+    pub estimate_via: C,
+}
+
+/// The results of [`check_heuristic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeuristicReport<K, C> {
+    pub overestimates: Vec<Overestimate<K, C>>,
+    pub inconsistencies: Vec<Inconsistency<K, C>>,
+    /// How many pairs of vertices were checked for admissibility. Pairs where
+    /// the goal cannot be reached from the start are not counted.
+    pub pairs_checked: usize,
+    /// How many edges were checked for consistency.
+    pub edges_checked: usize,
+}
+
+impl<K, C> Default for HeuristicReport<K, C> {
+    fn default() -> Self {
+        Self {
+            overestimates: Vec::new(),
+            inconsistencies: Vec::new(),
+            pairs_checked: 0,
+            edges_checked: 0,
+        }
+    }
+}
+
+impl<K, C> HeuristicReport<K, C> {
+    /// The heuristic never overestimated the cost between the checked pairs.
+    pub fn is_admissible(&self) -> bool {
+        self.overestimates.is_empty()
+    }
+
+    /// The heuristic satisfied the triangle inequality along every checked
+    /// edge.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum HeuristicCheckError<K, X, H> {
+    #[error("The vertex [{0:?}] is missing from the graph")]
+    MissingVertex(K),
+    #[error("An error occurred during extrapolation:\n{0}")]
+    Extrapolator(X),
+    #[error("An error occurred while computing the heuristic:\n{0}")]
+    Heuristic(H),
+}
+
+// We use this wrapper because otherwise derive(Debug) does not work correctly
+pub type HeuristicCheckErrorOf<G, W, X, H, C> = HeuristicCheckError<
+    <G as Graph>::Key,
+    <X as Extrapolator<W, <G as Graph>::Vertex>>::Error,
+    <H as Heuristic<<G as Graph>::Key, <G as Graph>::Key, C>>::Error,
+>;
+
+pub type HeuristicCheckResultOf<G, W, X, C, H> = Result<
+    HeuristicReport<<G as Graph>::Key, <C as CostCalculator<W>>::Cost>,
+    HeuristicCheckErrorOf<G, W, X, H, <C as CostCalculator<W>>::Cost>,
+>;
+
+/// Check whether a heuristic is admissible and consistent on a graph. This is
+/// meant to be used in tests for custom heuristics, since a heuristic that
+/// overestimates will silently cause A* to return suboptimal plans.
+///
+/// The true cost between every pair of the given `vertices` is found with an
+/// exhaustive Dijkstra search using [`Tree`], and compared against the
+/// heuristic estimate. Consistency is checked along every edge that leaves
+/// one of the given vertices, using each of the given vertices as the goal.
+///
+/// Each search starts from a waypoint at time zero at the position of its
+/// vertex, so this check assumes that edge costs do not depend on time. This
+/// requires an exhaustive search from each of the given vertices, so choose a
+/// modest subset of vertices when checking large graphs.
+pub fn check_heuristic<G, W, X, C, H>(
+    graph: Arc<G>,
+    extrapolator: Arc<X>,
+    cost_calculator: Arc<C>,
+    heuristic: &H,
+    vertices: impl IntoIterator<Item = G::Key>,
+) -> HeuristicCheckResultOf<G, W, X, C, H>
+where
+    G: Graph,
+    W: PositionedWaypoint<Position = G::Vertex>,
+    X: Extrapolator<W, G::Vertex>,
+    C: CostCalculator<W>,
+    H: Heuristic<G::Key, G::Key, C::Cost>,
+{
+    let vertices: Vec<G::Key> = vertices.into_iter().collect();
+    let expander = Arc::new(Expander::<CheckPolicy<G, W, X, C>> {
+        graph: graph.clone(),
+        extrapolator: extrapolator.clone(),
+        cost_calculator: cost_calculator.clone(),
+        heuristic: Arc::new(Uninformed),
+        reacher: Arc::new(NoReach),
+    });
+
+    let estimate = |from: &G::Key, to: &G::Key| {
+        heuristic
+            .estimate_cost(from, to)
+            .map_err(HeuristicCheckError::Heuristic)
+    };
+
+    let mut report = HeuristicReport::default();
+    for from in &vertices {
+        let position = graph
+            .vertex(from.clone())
+            .ok_or_else(|| HeuristicCheckError::MissingVertex(from.clone()))?;
+        let start = W::from_position(TimePoint::zero(), position);
+
+        let root = expander.start_from(start.clone(), Some(from.clone()), C::Cost::zero(), None);
+        let mut tree = Tree::new(root, expander.clone());
+        while !tree.is_exhausted() {
+            for node in tree.grow() {
+                node.map_err(HeuristicCheckError::Extrapolator)?;
+            }
+        }
+
+        for to in &vertices {
+            let actual = match tree.closed().get(to) {
+                Some(node) => node.cost(),
+                // The true cost is infinite, so any estimate is admissible
+                None => continue,
+            };
+            report.pairs_checked += 1;
+
+            let h = estimate(from, to)?;
+            if h.filter(|h| *h <= actual).is_none() {
+                report.overestimates.push(Overestimate {
+                    from: from.clone(),
+                    to: to.clone(),
+                    estimate: h,
+                    actual,
+                });
+            }
+        }
+
+        for edge in graph.edges_from_vertex(from.clone()) {
+            let via = edge.to_vertex().clone();
+            let target = match graph.vertex(via.clone()) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let edge_cost = extrapolator
+                .make_trajectory(start.clone(), &target)
+                .map_err(HeuristicCheckError::Extrapolator)?
+                .map(|t| cost_calculator.compute_cost(&t))
+                .unwrap_or(C::Cost::zero());
+
+            report.edges_checked += 1;
+            for goal in &vertices {
+                let estimate_via = match estimate(&via, goal)? {
+                    Some(h) => h,
+                    // An infinite estimate can never be exceeded
+                    None => continue,
+                };
+
+                let estimate_from = estimate(from, goal)?;
+                if estimate_from
+                    .filter(|h| *h <= edge_cost + estimate_via)
+                    .is_none()
+                {
+                    report.inconsistencies.push(Inconsistency {
+                        from: from.clone(),
+                        via: via.clone(),
+                        goal: goal.clone(),
+                        estimate_from,
+                        edge_cost,
+                        estimate_via,
+                    });
+                }
+            }
+        }
+    }
+
+    return Ok(report);
+}
+
+/// A policy for searching a graph without a heuristic, used to find the true
+/// costs between vertices.
+struct CheckPolicy<G, W, X, C> {
+    _ignore: std::marker::PhantomData<(G, W, X, C)>,
+}
+
+impl<G, W, X, C> Policy for CheckPolicy<G, W, X, C>
+where
+    G: Graph,
+    W: Waypoint,
+    X: Extrapolator<W, G::Vertex>,
+    C: CostCalculator<W>,
+{
+    type Waypoint = W;
+    type ClosedSet = PartialKeyedClosedSet<Self::Node>;
+    type Graph = G;
+    type StateKey = G::Key;
+    type Node = BuiltinNode<C::Cost, G::Key, W>;
+    type Extrapolator = X;
+    type Heuristic = Uninformed;
+    type Reach = NoReach;
+    type CostCalculator = C;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        directed::SimpleGraph,
+        error::NoError,
+        motion::{
+            r2::{direct_travel::DirectTravelHeuristic, timed_position::LineFollow, Point},
+            trajectory::DurationCostCalculator,
+        },
+    };
+
+    fn make_test_graph() -> SimpleGraph<Point> {
+        /*
+         * 0-----1-----2-----3
+         *           /       |
+         *         /         |
+         *       4-----5     6
+         */
+
+        let vertices = vec![
+            Point::new(0.0, 0.0),  // 0
+            Point::new(1.0, 0.0),  // 1
+            Point::new(2.0, 0.0),  // 2
+            Point::new(3.0, 0.0),  // 3
+            Point::new(1.0, -1.0), // 4
+            Point::new(2.0, -1.0), // 5
+            Point::new(3.0, -1.0), // 6
+        ];
+
+        let mut edges = Vec::<Vec<usize>>::new();
+        edges.resize(vertices.len(), Vec::new());
+        let mut add_bidir_edge = |v0: usize, v1: usize| {
+            edges[v0].push(v1);
+            edges[v1].push(v0);
+        };
+        add_bidir_edge(0, 1);
+        add_bidir_edge(1, 2);
+        add_bidir_edge(2, 3);
+        add_bidir_edge(2, 4);
+        add_bidir_edge(3, 6);
+        add_bidir_edge(4, 5);
+
+        return SimpleGraph::new(vertices, edges);
+    }
+
+    /// Pretends that every vertex is a fixed distance from every other vertex
+    struct Constant(i64);
+    impl Heuristic<usize, usize, i64> for Constant {
+        type Error = NoError;
+        fn estimate_cost(&self, from: &usize, to: &usize) -> Result<Option<i64>, NoError> {
+            if from == to {
+                return Ok(Some(0));
+            }
+
+            Ok(Some(self.0))
+        }
+    }
+
+    #[test]
+    fn test_check_direct_travel_heuristic() {
+        let graph = Arc::new(make_test_graph());
+        let extrapolator = Arc::new(LineFollow::new(1.0).unwrap());
+        let cost_calculator = Arc::new(DurationCostCalculator);
+        let heuristic = DirectTravelHeuristic {
+            graph: graph.clone(),
+            cost_calculator: cost_calculator.clone(),
+            extrapolator: *extrapolator,
+        };
+
+        let report =
+            check_heuristic(graph, extrapolator, cost_calculator, &heuristic, 0..7).unwrap();
+        assert_eq!(report.pairs_checked, 49);
+        assert_eq!(report.edges_checked, 12);
+        assert!(report.is_admissible());
+        assert!(report.is_consistent());
+    }
+
+    #[test]
+    fn test_check_bad_heuristic() {
+        let graph = Arc::new(make_test_graph());
+        let extrapolator = Arc::new(LineFollow::new(1.0).unwrap());
+        let cost_calculator = Arc::new(DurationCostCalculator);
+        let two_seconds = 2_000_000_000;
+        let report = check_heuristic(
+            graph,
+            extrapolator,
+            cost_calculator,
+            &Constant(two_seconds),
+            0..7,
+        )
+        .unwrap();
+
+        // Neighbors are only one or two seconds apart, so the constant
+        // estimate overestimates between adjacent vertices that are only one
+        // second apart.
+        assert!(!report.is_admissible());
+        assert!(report
+            .overestimates
+            .iter()
+            .any(|o| (o.from, o.to) == (0, 1) && o.estimate == Some(two_seconds)));
+        assert!(report
+            .overestimates
+            .iter()
+            .all(|o| o.estimate.unwrap() > o.actual));
+
+        // Moving from 0 to its neighbor 1 costs one second, but the estimate
+        // to reach 1 drops from two seconds to zero.
+        assert!(!report.is_consistent());
+        assert!(report
+            .inconsistencies
+            .iter()
+            .any(|i| (i.from, i.via, i.goal) == (0, 1, 1)));
+    }
+}
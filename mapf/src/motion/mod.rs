@@ -21,7 +21,7 @@ pub mod r2;
 pub mod se2;
 
 pub mod waypoint;
-pub use waypoint::Waypoint;
+pub use waypoint::{PositionedWaypoint, Waypoint};
 
 pub mod trajectory;
 pub use trajectory::Trajectory;
@@ -30,12 +30,14 @@ pub mod extrapolator;
 pub use extrapolator::Extrapolator;
 
 pub mod graph_search;
+pub mod heuristic_check;
 
 pub mod hold;
 pub mod movable;
 pub mod reach;
 
 pub mod collide;
+pub mod obstacle_index;
 
 pub use time_point::{Duration, TimePoint};
 
@@ -0,0 +1,346 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    motion::{
+        collide::{detect_proximity_intervals, CollisionInterval},
+        r2, se2, Duration, TimePoint,
+    },
+    node::Key,
+};
+use std::collections::{hash_map, HashMap, HashSet};
+use thiserror::Error as ThisError;
+
+/// Identifies a bucket of space-time by (time, x, y) indices.
+type BucketKey = (i64, i64, i64);
+
+/// Identifies a segment of an obstacle trajectory. The index refers to the
+/// waypoint that the segment is approaching.
+type SegmentId<K> = (K, usize);
+
+/// Returned by [`CircleObstacleIndex::new`] when a bucket size is invalid.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq)]
+pub enum ObstacleIndexError {
+    #[error("The cell size must be greater than zero, but it was {0}")]
+    CellSize(f64),
+    #[error("The time bucket must be greater than zero, but it was {0:?}")]
+    TimeBucket(Duration),
+}
+
+#[derive(Debug, Clone)]
+struct Obstacle {
+    radius: f64,
+    trajectory: se2::LinearTrajectory,
+    buckets: Vec<BucketKey>,
+}
+
+/// A broad-phase index over the trajectories of circular obstacles. Each
+/// segment of each trajectory is inserted into every bucket of a uniform
+/// space-time grid that its bounding box touches. Collision queries only need
+/// to perform exact checks against the segments that share a bucket with the
+/// query trajectory.
+///
+/// Obstacles are identified by a key so they can be replaced or removed as the
+/// plans of other agents change.
+#[derive(Debug, Clone)]
+pub struct CircleObstacleIndex<K: Key> {
+    cell_size: f64,
+    time_bucket: Duration,
+    obstacles: HashMap<K, Obstacle>,
+    buckets: HashMap<BucketKey, HashSet<SegmentId<K>>>,
+}
+
+impl<K: Key> CircleObstacleIndex<K> {
+    /// Create a new empty index. The cell size is the width and height of each
+    /// spatial bucket, and the time bucket is the duration of each temporal
+    /// bucket. Both must be greater than zero.
+    ///
+    /// Good values are on the order of the distance and time that an agent
+    /// typically covers in a single segment of its trajectory.
+    pub fn new(cell_size: f64, time_bucket: Duration) -> Result<Self, ObstacleIndexError> {
+        if cell_size <= 0.0 {
+            return Err(ObstacleIndexError::CellSize(cell_size));
+        }
+
+        if time_bucket.nanos <= 0 {
+            return Err(ObstacleIndexError::TimeBucket(time_bucket));
+        }
+
+        Ok(Self {
+            cell_size,
+            time_bucket,
+            obstacles: HashMap::new(),
+            buckets: HashMap::new(),
+        })
+    }
+
+    /// Get the width and height of each spatial bucket.
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// Get the duration of each temporal bucket.
+    pub fn time_bucket(&self) -> Duration {
+        self.time_bucket
+    }
+
+    /// Insert the trajectory of an obstacle into the index. If an obstacle
+    /// already existed with the same key, it will be replaced and its previous
+    /// radius and trajectory will be returned.
+    pub fn insert(
+        &mut self,
+        key: K,
+        radius: f64,
+        trajectory: se2::LinearTrajectory,
+    ) -> Option<(f64, se2::LinearTrajectory)> {
+        let previous = self.remove(&key);
+
+        let mut buckets = Vec::new();
+        for index in 1..trajectory.len() {
+            let wp0: r2::timed_position::Waypoint = trajectory[index - 1].0.into();
+            let wp1: r2::timed_position::Waypoint = trajectory[index].0.into();
+            let first = buckets.len();
+            self.for_each_bucket(&wp0, &wp1, radius, |bucket| buckets.push(bucket));
+            for bucket in &buckets[first..] {
+                self.buckets
+                    .entry(*bucket)
+                    .or_default()
+                    .insert((key.clone(), index));
+            }
+        }
+
+        self.obstacles.insert(
+            key,
+            Obstacle {
+                radius,
+                trajectory,
+                buckets,
+            },
+        );
+
+        return previous;
+    }
+
+    /// Remove an obstacle from the index. If an obstacle existed for the key
+    /// then its radius and trajectory will be returned.
+    pub fn remove(&mut self, key: &K) -> Option<(f64, se2::LinearTrajectory)> {
+        let obstacle = self.obstacles.remove(key)?;
+        for bucket in &obstacle.buckets {
+            if let hash_map::Entry::Occupied(mut entry) = self.buckets.entry(*bucket) {
+                entry.get_mut().retain(|(k, _)| k != key);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+
+        return Some((obstacle.radius, obstacle.trajectory));
+    }
+
+    /// Remove all obstacles from the index.
+    pub fn clear(&mut self) {
+        self.obstacles.clear();
+        self.buckets.clear();
+    }
+
+    /// Get the radius and trajectory of an obstacle.
+    pub fn get(&self, key: &K) -> Option<(f64, &se2::LinearTrajectory)> {
+        self.obstacles
+            .get(key)
+            .map(|obstacle| (obstacle.radius, &obstacle.trajectory))
+    }
+
+    /// Iterate through all the obstacles in the index.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, f64, &se2::LinearTrajectory)> {
+        self.obstacles
+            .iter()
+            .map(|(key, obstacle)| (key, obstacle.radius, &obstacle.trajectory))
+    }
+
+    /// Get the number of obstacles in the index.
+    pub fn len(&self) -> usize {
+        self.obstacles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.obstacles.is_empty()
+    }
+
+    /// Find the first moment that a circular agent following the given
+    /// trajectory would collide with any obstacle in the index. The key of the
+    /// obstacle will be returned along with the time of first contact.
+    pub fn detect_collision_circles_se2(
+        &self,
+        radius: f64,
+        trajectory: &se2::LinearTrajectory,
+    ) -> Option<(K, TimePoint)> {
+        self.detect_collision_intervals_circles_se2(radius, trajectory)
+            .into_iter()
+            .min_by_key(|(_, interval)| interval.begin)
+            .map(|(key, interval)| (key, interval.begin))
+    }
+
+    /// Find every interval of time where a circular agent following the given
+    /// trajectory would collide with an obstacle in the index. Each interval is
+    /// paired with the key of the obstacle that it collides with. Intervals that
+    /// belong to the same obstacle are not merged across obstacle segments.
+    pub fn detect_collision_intervals_circles_se2(
+        &self,
+        radius: f64,
+        trajectory: &se2::LinearTrajectory,
+    ) -> Vec<(K, CollisionInterval)> {
+        let mut collisions = Vec::new();
+        for (key, index) in self.nearby_segments(radius, trajectory) {
+            let obstacle = match self.obstacles.get(&key) {
+                Some(obstacle) => obstacle,
+                None => continue,
+            };
+
+            let dist_squared = (radius + obstacle.radius).powi(2);
+            let segment = [index - 1, index]
+                .into_iter()
+                .map(|i| r2::timed_position::Waypoint::from(obstacle.trajectory[i].0));
+            for interval in detect_proximity_intervals(
+                dist_squared,
+                trajectory.iter().map(|w| w.0.into()),
+                segment,
+            ) {
+                collisions.push((key.clone(), interval));
+            }
+        }
+
+        return collisions;
+    }
+
+    /// Get the set of obstacle segments that share a space-time bucket with a
+    /// circular agent following the given trajectory. These are the only
+    /// segments that the agent could possibly collide with.
+    fn nearby_segments(
+        &self,
+        radius: f64,
+        trajectory: &se2::LinearTrajectory,
+    ) -> HashSet<SegmentId<K>> {
+        let mut segments = HashSet::new();
+        for index in 1..trajectory.len() {
+            let wp0: r2::timed_position::Waypoint = trajectory[index - 1].0.into();
+            let wp1: r2::timed_position::Waypoint = trajectory[index].0.into();
+            self.for_each_bucket(&wp0, &wp1, radius, |bucket| {
+                if let Some(bucket_segments) = self.buckets.get(&bucket) {
+                    segments.extend(bucket_segments.iter().cloned());
+                }
+            });
+        }
+
+        return segments;
+    }
+
+    fn for_each_bucket(
+        &self,
+        wp0: &r2::timed_position::Waypoint,
+        wp1: &r2::timed_position::Waypoint,
+        inflation: f64,
+        mut f: impl FnMut(BucketKey),
+    ) {
+        let t_min = self.time_bucket_of(&wp0.time);
+        let t_max = self.time_bucket_of(&wp1.time);
+        let x_min = self.cell_of(wp0.position.x.min(wp1.position.x) - inflation);
+        let x_max = self.cell_of(wp0.position.x.max(wp1.position.x) + inflation);
+        let y_min = self.cell_of(wp0.position.y.min(wp1.position.y) - inflation);
+        let y_max = self.cell_of(wp0.position.y.max(wp1.position.y) + inflation);
+
+        for t in t_min..=t_max {
+            for x in x_min..=x_max {
+                for y in y_min..=y_max {
+                    f((t, x, y));
+                }
+            }
+        }
+    }
+
+    fn time_bucket_of(&self, time: &TimePoint) -> i64 {
+        time.nanos_since_zero.div_euclid(self.time_bucket.nanos)
+    }
+
+    fn cell_of(&self, value: f64) -> i64 {
+        (value / self.cell_size).floor() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::se2::timed_position::Waypoint as WaypointSE2;
+    use approx::assert_relative_eq;
+
+    fn make_trajectory(waypoints: &[(f64, f64, f64)]) -> se2::LinearTrajectory {
+        se2::LinearTrajectory::from_iter(
+            waypoints
+                .iter()
+                .map(|(t, x, y)| WaypointSE2::new(TimePoint::from_secs_f64(*t), *x, *y, 0.0)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_obstacle_index_queries() {
+        let mut index =
+            CircleObstacleIndex::<&'static str>::new(1.0, Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            CircleObstacleIndex::<&'static str>::new(0.0, Duration::from_secs(1)).unwrap_err(),
+            ObstacleIndexError::CellSize(0.0)
+        );
+        assert_eq!(
+            CircleObstacleIndex::<&'static str>::new(1.0, Duration::zero()).unwrap_err(),
+            ObstacleIndexError::TimeBucket(Duration::zero())
+        );
+
+        index.insert(
+            "crossing",
+            0.5,
+            make_trajectory(&[(0.0, 10.0, 0.0), (10.0, 0.0, 0.0)]),
+        );
+        index.insert(
+            "far_away",
+            0.5,
+            make_trajectory(&[(0.0, 100.0, 100.0), (10.0, 100.0, 110.0)]),
+        );
+        assert_eq!(index.len(), 2);
+
+        let agent = make_trajectory(&[(0.0, 0.0, 0.0), (10.0, 10.0, 0.0)]);
+        let (key, time) = index.detect_collision_circles_se2(0.5, &agent).unwrap();
+        assert_eq!(key, "crossing");
+        assert_relative_eq!(time.as_secs_f64(), 4.5, epsilon = 1e-6);
+
+        let nearby = index.nearby_segments(0.5, &agent);
+        assert!(nearby.iter().all(|(key, _)| *key == "crossing"));
+
+        // Replanning the crossing obstacle should move it out of the way
+        let previous = index.insert(
+            "crossing",
+            0.5,
+            make_trajectory(&[(0.0, 10.0, 5.0), (10.0, 0.0, 5.0)]),
+        );
+        assert!(previous.is_some());
+        assert_eq!(index.len(), 2);
+        assert!(index.detect_collision_circles_se2(0.5, &agent).is_none());
+
+        index.remove(&"crossing");
+        index.remove(&"far_away");
+        assert!(index.is_empty());
+        assert!(index.buckets.is_empty());
+    }
+}
@@ -24,11 +24,44 @@ use arrayvec::ArrayVec;
 use time_point::{Duration, TimePoint};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "WaypointData", into = "WaypointData")
+)]
 pub struct Waypoint {
     pub time: TimePoint,
     pub position: Position,
 }
 
+/// The serialized representation of a Waypoint. The time is given in
+/// nanoseconds so that it can be stored without any loss of precision.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WaypointData {
+    time: i64,
+    x: f64,
+    y: f64,
+}
+
+#[cfg(feature = "serde")]
+impl From<Waypoint> for WaypointData {
+    fn from(wp: Waypoint) -> Self {
+        WaypointData {
+            time: wp.time.nanos_since_zero,
+            x: wp.position.x,
+            y: wp.position.y,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<WaypointData> for Waypoint {
+    fn from(data: WaypointData) -> Self {
+        Waypoint::new(TimePoint::new(data.time), data.x, data.y)
+    }
+}
+
 impl timed::Timed for Waypoint {
     fn time(&self) -> &TimePoint {
         return &self.time;
@@ -53,6 +86,16 @@ impl motion::Waypoint for Waypoint {
     type Velocity = Velocity;
 }
 
+impl motion::PositionedWaypoint for Waypoint {
+    fn from_position(time: TimePoint, position: Position) -> Self {
+        return Waypoint { time, position };
+    }
+
+    fn position_matches(&self, other: &Self) -> bool {
+        return (self.position - other.position).norm() <= motion::DEFAULT_TRANSLATIONAL_THRESHOLD;
+    }
+}
+
 pub struct Motion {
     initial_wp: Waypoint,
     final_wp: Waypoint,
@@ -45,12 +45,15 @@ use std::sync::Arc;
 use thiserror::Error as ThisError;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OrientationGoal {
+    #[cfg_attr(feature = "serde", serde(with = "se2::serde_yaw"))]
     pub target: se2::Rotation,
     pub threshold: f64,
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GoalSE2<GraphKey: Key> {
     pub vertex: GraphKey,
     pub orientation: Option<OrientationGoal>,
@@ -85,6 +88,7 @@ impl<GraphKey: Key> PartialKeyed for GoalSE2<GraphKey> {
 impl<GraphKey: Key> Keyed for GoalSE2<GraphKey> {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KeySE2<GraphKey: Key, const RESOLUTION: u64> {
     /// The graph vertex that the agent is currently on
     vertex: GraphKey,
@@ -133,6 +137,12 @@ pub struct ReachForLinearSE2 {
     extrapolator: Arc<se2::timed_position::DifferentialDriveLineFollow>,
 }
 
+impl ReachForLinearSE2 {
+    pub fn new(extrapolator: Arc<se2::timed_position::DifferentialDriveLineFollow>) -> Self {
+        Self { extrapolator }
+    }
+}
+
 impl<GraphKey: Key, const RESOLUTION: u64>
     Reachable<Node<GraphKey, RESOLUTION>, GoalSE2<GraphKey>, se2::timed_position::Waypoint>
     for ReachForLinearSE2
@@ -167,8 +177,10 @@ impl<GraphKey: Key, const RESOLUTION: u64>
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StartSE2<GraphKey: Key> {
     pub vertex: GraphKey,
+    #[cfg_attr(feature = "serde", serde(with = "se2::serde_yaw"))]
     pub orientation: se2::Rotation,
 }
 
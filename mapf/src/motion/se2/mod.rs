@@ -26,6 +26,21 @@ pub struct Velocity {
     pub rotational: f64,
 }
 
+/// Serialize a rotation as its yaw angle in radians.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_yaw {
+    use super::Rotation;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(rotation: &Rotation, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f64(rotation.angle())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Rotation, D::Error> {
+        Ok(Rotation::new(f64::deserialize(d)?))
+    }
+}
+
 pub mod timed_position;
 pub type LinearTrajectory = super::Trajectory<timed_position::Waypoint>;
 
@@ -33,3 +48,9 @@ pub mod quickest_path;
 pub use quickest_path::QuickestPath;
 
 pub mod graph_search;
+
+pub mod multi_level;
+pub use multi_level::{
+    make_multi_level_expander, make_multi_level_expander_with_cost, LevelChange, MultiLevelExpander,
+    MultiLevelHeuristic,
+};
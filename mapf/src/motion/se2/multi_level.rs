@@ -0,0 +1,635 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    expander::{Chain, Chainable, Expander as ExpanderTrait, Goal, Targeted},
+    graph::Graph,
+    heuristic::Heuristic,
+    motion::{
+        graph_search::{Expander, StateKey},
+        movable::ArcMovable,
+        r2,
+        se2::{
+            self,
+            graph_search::{KeySE2, LinearSE2Policy, Node, ReachForLinearSE2},
+            timed_position::{DifferentialDriveLineFollow, Waypoint},
+            QuickestPath,
+        },
+        trajectory::{CostCalculator, DurationCostCalculator},
+        Extrapolator, TimePoint, Trajectory,
+    },
+    multi_level::{LevelKey, MultiLevelGraph, Transition, TransitionKind, WithinLevels},
+    node::{closed_set::PartialKeyedClosedSet, Agent, Keyed, PartialKeyed},
+    tree::garden::PoisonedMutex,
+};
+use num::Zero;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Expands nodes across the transitions of a [`MultiLevelGraph`]. Each
+/// transition produces a single motion which holds the agent in place while
+/// it waits for the lift or door, and then either rides the lift to the
+/// target vertex or drives through the door to it. The cost calculator sees
+/// the entire motion, so waiting and riding are both accounted for.
+///
+/// This is meant to be chained onto an expander that searches over
+/// [`WithinLevels`] of the same graph.
+pub struct LevelChange<G, C, H, const RESOLUTION: u64 = 100>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<Waypoint, Cost = i64>,
+{
+    graph: Arc<MultiLevelGraph<G>>,
+    extrapolator: Arc<DifferentialDriveLineFollow>,
+    cost_calculator: Arc<C>,
+    heuristic: Arc<H>,
+}
+
+impl<G, C, H, const RESOLUTION: u64> LevelChange<G, C, H, RESOLUTION>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<Waypoint, Cost = i64>,
+{
+    pub fn new(
+        graph: Arc<MultiLevelGraph<G>>,
+        extrapolator: Arc<DifferentialDriveLineFollow>,
+        cost_calculator: Arc<C>,
+        heuristic: Arc<H>,
+    ) -> Self {
+        Self {
+            graph,
+            extrapolator,
+            cost_calculator,
+            heuristic,
+        }
+    }
+
+    /// Make the motion for moving through a transition, starting from the
+    /// given waypoint. If the transition takes no time at all then this will
+    /// return None.
+    pub fn transition_motion(
+        &self,
+        from: &Waypoint,
+        to: &se2::Point,
+        kind: &TransitionKind,
+    ) -> Option<se2::LinearTrajectory> {
+        let mut waypoints = vec![*from];
+        let mut last = *from;
+        let wait = kind.wait();
+        if wait.nanos > 0 {
+            last.time += wait;
+            waypoints.push(last);
+        }
+
+        match kind {
+            TransitionKind::Lift { ride, .. } => {
+                last.time += *ride;
+                last.position.translation.vector = to.coords;
+                waypoints.push(last);
+            }
+            TransitionKind::Door { .. } => match self.extrapolator.extrapolate(&last, to) {
+                Ok(drive) => waypoints.extend(drive),
+                Err(err) => match err {},
+            },
+        }
+
+        Trajectory::from_iter(waypoints).ok()
+    }
+}
+
+impl<G, C, H, const RESOLUTION: u64> ExpanderTrait for LevelChange<G, C, H, RESOLUTION>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<Waypoint, Cost = i64>,
+{
+    type Node = Node<LevelKey<G::Key>, RESOLUTION>;
+}
+
+impl<G, C, H, Gl, const RESOLUTION: u64> Targeted<Gl> for LevelChange<G, C, H, RESOLUTION>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<Waypoint, Cost = i64>,
+    H: Heuristic<KeySE2<LevelKey<G::Key>, RESOLUTION>, Gl, i64>,
+    Gl: Goal<Node<LevelKey<G::Key>, RESOLUTION>>,
+{
+    type TargetedError = H::Error;
+    type TargetedExpansion<'a> = impl Iterator<Item=Result<Arc<Self::Node>, H::Error>> + 'a where G: 'a, C: 'a, H: 'a, Gl: 'a;
+
+    fn expand<'a>(
+        &'a self,
+        parent: &'a Arc<Self::Node>,
+        goal: &'a Gl,
+    ) -> Self::TargetedExpansion<'a> {
+        parent
+            .partial_key()
+            .into_iter()
+            .flat_map(move |parent_key| self.graph.transitions_from(&parent_key.vertex()))
+            .filter_map(move |transition| {
+                self.graph
+                    .vertex(transition.to.clone())
+                    .map(|target| (transition, target))
+            })
+            .map(
+                move |(transition, target)| -> Result<Option<Arc<Self::Node>>, H::Error> {
+                    let trajectory =
+                        self.transition_motion(parent.state(), &target, &transition.kind);
+                    let state = trajectory
+                        .as_ref()
+                        .map(|t| t.finish())
+                        .unwrap_or(parent.state());
+                    let to_key =
+                        KeySE2::<LevelKey<G::Key>, RESOLUTION>::from_state(&transition.to, state);
+                    let h = self.heuristic.estimate_cost(&to_key, goal)?;
+
+                    Ok(h.map(|h| {
+                        let cost_from_parent = trajectory
+                            .as_ref()
+                            .map(|t| self.cost_calculator.compute_cost(t))
+                            .unwrap_or(0);
+                        parent
+                            .clone()
+                            .moved_with(Some(to_key), cost_from_parent, h, trajectory)
+                    }))
+                },
+            )
+            .filter_map(|r| r.transpose())
+    }
+}
+
+type CostR2<C> = <C as CostCalculator<r2::timed_position::Waypoint>>::Cost;
+type WithinLevelsHeuristic<G, C> = QuickestPath<WithinLevels<G>, C>;
+type WithinLevelsErrorOf<G, C> = <WithinLevelsHeuristic<G, C> as Heuristic<
+    LevelKey<<G as Graph>::Key>,
+    LevelKey<<G as Graph>::Key>,
+    CostR2<C>,
+>>::Error;
+type ExitCosts<G, C> = Arc<HashMap<LevelKey<<G as Graph>::Key>, CostR2<C>>>;
+type ExitCostCache<G, C> = Mutex<HashMap<LevelKey<<G as Graph>::Key>, ExitCosts<G, C>>>;
+
+/// Estimates the cost of reaching a goal in a [`MultiLevelGraph`]. Travel
+/// within each level is estimated by a [`QuickestPath`] over [`WithinLevels`],
+/// and each transition is estimated by the cost of an r2 motion that waits at
+/// the entrance of the transition and then moves to its exit in the time that
+/// the transition requires. A lift is never estimated as a drive between its
+/// endpoints, so the heuristic stays admissible even when the two ends of a
+/// lift are at different positions in the plane.
+pub struct MultiLevelHeuristic<G, C>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    graph: Arc<MultiLevelGraph<G>>,
+    within: WithinLevelsHeuristic<G, C>,
+    cost_calculator: Arc<C>,
+    extrapolator: Arc<r2::timed_position::LineFollow>,
+    /// For each goal that has been requested, the estimated cost of reaching
+    /// it from the exit of each transition.
+    from_exits: ExitCostCache<G, C>,
+}
+
+impl<G, C> MultiLevelHeuristic<G, C>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+    WithinLevelsHeuristic<G, C>: Heuristic<LevelKey<G::Key>, LevelKey<G::Key>, C::Cost>,
+    WithinLevelsErrorOf<G, C>: From<PoisonedMutex>,
+{
+    pub fn new(
+        graph: Arc<MultiLevelGraph<G>>,
+        cost_calculator: Arc<C>,
+        extrapolator: Arc<r2::timed_position::LineFollow>,
+    ) -> Self {
+        Self {
+            within: QuickestPath::new(
+                Arc::new(WithinLevels::new(graph.clone())),
+                cost_calculator.clone(),
+                extrapolator.clone(),
+            ),
+            graph,
+            cost_calculator,
+            extrapolator,
+            from_exits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Discard the costs that have been cached for each goal, as well as the
+    /// paths cached by the estimates within each level. A heuristic that is
+    /// asked about many different goals keeps one set of costs for each of
+    /// them until this is called.
+    pub fn clear_cache(&self) -> Result<(), PoisonedMutex> {
+        self.from_exits.lock().map_err(|_| PoisonedMutex)?.clear();
+        self.within.clear_cache()
+    }
+
+    /// A lower bound on the cost of moving through a transition. The agent
+    /// waits at the entrance, and then either rides the lift to the exit or
+    /// drives straight to the exit without needing to turn.
+    pub fn transition_cost(&self, transition: &Transition<G::Key>) -> C::Cost {
+        let (p0, p1) = match (
+            self.graph.vertex(transition.from.clone()),
+            self.graph.vertex(transition.to.clone()),
+        ) {
+            (Some(p0), Some(p1)) => (p0, p1),
+            _ => return C::Cost::zero(),
+        };
+
+        let mut last = r2::timed_position::Waypoint::new(TimePoint::zero(), p0.x, p0.y);
+        let mut waypoints = vec![last];
+        let wait = transition.kind.wait();
+        if wait.nanos > 0 {
+            last.time += wait;
+            waypoints.push(last);
+        }
+
+        match transition.kind {
+            TransitionKind::Lift { ride, .. } => {
+                last.time += ride;
+                last.position = p1;
+                waypoints.push(last);
+            }
+            TransitionKind::Door { .. } => match self.extrapolator.extrapolate(&last, &p1) {
+                Ok(drive) => waypoints.extend(drive),
+                Err(err) => match err {},
+            },
+        }
+
+        Trajectory::from_iter(waypoints)
+            .map(|t| self.cost_calculator.compute_cost(&t))
+            .unwrap_or(C::Cost::zero())
+    }
+
+    /// Get the estimated cost of reaching the goal from the exit of each
+    /// transition, calculating it if it has not been requested before.
+    fn costs_from_exits(
+        &self,
+        goal: &LevelKey<G::Key>,
+    ) -> Result<ExitCosts<G, C>, WithinLevelsErrorOf<G, C>> {
+        if let Some(costs) = self.from_exits.lock().map_err(|_| PoisonedMutex)?.get(goal) {
+            return Ok(costs.clone());
+        }
+
+        let transitions: Vec<_> = self
+            .graph
+            .transitions()
+            .map(|t| (t, self.transition_cost(t)))
+            .collect();
+
+        let mut costs = HashMap::new();
+        for (transition, _) in &transitions {
+            if let Some(cost) = self.within.estimate_cost(&transition.to, goal)? {
+                costs.insert(transition.to.clone(), cost);
+            }
+        }
+
+        // Each round allows one more transition to be used along the way, and
+        // a shortest route never needs to use the same transition twice.
+        for _ in 0..transitions.len() {
+            let mut improved = false;
+            for (exit, _) in &transitions {
+                let exit = &exit.to;
+                for (next, transition_cost) in &transitions {
+                    let remaining = match costs.get(&next.to) {
+                        Some(remaining) => *remaining,
+                        None => continue,
+                    };
+
+                    let to_entrance = match self.within.estimate_cost(exit, &next.from)? {
+                        Some(cost) => cost,
+                        None => continue,
+                    };
+
+                    let cost = to_entrance + *transition_cost + remaining;
+                    if costs.get(exit).map(|c| cost < *c).unwrap_or(true) {
+                        costs.insert(exit.clone(), cost);
+                        improved = true;
+                    }
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        let costs = Arc::new(costs);
+        self.from_exits
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .insert(goal.clone(), costs.clone());
+        return Ok(costs);
+    }
+}
+
+impl<G, C, S, Gl> Heuristic<S, Gl, C::Cost> for MultiLevelHeuristic<G, C>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+    S: StateKey<LevelKey<G::Key>, Waypoint>,
+    Gl: Keyed<Key = LevelKey<G::Key>>,
+    WithinLevelsHeuristic<G, C>: Heuristic<LevelKey<G::Key>, LevelKey<G::Key>, C::Cost>,
+    WithinLevelsErrorOf<G, C>: From<PoisonedMutex>,
+{
+    type Error = WithinLevelsErrorOf<G, C>;
+
+    fn estimate_cost(&self, from_state: &S, to_goal: &Gl) -> Result<Option<C::Cost>, Self::Error> {
+        let from = from_state.graph_key();
+        let goal = to_goal.key();
+        let costs = self.costs_from_exits(goal)?;
+
+        let mut best = self.within.estimate_cost(&from, goal)?;
+        for transition in self.graph.transitions() {
+            let remaining = match costs.get(&transition.to) {
+                Some(remaining) => *remaining,
+                None => continue,
+            };
+
+            let to_entrance = match self.within.estimate_cost(&from, &transition.from)? {
+                Some(cost) => cost,
+                None => continue,
+            };
+
+            let cost = to_entrance + self.transition_cost(transition) + remaining;
+            best = Some(best.map(|b| b.min(cost)).unwrap_or(cost));
+        }
+
+        return Ok(best);
+    }
+}
+
+/// Plans through a [`MultiLevelGraph`], driving along the edges of each level
+/// and moving between levels through lifts and doors.
+pub type MultiLevelExpander<G, C = DurationCostCalculator, const RESOLUTION: u64 = 100> = Chain<
+    Expander<
+        LinearSE2Policy<
+            WithinLevels<G>,
+            PartialKeyedClosedSet<Node<LevelKey<<G as Graph>::Key>, RESOLUTION>>,
+            C,
+            MultiLevelHeuristic<G, C>,
+            RESOLUTION,
+        >,
+    >,
+    LevelChange<G, C, MultiLevelHeuristic<G, C>, RESOLUTION>,
+>;
+
+/// Make a [`MultiLevelExpander`] whose costs are the durations of the motions.
+pub fn make_multi_level_expander<G: Graph<Vertex = se2::Point>>(
+    graph: Arc<MultiLevelGraph<G>>,
+    extrapolator: Arc<DifferentialDriveLineFollow>,
+) -> MultiLevelExpander<G>
+where
+    WithinLevelsHeuristic<G, DurationCostCalculator>:
+        Heuristic<LevelKey<G::Key>, LevelKey<G::Key>, i64>,
+    WithinLevelsErrorOf<G, DurationCostCalculator>: From<PoisonedMutex>,
+{
+    make_multi_level_expander_with_cost(graph, extrapolator, Arc::new(DurationCostCalculator))
+}
+
+/// Make a [`MultiLevelExpander`] that uses a custom cost calculator. The cost
+/// of each r2 motion must never exceed the cost of the se2 motion that
+/// follows the same path, otherwise the heuristic will not be admissible.
+pub fn make_multi_level_expander_with_cost<G, C>(
+    graph: Arc<MultiLevelGraph<G>>,
+    extrapolator: Arc<DifferentialDriveLineFollow>,
+    cost_calculator: Arc<C>,
+) -> MultiLevelExpander<G, C>
+where
+    G: Graph<Vertex = se2::Point>,
+    C: CostCalculator<Waypoint, Cost = i64>
+        + CostCalculator<r2::timed_position::Waypoint, Cost = i64>,
+    WithinLevelsHeuristic<G, C>: Heuristic<LevelKey<G::Key>, LevelKey<G::Key>, i64>,
+    WithinLevelsErrorOf<G, C>: From<PoisonedMutex>,
+{
+    let heuristic = Arc::new(MultiLevelHeuristic::new(
+        graph.clone(),
+        cost_calculator.clone(),
+        Arc::new(extrapolator.as_ref().into()),
+    ));
+    let reacher = Arc::new(ReachForLinearSE2::new(extrapolator.clone()));
+
+    Expander {
+        graph: Arc::new(WithinLevels::new(graph.clone())),
+        extrapolator: extrapolator.clone(),
+        cost_calculator: cost_calculator.clone(),
+        heuristic: heuristic.clone(),
+        reacher,
+    }
+    .chain(LevelChange::new(
+        graph,
+        extrapolator,
+        cost_calculator,
+        heuristic,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star,
+        algorithm::Status,
+        directed::SimpleGraph,
+        motion::{
+            se2::graph_search::{GoalSE2, StartSE2},
+            Duration,
+        },
+        multi_level::Transition,
+        planner::make_planner,
+    };
+    use approx::assert_relative_eq;
+    use se2::Point;
+
+    fn make_test_graph() -> MultiLevelGraph<SimpleGraph<Point>> {
+        /*
+         * Level 0:  0-----1 (lift)
+         *
+         * Level 1:        0 (lift)
+         *                 |
+         *                 |
+         *                 1
+         *                 : (door)
+         *                 2
+         */
+        let ground = SimpleGraph::from_iters(
+            [Point::new(0.0, 0.0), Point::new(5.0, 0.0)],
+            [(0, 1), (1, 0)],
+        );
+        let upper = SimpleGraph::from_iters(
+            [
+                Point::new(5.0, 0.0),
+                Point::new(5.0, 5.0),
+                Point::new(5.0, 7.0),
+            ],
+            [(0, 1), (1, 0)],
+        );
+
+        let mut graph = MultiLevelGraph::new(vec![Arc::new(ground), Arc::new(upper)]);
+        let lift = TransitionKind::Lift {
+            wait: Duration::from_secs(10),
+            ride: Duration::from_secs(20),
+        };
+        let door = TransitionKind::Door {
+            open: Duration::from_secs(3),
+        };
+        for (from, to, kind) in [
+            (LevelKey::new(0, 1), LevelKey::new(1, 0), lift),
+            (LevelKey::new(1, 0), LevelKey::new(0, 1), lift),
+            (LevelKey::new(1, 1), LevelKey::new(1, 2), door),
+            (LevelKey::new(1, 2), LevelKey::new(1, 1), door),
+        ] {
+            graph.add_transition(Transition { from, to, kind }).unwrap();
+        }
+
+        return graph;
+    }
+
+    #[test]
+    fn test_multi_level_expander() {
+        let expander = make_multi_level_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(DifferentialDriveLineFollow::new(1.0, std::f64::consts::PI).unwrap()),
+        );
+
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner
+            .plan(
+                &StartSE2 {
+                    vertex: LevelKey::new(0, 0),
+                    orientation: se2::Rotation::new(0.0),
+                },
+                GoalSE2 {
+                    vertex: LevelKey::new(1, 2),
+                    orientation: None,
+                },
+            )
+            .unwrap();
+
+        match progress.solve().unwrap() {
+            Status::Solved(solution) => {
+                // Drive 5s to the lift, wait 10s, ride 20s, turn 0.5s, drive
+                // 5s to the door, wait 3s for it to open, then drive 2s.
+                let motion = solution.motion().as_ref().unwrap();
+                assert_relative_eq!(motion.duration().as_secs_f64(), 45.5, max_relative = 1e-6);
+                assert_relative_eq!(*solution.cost() as f64, 45.5e9, max_relative = 1e-6);
+
+                let p = motion.finish().position.translation.vector;
+                assert_relative_eq!(p.x, 5.0, max_relative = 1e-6);
+                assert_relative_eq!(p.y, 7.0, max_relative = 1e-6);
+            }
+            Status::Impossible => {
+                panic!("The search found the problem to be impossible");
+            }
+            Status::Incomplete => {
+                panic!("The search stopped before finding a solution");
+            }
+        }
+    }
+
+    #[test]
+    fn test_lift_between_different_positions() {
+        /*
+         * Level 0:  0-----1 (lift)
+         *
+         * Level 1:        0 (lift, 95m away from the ground level lift)
+         *                 |
+         *                 1
+         */
+        let ground = SimpleGraph::from_iters(
+            [Point::new(0.0, 0.0), Point::new(5.0, 0.0)],
+            [(0, 1), (1, 0)],
+        );
+        let upper = SimpleGraph::from_iters(
+            [Point::new(100.0, 0.0), Point::new(100.0, 5.0)],
+            [(0, 1), (1, 0)],
+        );
+        let mut graph = MultiLevelGraph::new(vec![Arc::new(ground), Arc::new(upper)]);
+        graph
+            .add_transition(Transition {
+                from: LevelKey::new(0, 1),
+                to: LevelKey::new(1, 0),
+                kind: TransitionKind::Lift {
+                    wait: Duration::from_secs(10),
+                    ride: Duration::from_secs(20),
+                },
+            })
+            .unwrap();
+        let graph = Arc::new(graph);
+
+        // Driving 95m between the ends of the lift would take far longer than
+        // riding it, so the heuristic must only count the wait and the ride.
+        let heuristic = MultiLevelHeuristic::new(
+            graph.clone(),
+            Arc::new(DurationCostCalculator),
+            Arc::new(r2::timed_position::LineFollow::new(1.0).unwrap()),
+        );
+        let lift = graph.transitions().next().unwrap();
+        assert_eq!(heuristic.transition_cost(lift), 30_000_000_000);
+        let h = heuristic
+            .estimate_cost(&LevelKey::new(0, 0), &LevelKey::new(1, 1))
+            .unwrap()
+            .unwrap();
+        assert_relative_eq!(h as f64, 40e9, max_relative = 1e-6);
+        assert!(heuristic
+            .estimate_cost(&LevelKey::new(1, 1), &LevelKey::new(0, 0))
+            .unwrap()
+            .is_none());
+        assert_eq!(heuristic.from_exits.lock().unwrap().len(), 2);
+        heuristic.clear_cache().unwrap();
+        assert!(heuristic.from_exits.lock().unwrap().is_empty());
+        assert_eq!(
+            heuristic
+                .estimate_cost(&LevelKey::new(0, 0), &LevelKey::new(1, 1))
+                .unwrap(),
+            Some(h)
+        );
+
+        let expander = make_multi_level_expander_with_cost(
+            graph,
+            Arc::new(DifferentialDriveLineFollow::new(1.0, std::f64::consts::PI).unwrap()),
+            Arc::new(DurationCostCalculator),
+        );
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner
+            .plan(
+                &StartSE2 {
+                    vertex: LevelKey::new(0, 0),
+                    orientation: se2::Rotation::new(0.0),
+                },
+                GoalSE2 {
+                    vertex: LevelKey::new(1, 1),
+                    orientation: None,
+                },
+            )
+            .unwrap();
+
+        match progress.solve().unwrap() {
+            Status::Solved(solution) => {
+                // Drive 5s to the lift, wait 10s, ride 20s, turn 0.5s, and
+                // drive 5s to the goal.
+                assert_relative_eq!(*solution.cost() as f64, 40.5e9, max_relative = 1e-6);
+                assert!(h <= *solution.cost());
+            }
+            Status::Impossible => {
+                panic!("The search found the problem to be impossible");
+            }
+            Status::Incomplete => {
+                panic!("The search stopped before finding a solution");
+            }
+        }
+    }
+}
@@ -21,10 +21,22 @@ use crate::{
     heuristic::{Heuristic, Uninformed},
     motion::{graph_search::StateKey, r2, reach::NoReach, se2, trajectory::CostCalculator},
     node::{Keyed, KeyedSet, PartialKeyed, Weighted},
-    tree::{garden, Garden},
+    tree::{
+        garden::{self, CacheLimits, CacheStats, PoisonedMutex, SavedSolutions},
+        Garden,
+    },
 };
 use std::sync::Arc;
+#[cfg(feature = "cache_files")]
+use std::{fs::File, path::Path};
+#[cfg(feature = "cache_files")]
+use thiserror::Error as ThisError;
 
+/// A heuristic that finds the exact cost of the quickest path to the goal on
+/// the graph while ignoring orientation. The search effort is cached, so use
+/// [`CacheLimits`] to bound the memory usage of a long-lived heuristic, and
+/// [`QuickestPath::invalidate_region`] to discard cached results when part of
+/// the graph changes.
 pub struct QuickestPath<G, C>
 where
     G: Graph<Vertex = r2::Position>,
@@ -42,20 +54,131 @@ where
         graph: Arc<G>,
         cost_calculator: Arc<C>,
         extrapolator: Arc<r2::timed_position::LineFollow>,
+    ) -> Self {
+        Self::with_limits(graph, cost_calculator, extrapolator, CacheLimits::default())
+    }
+
+    pub fn with_limits(
+        graph: Arc<G>,
+        cost_calculator: Arc<C>,
+        extrapolator: Arc<r2::timed_position::LineFollow>,
+        limits: CacheLimits,
     ) -> Self {
         Self {
-            garden: Garden::new(Arc::new(r2::graph_search::TimeInvariantExpander {
-                graph,
-                extrapolator,
-                cost_calculator,
-                heuristic: Arc::new(Uninformed),
-                reacher: Arc::new(NoReach),
-            })),
+            garden: Garden::with_limits(
+                Arc::new(r2::graph_search::TimeInvariantExpander {
+                    graph,
+                    extrapolator,
+                    cost_calculator,
+                    heuristic: Arc::new(Uninformed),
+                    reacher: Arc::new(NoReach),
+                }),
+                limits,
+            ),
         }
     }
+
+    pub fn limits(&self) -> Result<CacheLimits, PoisonedMutex> {
+        self.garden.limits()
+    }
+
+    pub fn set_limits(&self, limits: CacheLimits) -> Result<(), PoisonedMutex> {
+        self.garden.set_limits(limits)
+    }
+
+    pub fn cache_stats(&self) -> Result<CacheStats, PoisonedMutex> {
+        self.garden.cache_stats()
+    }
+
+    /// Discard everything that has been cached.
+    pub fn clear_cache(&self) -> Result<(), PoisonedMutex> {
+        self.garden.clear()
+    }
+}
+
+impl<G, C> QuickestPath<G, C>
+where
+    G: Graph<Vertex = r2::Position>,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+    NodeOf<UninformedExpanderR2<G, C>>: PartialKeyed<Key = G::Key>,
+{
+    /// Discard cached results that might be affected by a change to the
+    /// vertices that satisfy the `affected` predicate or to the edges that
+    /// touch them. See [`Garden::invalidate`] for details.
+    pub fn invalidate(&self, affected: impl Fn(&G::Key) -> bool) -> Result<(), PoisonedMutex> {
+        self.garden.invalidate(affected)
+    }
+
+    /// Discard cached results that might be affected by a change to the graph
+    /// within a region of the plane. Vertices that no longer exist in the graph
+    /// are treated as being inside the region.
+    pub fn invalidate_region(
+        &self,
+        in_region: impl Fn(&r2::Position) -> bool,
+    ) -> Result<(), PoisonedMutex> {
+        let graph = &self.garden.expander().graph;
+        self.garden.invalidate(|key| {
+            graph
+                .vertex(key.clone())
+                .map(|p| in_region(&p))
+                .unwrap_or(true)
+        })
+    }
+
+    /// Take a snapshot of the solutions that are currently cached.
+    pub fn save_solutions(&self) -> Result<SavedSolutionsR2<G, C>, PoisonedMutex>
+    where
+        SolutionOf<UninformedExpanderR2<G, C>>: Clone,
+    {
+        self.garden.save_solutions()
+    }
+
+    /// Load solutions that were saved from a heuristic for the same graph.
+    pub fn load_solutions(&self, saved: SavedSolutionsR2<G, C>) -> Result<(), PoisonedMutex> {
+        self.garden.load_solutions(saved)
+    }
+}
+
+#[cfg(feature = "cache_files")]
+#[derive(ThisError, Debug)]
+pub enum CacheFileError {
+    #[error("An error occurred while reading or writing a cache file:\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error("An error occurred while parsing a cache file:\n{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("a mutex was poisoned")]
+    PoisonedMutex(#[from] PoisonedMutex),
+}
+
+#[cfg(feature = "cache_files")]
+impl<G, C> QuickestPath<G, C>
+where
+    G: Graph<Vertex = r2::Position>,
+    G::Key: serde::Serialize + serde::de::DeserializeOwned,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+    NodeOf<UninformedExpanderR2<G, C>>: PartialKeyed<Key = G::Key>,
+    SolutionOf<UninformedExpanderR2<G, C>>: Clone + serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Save the cached solutions to a YAML file so they can be loaded after a
+    /// restart.
+    pub fn save_solutions_to_file(&self, path: impl AsRef<Path>) -> Result<(), CacheFileError> {
+        let saved = self.save_solutions()?;
+        std::fs::write(path, serde_yaml::to_string(&saved)?)?;
+        Ok(())
+    }
+
+    /// Load solutions from a YAML file that was written by
+    /// [`QuickestPath::save_solutions_to_file`] for the same graph.
+    pub fn load_solutions_from_file(&self, path: impl AsRef<Path>) -> Result<(), CacheFileError> {
+        let saved = serde_yaml::from_reader(File::open(path)?)?;
+        self.load_solutions(saved)?;
+        Ok(())
+    }
 }
 
 type UninformedExpanderR2<G, C> = r2::graph_search::TimeInvariantExpander<G, C, Uninformed>;
+type SavedSolutionsR2<G, C> =
+    SavedSolutions<<G as Graph>::Key, SolutionOf<UninformedExpanderR2<G, C>>>;
 
 impl<G, C, S, Goal> Heuristic<S, Goal, C::Cost> for QuickestPath<G, C>
 where
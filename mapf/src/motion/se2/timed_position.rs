@@ -24,11 +24,47 @@ use arrayvec::ArrayVec;
 use time_point::TimePoint;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "WaypointData", into = "WaypointData")
+)]
 pub struct Waypoint {
     pub time: TimePoint,
     pub position: Position,
 }
 
+/// The serialized representation of a Waypoint. The time is given in
+/// nanoseconds so that it can be stored without any loss of precision, and the
+/// orientation is given as a yaw angle in radians.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WaypointData {
+    time: i64,
+    x: f64,
+    y: f64,
+    yaw: f64,
+}
+
+#[cfg(feature = "serde")]
+impl From<Waypoint> for WaypointData {
+    fn from(wp: Waypoint) -> Self {
+        WaypointData {
+            time: wp.time.nanos_since_zero,
+            x: wp.position.translation.vector.x,
+            y: wp.position.translation.vector.y,
+            yaw: wp.position.rotation.angle(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<WaypointData> for Waypoint {
+    fn from(data: WaypointData) -> Self {
+        Waypoint::new(TimePoint::new(data.time), data.x, data.y, data.yaw)
+    }
+}
+
 impl timed::Timed for Waypoint {
     fn time(&self) -> &TimePoint {
         return &self.time;
@@ -53,6 +89,19 @@ impl motion::Waypoint for Waypoint {
     type Velocity = Velocity;
 }
 
+impl motion::PositionedWaypoint for Waypoint {
+    fn from_position(time: TimePoint, position: Position) -> Self {
+        return Waypoint { time, position };
+    }
+
+    fn position_matches(&self, other: &Self) -> bool {
+        let delta_p = other.position.translation.vector - self.position.translation.vector;
+        let delta_yaw = (self.position.rotation.inverse() * other.position.rotation).angle();
+        return delta_p.norm() <= motion::DEFAULT_TRANSLATIONAL_THRESHOLD
+            && delta_yaw.abs() <= motion::DEFAULT_ROTATIONAL_THRESHOLD;
+    }
+}
+
 impl From<Waypoint> for r2::timed_position::Waypoint {
     fn from(se2_wp: Waypoint) -> Self {
         r2::timed_position::Waypoint {
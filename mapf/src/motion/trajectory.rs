@@ -17,12 +17,31 @@
 
 use super::{
     timed::{TimeCmp, Timed},
-    waypoint, Duration, InterpError, Motion, TimePoint, Waypoint,
+    Duration, InterpError, Motion, PositionedWaypoint, TimePoint, Waypoint,
 };
+use arrayvec::ArrayVec;
 use cached::{Cached, UnboundCache};
-use sorted_vec::{FindOrInsert, SortedSet};
 use std::cell::RefCell;
 use std::rc::Rc;
+use thiserror::Error as ThisError;
+
+/// Returned by [`Trajectory::scale_time`] when the trajectory cannot be scaled.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq)]
+pub enum ScaleTimeError {
+    #[error("The scaling factor must be positive and finite, but it was {0}")]
+    Factor(f64),
+    #[error("Scaling by {0} would put two waypoints on the same nanosecond")]
+    Collapsed(f64),
+}
+
+/// Returned by [`Trajectory::resample`] when it cannot sample the trajectory.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleError {
+    #[error("The resampling step must be greater than zero, but it was {0:?}")]
+    NonPositiveStep(Duration),
+    #[error("Failed to interpolate the trajectory: {0:?}")]
+    Interpolation(InterpError),
+}
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Find {
@@ -61,9 +80,155 @@ pub enum MutateError {
     InvalidTimeChange,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConcatenateError {
+    /// The trajectory being appended does not begin at the time that the
+    /// original trajectory finishes.
+    TimeMismatch,
+
+    /// The trajectory being appended does not begin at the position where the
+    /// original trajectory finishes.
+    PositionMismatch,
+}
+
+/// The number of waypoints that a trajectory can hold without allocating any
+/// heap memory. This covers the motion of a typical search node: the waypoint
+/// of its parent plus the (at most) three waypoints that an extrapolator
+/// produces for a single motion.
+pub const INLINE_WAYPOINTS: usize = 4;
+
+/// Find the index of an element within a sorted slice, or the index where it
+/// belongs if it is not there.
+fn find_sorted<T: Ord>(slice: &[T], element: &T) -> Result<usize, usize> {
+    // Waypoints are usually added in order, so check the back first.
+    match slice.last() {
+        None => Err(0),
+        Some(last) if *last < *element => Err(slice.len()),
+        _ => slice.binary_search(element),
+    }
+}
+
+/// Sorted storage for the waypoints of a trajectory. Short trajectories are
+/// kept inline and only move to the heap once they grow past
+/// [`INLINE_WAYPOINTS`].
+#[derive(Clone)]
+enum Waypoints<W: Waypoint> {
+    Inline(ArrayVec<TimeCmp<W>, INLINE_WAYPOINTS>),
+    Heap(Vec<TimeCmp<W>>),
+}
+
+impl<W: Waypoint> Waypoints<W> {
+    fn new() -> Self {
+        Self::Inline(ArrayVec::new())
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        if capacity <= INLINE_WAYPOINTS {
+            Self::new()
+        } else {
+            Self::Heap(Vec::with_capacity(capacity))
+        }
+    }
+
+    /// Insert an element into its sorted position, or get the index of an
+    /// equal element that is already there.
+    fn find_or_push(&mut self, element: TimeCmp<W>) -> Result<usize, usize> {
+        match find_sorted(self, &element) {
+            Ok(index) => Err(index),
+            Err(index) => {
+                self.insert_at(index, element);
+                Ok(index)
+            }
+        }
+    }
+
+    /// Insert an element into its sorted position. If an equal element is
+    /// already there then it gets replaced.
+    fn push(&mut self, element: TimeCmp<W>) -> usize {
+        match find_sorted(self, &element) {
+            Ok(index) => {
+                self.as_mut_slice()[index] = element;
+                index
+            }
+            Err(index) => {
+                self.insert_at(index, element);
+                index
+            }
+        }
+    }
+
+    fn insert_at(&mut self, index: usize, element: TimeCmp<W>) {
+        self.reserve(1);
+        match self {
+            Self::Inline(inline) => inline.insert(index, element),
+            Self::Heap(heap) => heap.insert(index, element),
+        }
+    }
+
+    fn remove_index(&mut self, index: usize) -> TimeCmp<W> {
+        match self {
+            Self::Inline(inline) => inline.remove(index),
+            Self::Heap(heap) => heap.remove(index),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Inline(inline) => {
+                let required = inline.len() + additional;
+                if required > INLINE_WAYPOINTS {
+                    let mut heap = Vec::with_capacity(required);
+                    heap.extend(inline.drain(..));
+                    *self = Self::Heap(heap);
+                }
+            }
+            Self::Heap(heap) => heap.reserve(additional),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Self::Inline(_) => INLINE_WAYPOINTS,
+            Self::Heap(heap) => heap.capacity(),
+        }
+    }
+
+    fn is_inline(&self) -> bool {
+        matches!(self, Self::Inline(_))
+    }
+
+    /// Get mutable access to the waypoints. The caller must make sure that
+    /// the order of the waypoints does not change.
+    fn as_mut_slice(&mut self) -> &mut [TimeCmp<W>] {
+        match self {
+            Self::Inline(inline) => inline.as_mut_slice(),
+            Self::Heap(heap) => heap.as_mut_slice(),
+        }
+    }
+}
+
+impl<W: Waypoint> std::ops::Deref for Waypoints<W> {
+    type Target = [TimeCmp<W>];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Inline(inline) => inline.as_slice(),
+            Self::Heap(heap) => heap.as_slice(),
+        }
+    }
+}
+
+impl<W: Waypoint> PartialEq for Waypoints<W> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<W: Waypoint> Eq for Waypoints<W> {}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Trajectory<W: Waypoint> {
-    waypoints: SortedSet<TimeCmp<W>>,
+    waypoints: Waypoints<W>,
 }
 
 impl<'a, W: Waypoint> Trajectory<W> {
@@ -75,7 +240,7 @@ impl<'a, W: Waypoint> Trajectory<W> {
         }
 
         let mut result = Self {
-            waypoints: SortedSet::new(),
+            waypoints: Waypoints::new(),
         };
 
         result.waypoints.push(TimeCmp(start));
@@ -100,8 +265,9 @@ impl<'a, W: Waypoint> Trajectory<W> {
     /// Trajectory with them. If the final number of elements that would be in
     /// the trajectory is less than 2, then this function returns an Err.
     pub fn from_iter<I: std::iter::IntoIterator<Item = W>>(iter: I) -> Result<Self, ()> {
+        let iter = iter.into_iter();
         let mut result = Self {
-            waypoints: SortedSet::new(),
+            waypoints: Waypoints::with_capacity(iter.size_hint().0),
         };
         for element in iter {
             result.waypoints.push(TimeCmp(element));
@@ -118,17 +284,14 @@ impl<'a, W: Waypoint> Trajectory<W> {
     /// already exists with the same time value as the waypoint, then this
     /// returns an error with the index of the existing waypoint.
     pub fn insert(&mut self, waypoint: W) -> Result<usize, usize> {
-        match self.waypoints.find_or_push(TimeCmp(waypoint)) {
-            FindOrInsert::Found(index) => Result::Err(index),
-            FindOrInsert::Inserted(index) => Result::Ok(index),
-        }
+        return self.waypoints.find_or_push(TimeCmp(waypoint));
     }
 
     /// Inserts the waypoint into this trajectory, or if the trajectory already
     /// has a waypoint at an equivalent time, then the value of this waypoint
     /// gets assigned to the old one.
     pub fn insert_or_assign(&mut self, waypoint: W) -> usize {
-        return self.waypoints.insert(TimeCmp(waypoint));
+        return self.waypoints.push(TimeCmp(waypoint));
     }
 
     /// Attempt to remove the waypoint at the specified index. If the erasure
@@ -168,15 +331,49 @@ impl<'a, W: Waypoint> Trajectory<W> {
 
     /// Simultaneously adjust the times of all the waypoints in the trajectory
     pub fn adjust_times(&mut self, by: Duration) {
-        // SAFETY: Uniformly changing the times of all the waypoints inside the
+        // Uniformly changing the times of all the waypoints inside the
         // trajectory cannot change their relative ordering.
-        unsafe {
-            let vec = self.waypoints.get_unchecked_mut_vec();
-            for element in vec.iter_mut() {
-                let new_time = *element.0.time() + by;
-                element.0.set_time(new_time);
+        for element in self.waypoints.as_mut_slice() {
+            let new_time = *element.0.time() + by;
+            element.0.set_time(new_time);
+        }
+    }
+
+    /// Scale the duration of the trajectory by the given factor while keeping
+    /// its initial time fixed. A factor greater than 1 will slow the trajectory
+    /// down while a factor less than 1 will speed it up.
+    ///
+    /// If the factor is not positive, or if the scaling would cause two
+    /// waypoints to land on the same nanosecond, then the trajectory is left
+    /// unchanged and this returns an Err.
+    pub fn scale_time(&mut self, factor: f64) -> Result<(), ScaleTimeError> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return Result::Err(ScaleTimeError::Factor(factor));
+        }
+
+        let t0 = self.initial_time();
+        let new_times: Vec<TimePoint> = self
+            .waypoints
+            .iter()
+            .map(|wp| {
+                let dt = (*wp.0.time() - t0).nanos as f64 * factor;
+                t0 + Duration::new(dt.round() as i64)
+            })
+            .collect();
+
+        for pair in new_times.windows(2) {
+            if pair[1] <= pair[0] {
+                return Result::Err(ScaleTimeError::Collapsed(factor));
             }
         }
+
+        // We have verified above that the new times remain strictly
+        // increasing, so the order of the waypoints is preserved.
+        for (element, new_time) in self.waypoints.as_mut_slice().iter_mut().zip(new_times) {
+            element.0.set_time(new_time);
+        }
+
+        return Result::Ok(());
     }
 
     /// Get the waypoint at the requested index if it is available, otherwise
@@ -232,7 +429,7 @@ impl<'a, W: Waypoint> Trajectory<W> {
         // and we ensure that changes to the time value of the waypoint will
         // keep it within its current location in the trajectory.
         unsafe {
-            let vec = self.waypoints.get_unchecked_mut_vec();
+            let vec = self.waypoints.as_mut_slice();
             if index > 0 {
                 lower_bound_opt = Some(vec.get_unchecked(index - 1).0.time().clone());
             }
@@ -288,6 +485,13 @@ impl<'a, W: Waypoint> Trajectory<W> {
         return self.waypoints.capacity();
     }
 
+    /// Check whether the waypoints are stored inline, without any heap
+    /// allocation. This is true as long as the trajectory has never held more
+    /// than [`INLINE_WAYPOINTS`] waypoints.
+    pub fn is_inline(&self) -> bool {
+        return self.waypoints.is_inline();
+    }
+
     /// Get a motion for this trajectory
     pub fn motion(&'a self) -> TrajectoryMotion<'a, W> {
         return TrajectoryMotion {
@@ -308,6 +512,129 @@ impl<'a, W: Waypoint> Trajectory<W> {
     }
 }
 
+impl<W: PositionedWaypoint> Trajectory<W> {
+    /// Get the waypoint that the trajectory passes through at the requested
+    /// time. If the time lands between two waypoints then a new waypoint will
+    /// be interpolated.
+    pub fn waypoint_at(&self, time: &TimePoint) -> Result<W, InterpError> {
+        match self.find(time) {
+            Find::Exact(index) => {
+                return Ok(self.waypoints[index].0.clone());
+            }
+            Find::Approaching(index) => {
+                let wp0 = &self.waypoints[index - 1].0;
+                let wp1 = &self.waypoints[index].0;
+                let position = wp0.interpolate(wp1).compute_position(time)?;
+                return Ok(W::from_position(*time, position));
+            }
+            Find::BeforeStart | Find::AfterFinish => {
+                return Err(InterpError::OutOfBounds);
+            }
+        }
+    }
+
+    /// Split the trajectory into the part that comes before the requested time
+    /// and the part that comes after it. Both parts will contain a waypoint at
+    /// the requested time, interpolated if necessary.
+    ///
+    /// The time must be strictly between the initial and finish times of the
+    /// trajectory, otherwise one of the parts would be empty and this will
+    /// return Err(InterpError::OutOfBounds).
+    pub fn split(&self, time: &TimePoint) -> Result<(Self, Self), InterpError> {
+        if *time <= self.initial_time() || self.finish_time() <= *time {
+            return Err(InterpError::OutOfBounds);
+        }
+
+        let cut = self.waypoint_at(time)?;
+        let before = Self::from_iter(
+            self.waypoints
+                .iter()
+                .take_while(|wp| wp.0.time() < time)
+                .map(|wp| wp.0.clone())
+                .chain([cut.clone()]),
+        )
+        .map_err(|_| InterpError::OutOfBounds)?;
+
+        let after = Self::from_iter(
+            [cut].into_iter().chain(
+                self.waypoints
+                    .iter()
+                    .skip_while(|wp| wp.0.time() <= time)
+                    .map(|wp| wp.0.clone()),
+            ),
+        )
+        .map_err(|_| InterpError::OutOfBounds)?;
+
+        return Ok((before, after));
+    }
+
+    /// Remove the part of the trajectory that comes before the requested time,
+    /// e.g. the part that a robot has already executed. The trajectory will
+    /// begin with a waypoint at the requested time, interpolated if necessary.
+    ///
+    /// If the time is at or before the start of the trajectory then nothing
+    /// will change. If the time is at or after the finish of the trajectory
+    /// then nothing would remain, so this returns
+    /// Err(InterpError::OutOfBounds) and the trajectory is unchanged.
+    pub fn trim_before(&mut self, time: &TimePoint) -> Result<(), InterpError> {
+        if *time <= self.initial_time() {
+            return Ok(());
+        }
+
+        let (_, remainder) = self.split(time)?;
+        *self = remainder;
+        return Ok(());
+    }
+
+    /// Append another trajectory onto the end of this one. The other trajectory
+    /// must begin at the same time and position where this one finishes,
+    /// otherwise this trajectory is unchanged and an Err is returned.
+    pub fn concatenate(&mut self, other: &Self) -> Result<(), ConcatenateError> {
+        if other.initial_time() != self.finish_time() {
+            return Err(ConcatenateError::TimeMismatch);
+        }
+
+        if !self.finish().position_matches(other.initial()) {
+            return Err(ConcatenateError::PositionMismatch);
+        }
+
+        self.waypoints.reserve(other.len() - 1);
+        for wp in other.waypoints.iter().skip(1) {
+            self.waypoints.push(wp.clone());
+        }
+
+        return Ok(());
+    }
+
+    /// Create a new trajectory whose waypoints are evenly spaced in time by
+    /// the given step, starting from the initial time of this trajectory. The
+    /// finish waypoint is always kept, so the last step may be shorter than
+    /// the others. Note that waypoints of the original trajectory which do not
+    /// land on a step will not be kept.
+    ///
+    /// If the step is not positive then this returns an Err.
+    pub fn resample(&self, step: Duration) -> Result<Self, ResampleError> {
+        if step.nanos <= 0 {
+            return Result::Err(ResampleError::NonPositiveStep(step));
+        }
+
+        let finish_time = self.finish_time();
+        let mut result = Self {
+            waypoints: Waypoints::new(),
+        };
+
+        let mut t = self.initial_time();
+        while t < finish_time {
+            let wp = self.waypoint_at(&t).map_err(ResampleError::Interpolation)?;
+            result.waypoints.push(TimeCmp(wp));
+            t += step;
+        }
+        result.waypoints.push(TimeCmp(self.finish().clone()));
+
+        return Result::Ok(result);
+    }
+}
+
 impl<W: Waypoint> std::fmt::Debug for Trajectory<W> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut builder = fmt.debug_list();
@@ -319,6 +646,45 @@ impl<W: Waypoint> std::fmt::Debug for Trajectory<W> {
     }
 }
 
+/// Trajectories are serialized as a sequence of their waypoints.
+#[cfg(feature = "serde")]
+impl<W: Waypoint + serde::Serialize> serde::Serialize for Trajectory<W> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.waypoints.iter().map(|wp| &wp.0))
+    }
+}
+
+/// Deserialization will fail if the sequence of waypoints does not describe a
+/// valid trajectory, i.e. it must have at least two waypoints and the times of
+/// the waypoints must be strictly increasing.
+#[cfg(feature = "serde")]
+impl<'de, W: Waypoint + serde::Deserialize<'de>> serde::Deserialize<'de> for Trajectory<W> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let waypoints = Vec::<W>::deserialize(deserializer)?;
+        if waypoints.len() < 2 {
+            return Err(D::Error::invalid_length(
+                waypoints.len(),
+                &"a trajectory with at least two waypoints",
+            ));
+        }
+
+        for pair in waypoints.windows(2) {
+            if pair[1].time() <= pair[0].time() {
+                return Err(D::Error::custom(format!(
+                    "the times of the waypoints in a trajectory must be strictly \
+                    increasing, but {:?} is followed by {:?}",
+                    pair[0].time(),
+                    pair[1].time(),
+                )));
+            }
+        }
+
+        Self::from_iter(waypoints)
+            .map_err(|_| D::Error::custom("unable to construct a trajectory"))
+    }
+}
+
 impl<W: Waypoint> std::ops::Deref for Trajectory<W> {
     type Target = [TimeCmp<W>];
 
@@ -483,4 +849,177 @@ mod tests {
         let err = motion.compute_velocity(&(t0 - time_point::Duration::new(1)));
         assert_eq!(err, Err(InterpError::OutOfBounds));
     }
+
+    fn make_test_trajectory() -> se2::LinearTrajectory {
+        let t0 = time_point::TimePoint::new(0);
+        se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(t0, 0.0, 0.0, 0.0),
+            WaypointSE2::new(t0 + time_point::Duration::from_secs_f64(2.0), 2.0, 0.0, 0.0),
+            WaypointSE2::new(t0 + time_point::Duration::from_secs_f64(4.0), 2.0, 2.0, 0.0),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_split_and_concatenate() {
+        let trajectory = make_test_trajectory();
+        let t_cut = time_point::TimePoint::from_secs_f64(1.0);
+        let (before, after) = trajectory.split(&t_cut).unwrap();
+        assert_eq!(before.len(), 2);
+        assert_eq!(after.len(), 3);
+        assert_eq!(before.finish_time(), t_cut);
+        assert_eq!(after.initial_time(), t_cut);
+        assert_relative_eq!(before.finish().position.translation.vector[0], 1.0);
+        assert_relative_eq!(after.initial().position.translation.vector[0], 1.0);
+
+        // Splitting exactly on a waypoint does not create a new one
+        let (before, after) = trajectory
+            .split(&time_point::TimePoint::from_secs_f64(2.0))
+            .unwrap();
+        assert_eq!(before.len(), 2);
+        assert_eq!(after.len(), 2);
+
+        assert_eq!(
+            trajectory.split(&trajectory.initial_time()).err(),
+            Some(InterpError::OutOfBounds)
+        );
+        assert_eq!(
+            trajectory.split(&trajectory.finish_time()).err(),
+            Some(InterpError::OutOfBounds)
+        );
+
+        let mut joined = before.clone();
+        joined.concatenate(&after).unwrap();
+        assert_eq!(joined.len(), trajectory.len());
+        assert_eq!(joined.finish_time(), trajectory.finish_time());
+
+        // The pieces do not connect when given in the wrong order
+        let mut wrong = after.clone();
+        assert_eq!(
+            wrong.concatenate(&before),
+            Err(ConcatenateError::TimeMismatch)
+        );
+
+        let mut shifted = after.clone();
+        shifted
+            .mutate_waypoint(0, |wp| wp.position.translation.vector[1] = 1.0)
+            .ok()
+            .unwrap();
+        let mut gap = before.clone();
+        assert_eq!(
+            gap.concatenate(&shifted),
+            Err(ConcatenateError::PositionMismatch)
+        );
+        assert_eq!(gap.len(), before.len());
+    }
+
+    #[test]
+    fn test_trim_resample_and_scale() {
+        let mut trajectory = make_test_trajectory();
+        let resampled = trajectory
+            .resample(time_point::Duration::from_secs_f64(1.5))
+            .unwrap();
+        // Samples at t = 0.0, 1.5, 3.0 and the finish at t = 4.0
+        assert_eq!(resampled.len(), 4);
+        assert_relative_eq!(resampled[1].position.translation.vector[0], 1.5);
+        assert_relative_eq!(resampled[2].position.translation.vector[1], 1.0);
+        assert_eq!(
+            trajectory
+                .resample(time_point::Duration::new(0))
+                .unwrap_err(),
+            ResampleError::NonPositiveStep(time_point::Duration::new(0))
+        );
+
+        let mut slow = trajectory.clone();
+        slow.scale_time(2.0).unwrap();
+        assert_eq!(slow.initial_time(), trajectory.initial_time());
+        assert_eq!(slow.duration(), time_point::Duration::from_secs_f64(8.0));
+        assert_eq!(slow.scale_time(0.0), Err(ScaleTimeError::Factor(0.0)));
+        assert_eq!(slow.scale_time(-1.0), Err(ScaleTimeError::Factor(-1.0)));
+
+        trajectory
+            .trim_before(&time_point::TimePoint::from_secs_f64(3.0))
+            .unwrap();
+        assert_eq!(trajectory.len(), 2);
+        assert_eq!(
+            trajectory.initial_time(),
+            time_point::TimePoint::from_secs_f64(3.0)
+        );
+        assert_relative_eq!(trajectory.initial().position.translation.vector[1], 1.0);
+
+        let unchanged = trajectory.clone();
+        assert_eq!(
+            trajectory.trim_before(&trajectory.finish_time()),
+            Err(InterpError::OutOfBounds)
+        );
+        assert_eq!(trajectory.len(), unchanged.len());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialization() {
+        let t0 = time_point::TimePoint::new(0);
+        let trajectory = se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(t0, 0.0, 0.0, 0.0),
+            WaypointSE2::new(t0 + time_point::Duration::from_secs_f64(1.0), 1.0, 0.0, 0.0),
+            WaypointSE2::new(
+                t0 + time_point::Duration::from_secs_f64(2.0),
+                1.0,
+                0.0,
+                90f64.to_radians(),
+            ),
+        ])
+        .unwrap();
+
+        let text = serde_json::to_string(&trajectory).unwrap();
+        let parsed: se2::LinearTrajectory = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed.len(), trajectory.len());
+        for (a, b) in parsed.iter().zip(trajectory.iter()) {
+            assert_eq!(a.time, b.time);
+            assert_relative_eq!(a.position.translation.vector[0], b.position.translation.vector[0]);
+            assert_relative_eq!(a.position.translation.vector[1], b.position.translation.vector[1]);
+            assert_relative_eq!(a.position.rotation.angle(), b.position.rotation.angle());
+        }
+
+        let too_short = r#"[{"time":0,"x":0.0,"y":0.0,"yaw":0.0}]"#;
+        assert!(serde_json::from_str::<se2::LinearTrajectory>(too_short).is_err());
+
+        let out_of_order = r#"[
+            {"time":10,"x":0.0,"y":0.0,"yaw":0.0},
+            {"time":5,"x":1.0,"y":0.0,"yaw":0.0}
+        ]"#;
+        assert!(serde_json::from_str::<se2::LinearTrajectory>(out_of_order).is_err());
+    }
+
+    #[test]
+    fn test_inline_waypoints_spill_to_heap() {
+        let t0 = time_point::TimePoint::new(0);
+        let waypoint = |secs: i64| {
+            WaypointSE2::new(
+                t0 + time_point::Duration::from_secs(secs),
+                secs as f64,
+                0.0,
+                0.0,
+            )
+        };
+
+        let mut trajectory =
+            se2::LinearTrajectory::from_iter((0..INLINE_WAYPOINTS as i64).map(waypoint))
+                .expect("Trajectory failed to be created");
+        assert!(trajectory.is_inline());
+        let inline = trajectory.clone();
+
+        assert_eq!(
+            trajectory.insert(waypoint(INLINE_WAYPOINTS as i64)).ok(),
+            Some(INLINE_WAYPOINTS)
+        );
+        assert!(!trajectory.is_inline());
+        assert_eq!(trajectory.len(), INLINE_WAYPOINTS + 1);
+
+        // Removing waypoints does not move them back inline, and equality
+        // does not depend on how the waypoints are stored.
+        assert!(trajectory.remove_index(INLINE_WAYPOINTS).is_ok());
+        assert!(!trajectory.is_inline());
+        assert!(trajectory == inline);
+    }
 }
@@ -16,6 +16,7 @@
 */
 
 use super::{timed, Interpolation};
+use time_point::TimePoint;
 
 pub trait Waypoint:
     timed::Timed + Interpolation<Self::Position, Self::Velocity> + Clone + std::fmt::Debug
@@ -26,3 +27,16 @@ pub trait Waypoint:
     /// How does the waypoint represent the time derivative of its position
     type Velocity;
 }
+
+/// Waypoints that can be created from just a time and position. Trajectories
+/// need this to split, trim, resample, or concatenate themselves.
+pub trait PositionedWaypoint: Waypoint {
+    /// Create a waypoint at the given time and position. This is used to
+    /// create new waypoints by interpolating along a trajectory.
+    fn from_position(time: TimePoint, position: Self::Position) -> Self;
+
+    /// Check whether this waypoint is at the same position as another,
+    /// regardless of the time of either waypoint. Positions are compared using
+    /// the default thresholds of the motion module.
+    fn position_matches(&self, other: &Self) -> bool;
+}
@@ -0,0 +1,343 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    graph::{Edge, Graph},
+    motion::Duration,
+    node::Key,
+};
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error as ThisError;
+
+/// Identifies a vertex on one level of a [`MultiLevelGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelKey<K> {
+    /// Index of the level that the vertex belongs to
+    pub level: usize,
+    /// Key of the vertex within the graph of its level
+    pub vertex: K,
+}
+
+impl<K> LevelKey<K> {
+    pub fn new(level: usize, vertex: K) -> Self {
+        Self { level, vertex }
+    }
+}
+
+impl<K: Key> Edge<LevelKey<K>> for (LevelKey<K>, LevelKey<K>) {
+    fn from_vertex(&self) -> &LevelKey<K> {
+        &self.0
+    }
+
+    fn to_vertex(&self) -> &LevelKey<K> {
+        &self.1
+    }
+}
+
+/// Describes how an agent moves through a transition and how long it takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionKind {
+    /// The agent waits for the lift to arrive and then rides it for a fixed
+    /// duration. The agent does not drive while inside the lift, so it exits
+    /// with the same orientation that it entered with.
+    Lift { wait: Duration, ride: Duration },
+    /// The agent waits for the door to open and then drives through it.
+    Door { open: Duration },
+}
+
+impl TransitionKind {
+    /// How long the agent must wait before it can begin moving through the
+    /// transition.
+    pub fn wait(&self) -> Duration {
+        match self {
+            TransitionKind::Lift { wait, .. } => *wait,
+            TransitionKind::Door { open } => *open,
+        }
+    }
+}
+
+/// A one-way connection between two vertices of a [`MultiLevelGraph`]. The
+/// vertices may be on the same level (e.g. a door between two rooms) or on
+/// different levels (e.g. a lift).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition<K> {
+    pub from: LevelKey<K>,
+    pub to: LevelKey<K>,
+    pub kind: TransitionKind,
+}
+
+/// Returned by [`MultiLevelGraph::add_transition`] when a transition is
+/// invalid.
+#[derive(ThisError, Debug, Clone, PartialEq)]
+pub enum TransitionError<K: Key> {
+    #[error("The transition begins at a vertex that does not exist: {0:?}")]
+    MissingFrom(LevelKey<K>),
+    #[error("The transition ends at a vertex that does not exist: {0:?}")]
+    MissingTo(LevelKey<K>),
+    #[error("The wait of a transition must not be negative, but it was {0:?}")]
+    NegativeWait(Duration),
+    #[error("The ride of a lift must take some time, but it was {0:?}")]
+    InstantRide(Duration),
+}
+
+/// A graph made of several levels, each with its own graph, which are connected
+/// to each other by lift and door transitions.
+///
+/// The edges of this graph include both the edges within each level and the
+/// transitions. Expanders that need to generate special motions for the
+/// transitions should expand over [`WithinLevels`] instead and handle the
+/// transitions themselves.
+#[derive(Debug)]
+pub struct MultiLevelGraph<G: Graph> {
+    levels: Vec<Arc<G>>,
+    transitions: HashMap<LevelKey<G::Key>, Vec<Transition<G::Key>>>,
+    /// A user may ask for the transitions of a vertex that has none, in which
+    /// case we return a reference to this always-empty vector.
+    _placeholder: Vec<Transition<G::Key>>,
+}
+
+impl<G: Graph> MultiLevelGraph<G> {
+    pub fn new(levels: Vec<Arc<G>>) -> Self {
+        Self {
+            levels,
+            transitions: HashMap::new(),
+            _placeholder: Vec::new(),
+        }
+    }
+
+    pub fn levels(&self) -> &[Arc<G>] {
+        &self.levels
+    }
+
+    pub fn level(&self, level: usize) -> Option<&Arc<G>> {
+        self.levels.get(level)
+    }
+
+    /// Add a transition to the graph. This will return an Err if either end of
+    /// the transition is not a vertex of this graph, if any of its durations
+    /// are negative, or if a lift ride has no duration.
+    pub fn add_transition(
+        &mut self,
+        transition: Transition<G::Key>,
+    ) -> Result<(), TransitionError<G::Key>> {
+        if self.vertex(transition.from.clone()).is_none() {
+            return Err(TransitionError::MissingFrom(transition.from));
+        }
+
+        if self.vertex(transition.to.clone()).is_none() {
+            return Err(TransitionError::MissingTo(transition.to));
+        }
+
+        if transition.kind.wait().nanos < 0 {
+            return Err(TransitionError::NegativeWait(transition.kind.wait()));
+        }
+
+        if let TransitionKind::Lift { ride, .. } = transition.kind {
+            if ride.nanos <= 0 {
+                return Err(TransitionError::InstantRide(ride));
+            }
+        }
+
+        self.transitions
+            .entry(transition.from.clone())
+            .or_default()
+            .push(transition);
+        return Ok(());
+    }
+
+    /// Get all the transitions that begin at the given vertex.
+    pub fn transitions_from(&self, key: &LevelKey<G::Key>) -> &[Transition<G::Key>] {
+        self.transitions.get(key).unwrap_or(&self._placeholder)
+    }
+
+    /// Iterate over every transition in the graph, in no particular order.
+    pub fn transitions(&self) -> impl Iterator<Item = &Transition<G::Key>> {
+        self.transitions.values().flatten()
+    }
+
+    fn vertex_on_level(&self, key: LevelKey<G::Key>) -> Option<G::Vertex> {
+        self.levels
+            .get(key.level)
+            .and_then(|graph| graph.vertex(key.vertex))
+    }
+
+    fn edges_within_level<'a>(
+        &'a self,
+        key: LevelKey<G::Key>,
+    ) -> impl Iterator<Item = (LevelKey<G::Key>, LevelKey<G::Key>)> + 'a {
+        let level = key.level;
+        self.levels.get(level).into_iter().flat_map(move |graph| {
+            graph
+                .edges_from_vertex(key.vertex.clone())
+                .into_iter()
+                .map(move |edge| {
+                    (
+                        LevelKey::new(level, edge.from_vertex().clone()),
+                        LevelKey::new(level, edge.to_vertex().clone()),
+                    )
+                })
+        })
+    }
+}
+
+impl<G: Graph> Graph for MultiLevelGraph<G> {
+    type Key = LevelKey<G::Key>;
+    type Vertex = G::Vertex;
+    type Edge = (LevelKey<G::Key>, LevelKey<G::Key>);
+    type EdgeIter<'a> = impl Iterator<Item=(LevelKey<G::Key>, LevelKey<G::Key>)> + 'a where Self: 'a;
+
+    fn vertex(&self, key: Self::Key) -> Option<Self::Vertex> {
+        self.vertex_on_level(key)
+    }
+
+    fn edges_from_vertex<'a>(&'a self, key: Self::Key) -> Self::EdgeIter<'a> {
+        let transitions = self
+            .transitions_from(&key)
+            .iter()
+            .map(|transition| (transition.from.clone(), transition.to.clone()));
+
+        self.edges_within_level(key).chain(transitions)
+    }
+}
+
+/// A view of a [`MultiLevelGraph`] which only contains the edges within each
+/// level, leaving out all of the transitions.
+#[derive(Debug)]
+pub struct WithinLevels<G: Graph> {
+    graph: Arc<MultiLevelGraph<G>>,
+}
+
+impl<G: Graph> WithinLevels<G> {
+    pub fn new(graph: Arc<MultiLevelGraph<G>>) -> Self {
+        Self { graph }
+    }
+
+    pub fn graph(&self) -> &Arc<MultiLevelGraph<G>> {
+        &self.graph
+    }
+}
+
+impl<G: Graph> Graph for WithinLevels<G> {
+    type Key = LevelKey<G::Key>;
+    type Vertex = G::Vertex;
+    type Edge = (LevelKey<G::Key>, LevelKey<G::Key>);
+    type EdgeIter<'a> = impl Iterator<Item=(LevelKey<G::Key>, LevelKey<G::Key>)> + 'a where Self: 'a;
+
+    fn vertex(&self, key: Self::Key) -> Option<Self::Vertex> {
+        self.graph.vertex_on_level(key)
+    }
+
+    fn edges_from_vertex<'a>(&'a self, key: Self::Key) -> Self::EdgeIter<'a> {
+        self.graph.edges_within_level(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{directed::SimpleGraph, motion::r2::Position};
+
+    fn make_test_graph() -> MultiLevelGraph<SimpleGraph<Position>> {
+        let ground = SimpleGraph::from_iters(
+            [Position::new(0.0, 0.0), Position::new(5.0, 0.0)],
+            [(0, 1), (1, 0)],
+        );
+        let upper = SimpleGraph::from_iters(
+            [Position::new(5.0, 0.0), Position::new(5.0, 5.0)],
+            [(0, 1), (1, 0)],
+        );
+
+        MultiLevelGraph::new(vec![Arc::new(ground), Arc::new(upper)])
+    }
+
+    #[test]
+    fn test_multi_level_graph() {
+        let mut graph = make_test_graph();
+        let lift = TransitionKind::Lift {
+            wait: Duration::from_secs(10),
+            ride: Duration::from_secs(20),
+        };
+        graph
+            .add_transition(Transition {
+                from: LevelKey::new(0, 1),
+                to: LevelKey::new(1, 0),
+                kind: lift,
+            })
+            .unwrap();
+
+        // Transitions must connect vertices that exist
+        assert_eq!(
+            graph.add_transition(Transition {
+                from: LevelKey::new(0, 1),
+                to: LevelKey::new(2, 0),
+                kind: lift,
+            }),
+            Err(TransitionError::MissingTo(LevelKey::new(2, 0)))
+        );
+        assert_eq!(
+            graph.add_transition(Transition {
+                from: LevelKey::new(1, 5),
+                to: LevelKey::new(0, 0),
+                kind: TransitionKind::Door {
+                    open: Duration::from_secs(1)
+                },
+            }),
+            Err(TransitionError::MissingFrom(LevelKey::new(1, 5)))
+        );
+
+        // A lift needs to take some time to ride
+        assert_eq!(
+            graph.add_transition(Transition {
+                from: LevelKey::new(1, 0),
+                to: LevelKey::new(0, 1),
+                kind: TransitionKind::Lift {
+                    wait: Duration::from_secs(10),
+                    ride: Duration::zero(),
+                },
+            }),
+            Err(TransitionError::InstantRide(Duration::zero()))
+        );
+
+        let mut edges: Vec<_> = graph.edges_from_vertex(LevelKey::new(0, 1)).collect();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                (LevelKey::new(0, 1), LevelKey::new(0, 0)),
+                (LevelKey::new(0, 1), LevelKey::new(1, 0)),
+            ]
+        );
+
+        // The lift only goes up
+        let edges: Vec<_> = graph.edges_from_vertex(LevelKey::new(1, 0)).collect();
+        assert_eq!(edges, vec![(LevelKey::new(1, 0), LevelKey::new(1, 1))]);
+
+        let graph = Arc::new(graph);
+        let within = WithinLevels::new(graph.clone());
+        let edges: Vec<_> = within.edges_from_vertex(LevelKey::new(0, 1)).collect();
+        assert_eq!(edges, vec![(LevelKey::new(0, 1), LevelKey::new(0, 0))]);
+
+        assert_eq!(
+            within.vertex(LevelKey::new(1, 1)),
+            Some(Position::new(5.0, 5.0))
+        );
+        assert!(within.vertex(LevelKey::new(2, 0)).is_none());
+        assert_eq!(graph.transitions_from(&LevelKey::new(0, 1)).len(), 1);
+        assert!(graph.transitions_from(&LevelKey::new(0, 0)).is_empty());
+    }
+}
@@ -236,10 +236,131 @@ impl<N: Weighted + PartialKeyed + Timed> ClosedSet<N> for TimeVariantPartialKeye
     }
 }
 
+/// A closed set for time-variant searches which keeps a Pareto front of nodes
+/// for each key. A node is only considered closed if some node with the same
+/// key arrived no later and cost no more. Nodes that arrive later but cost less
+/// (or arrive earlier but cost more) will remain open.
+///
+/// Arrival times are rounded down to a multiple of `TIME_RESOLUTION`, given in
+/// nanoseconds, before they are compared, so nodes whose arrival times only
+/// differ by a tiny amount can still prune each other. Set `TIME_RESOLUTION`
+/// to 1 to compare the exact arrival times.
+///
+/// Note that if waiting in place costs more than zero, then a node that waits
+/// will always be dominated by the node it waited from, as long as they share
+/// a key.
+pub struct DominanceClosedSet<N, const TIME_RESOLUTION: u64 = 1_000_000>
+where
+    N: Weighted + PartialKeyed + Timed,
+{
+    /// Each front is sorted by arrival time, which means its costs are strictly
+    /// decreasing.
+    closed_set: HashMap<N::Key, Vec<Arc<N>>>,
+}
+
+impl<N, const TIME_RESOLUTION: u64> DominanceClosedSet<N, TIME_RESOLUTION>
+where
+    N: Weighted + PartialKeyed + Timed,
+{
+    /// The resolution, in nanoseconds, that arrival times are compared with.
+    pub const fn time_resolution() -> u64 {
+        TIME_RESOLUTION
+    }
+
+    /// Get the Pareto front of the nodes that were closed with this key,
+    /// sorted from the earliest arrival to the latest.
+    pub fn front(&self, key: &N::Key) -> &[Arc<N>] {
+        self.closed_set
+            .get(key)
+            .map(|f| f.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn time_key(node: &N) -> i64 {
+        node.time()
+            .nanos_since_zero
+            .div_euclid(TIME_RESOLUTION.max(1) as i64)
+    }
+
+    /// Find the node in the front that dominates this node, or else the index
+    /// where the node belongs in the front.
+    fn find_dominator<'a>(front: &'a [Arc<N>], node: &N) -> Result<&'a Arc<N>, usize> {
+        let t = Self::time_key(node);
+        let earlier = front.partition_point(|n| Self::time_key(n) < t);
+        let no_later = earlier + front[earlier..].partition_point(|n| Self::time_key(n) <= t);
+        // The last node that arrived no later than this one is the cheapest of
+        // all of them, so it is the only one that needs to be checked.
+        if let Some(prior) = no_later.checked_sub(1).map(|i| &front[i]) {
+            if prior.cost() <= node.cost() {
+                return Ok(prior);
+            }
+        }
+
+        return Err(earlier);
+    }
+}
+
+impl<N, const TIME_RESOLUTION: u64> Default for DominanceClosedSet<N, TIME_RESOLUTION>
+where
+    N: Weighted + PartialKeyed + Timed,
+{
+    fn default() -> Self {
+        Self {
+            closed_set: Default::default(),
+        }
+    }
+}
+
+impl<N, const TIME_RESOLUTION: u64> ClosedSet<N> for DominanceClosedSet<N, TIME_RESOLUTION>
+where
+    N: Weighted + PartialKeyed + Timed,
+{
+    type Iter<'a> = impl Iterator<Item=&'a Arc<N>> + 'a where N: 'a;
+
+    fn close(&mut self, node: &Arc<N>) -> CloseResult<N> {
+        if let Some(key) = node.partial_key() {
+            let front = self.closed_set.entry(key.clone()).or_default();
+            match Self::find_dominator(front, node) {
+                Ok(prior) => return CloseResult::Prior(prior.clone()),
+                Err(index) => {
+                    // Drop the nodes that arrive no earlier than the new node
+                    // and cost no less. Since the costs of the front decrease
+                    // over time, these are all next to each other.
+                    let dominated = front[index..]
+                        .iter()
+                        .take_while(|n| n.cost() >= node.cost())
+                        .count();
+                    front.splice(index..index + dominated, [node.clone()]);
+                    return CloseResult::Closed;
+                }
+            }
+        }
+
+        return CloseResult::Closed;
+    }
+
+    fn status(&self, node: &N) -> ClosedStatus<N> {
+        if let Some(key) = node.partial_key() {
+            if let Some(front) = self.closed_set.get(key) {
+                if let Ok(prior) = Self::find_dominator(front, node) {
+                    return ClosedStatus::Closed(prior.clone());
+                }
+            }
+        }
+
+        return ClosedStatus::Open;
+    }
+
+    fn iter<'a>(&'a self) -> Self::Iter<'a> {
+        self.closed_set.values().flatten()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use time_point::TimePoint;
 
     struct TestNode {
         graph_index: usize,
@@ -324,4 +445,110 @@ mod tests {
             ClosedStatus::Closed(_)
         ));
     }
+
+    struct TimedTestNode {
+        graph_index: usize,
+        time: TimePoint,
+        cost: u64,
+    }
+
+    impl TimedTestNode {
+        fn new(graph_index: usize, time_millis: i64, cost: u64) -> Arc<Self> {
+            Arc::new(Self {
+                graph_index,
+                time: TimePoint::new(time_millis * 1_000_000),
+                cost,
+            })
+        }
+    }
+
+    impl PartialKeyed for TimedTestNode {
+        type Key = usize;
+
+        fn partial_key(&self) -> Option<&Self::Key> {
+            Some(&self.graph_index)
+        }
+    }
+
+    impl Weighted for TimedTestNode {
+        type Cost = u64;
+
+        fn cost(&self) -> u64 {
+            return self.cost;
+        }
+    }
+
+    impl Timed for TimedTestNode {
+        fn time(&self) -> &TimePoint {
+            return &self.time;
+        }
+    }
+
+    fn costs_of_front<const R: u64>(
+        closed_set: &DominanceClosedSet<TimedTestNode, R>,
+        key: usize,
+    ) -> Vec<u64> {
+        closed_set.front(&key).iter().map(|n| n.cost).collect()
+    }
+
+    #[test]
+    fn dominance_closed_set_keeps_pareto_front() {
+        let mut closed_set = DominanceClosedSet::<TimedTestNode, 1>::default();
+
+        let node_a = TimedTestNode::new(0, 10_000, 10);
+        assert!(matches!(closed_set.status(&node_a), ClosedStatus::Open));
+        assert!(matches!(closed_set.close(&node_a), CloseResult::Closed));
+
+        // Arrives later and costs more
+        let node_b = TimedTestNode::new(0, 12_000, 12);
+        assert!(matches!(
+            closed_set.status(&node_b),
+            ClosedStatus::Closed(_)
+        ));
+        assert!(matches!(closed_set.close(&node_b), CloseResult::Prior(_)));
+
+        // Arrives later but costs less
+        let node_c = TimedTestNode::new(0, 12_000, 8);
+        assert!(matches!(closed_set.status(&node_c), ClosedStatus::Open));
+        assert!(matches!(closed_set.close(&node_c), CloseResult::Closed));
+
+        // Arrives earlier but costs more
+        let node_d = TimedTestNode::new(0, 8_000, 11);
+        assert!(matches!(closed_set.status(&node_d), ClosedStatus::Open));
+        assert!(matches!(closed_set.close(&node_d), CloseResult::Closed));
+        assert_eq!(costs_of_front(&closed_set, 0), [11, 10, 8]);
+
+        // Dominates node_a and node_c but not node_d
+        let node_e = TimedTestNode::new(0, 9_000, 7);
+        assert!(matches!(closed_set.close(&node_e), CloseResult::Closed));
+        assert_eq!(costs_of_front(&closed_set, 0), [11, 7]);
+
+        // Arrives at the same time and costs the same
+        let node_f = TimedTestNode::new(0, 9_000, 7);
+        assert!(matches!(closed_set.close(&node_f), CloseResult::Prior(_)));
+
+        // A different key is unaffected by the others
+        let node_g = TimedTestNode::new(1, 20_000, 20);
+        assert!(matches!(closed_set.status(&node_g), ClosedStatus::Open));
+        assert!(matches!(closed_set.close(&node_g), CloseResult::Closed));
+        assert_eq!(closed_set.iter().count(), 3);
+    }
+
+    #[test]
+    fn dominance_closed_set_time_resolution() {
+        let node_a = TimedTestNode::new(0, 10_200, 10);
+        let node_b = TimedTestNode::new(0, 10_700, 10);
+
+        let mut exact = DominanceClosedSet::<TimedTestNode, 1>::default();
+        exact.close(&node_b);
+        assert!(matches!(exact.status(&node_a), ClosedStatus::Open));
+        exact.close(&node_a);
+        assert_eq!(costs_of_front(&exact, 0), [10]);
+        assert!(Arc::ptr_eq(&exact.front(&0)[0], &node_a));
+
+        // With a resolution of one second, both nodes arrive at the same time
+        let mut coarse = DominanceClosedSet::<TimedTestNode, 1_000_000_000>::default();
+        coarse.close(&node_b);
+        assert!(matches!(coarse.status(&node_a), ClosedStatus::Closed(_)));
+    }
 }
@@ -0,0 +1,309 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    error::NoError,
+    expander::{Closable, Expander, InitTargeted, Solvable, Targeted},
+    motion::{
+        graph_search::ReconstructMotion,
+        movable::{ArcMovable, StartingPoint},
+        r2::{self, graph_search::Node, timed_position::LineFollow},
+        trajectory::CostCalculator,
+        Extrapolator, TimePoint, Trajectory,
+    },
+    node::{Agent, PartialKeyed, PartialKeyedClosedSet, PathSearch},
+    occupancy::{
+        grid_search::{straight_line_cost, GridSearchInitError, GridSearchSolution, NEIGHBORS},
+        Cell, Grid, Point,
+    },
+};
+use num::Zero;
+use std::sync::Arc;
+
+/// An any-angle expander that searches directly over the cells of an occupancy
+/// grid, in the style of Theta*.
+///
+/// Each node sits at the center of a cell and is expanded into the eight
+/// neighboring cells. When a child is created, the expander first tries to
+/// connect it straight to the parent of its parent. If that line is clear for
+/// an agent of the given radius, the intermediate corner is skipped. This
+/// gives paths that are close in quality to a visibility graph without having
+/// to precompute or maintain one, which makes it suitable for large maps that
+/// change often.
+///
+/// The heuristic is the cost of travelling in a straight line to the goal,
+/// which is admissible as long as the cost calculator never charges less for
+/// a longer straight line motion.
+pub struct AnyAngleExpander<G: Grid, C: CostCalculator<r2::timed_position::Waypoint>> {
+    pub grid: Arc<G>,
+    pub agent_radius: f64,
+    pub extrapolator: Arc<LineFollow>,
+    pub cost_calculator: Arc<C>,
+}
+
+impl<G, C> AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    pub fn new(
+        grid: Arc<G>,
+        agent_radius: f64,
+        extrapolator: Arc<LineFollow>,
+        cost_calculator: Arc<C>,
+    ) -> Self {
+        Self {
+            grid,
+            agent_radius,
+            extrapolator,
+            cost_calculator,
+        }
+    }
+
+    /// Check whether the agent can sit at the center of a cell.
+    pub fn is_cell_free(&self, cell: &Cell) -> bool {
+        let p = self.grid.cell_center(cell);
+        return self
+            .grid
+            .is_square_occupied(p, 2.0 * self.agent_radius)
+            .is_none();
+    }
+
+    /// Check whether the agent can move in a straight line between two points.
+    pub fn is_line_clear(&self, p0: &Point, p1: &Point) -> bool {
+        return self
+            .grid
+            .is_sweep_occupied(*p0, *p1, 2.0 * self.agent_radius)
+            .is_none();
+    }
+
+    fn estimate_remaining_cost(&self, from: &Point, goal: &Cell) -> C::Cost {
+        return straight_line_cost(
+            &self.extrapolator,
+            self.cost_calculator.as_ref(),
+            from,
+            &self.grid.cell_center(goal),
+        );
+    }
+
+    /// Create a node for the given cell, connected to the grandparent if there
+    /// is a clear line to it, or else to the parent.
+    fn connect(
+        &self,
+        parent: &Arc<Node<C::Cost, Cell>>,
+        cell: Cell,
+        goal: &Cell,
+    ) -> Option<Arc<Node<C::Cost, Cell>>> {
+        let p = self.grid.cell_center(&cell);
+        let from = match parent.parent() {
+            Some(grandparent) if self.is_line_clear(&grandparent.state().position, &p) => {
+                grandparent
+            }
+            _ => {
+                if !self.is_line_clear(&parent.state().position, &p) {
+                    return None;
+                }
+                parent
+            }
+        };
+
+        let trajectory = self
+            .extrapolator
+            .make_trajectory(*from.state(), &p)
+            .ok()??;
+        let cost = self.cost_calculator.compute_cost(&trajectory);
+        let h = self.estimate_remaining_cost(&p, goal);
+        return Some(from.clone().moved_with(Some(cell), cost, h, Some(trajectory)));
+    }
+}
+
+impl<G, C> Expander for AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type Node = Node<C::Cost, Cell>;
+}
+
+impl<G, C> Closable for AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type ClosedSet = PartialKeyedClosedSet<Self::Node>;
+}
+
+/// The any-angle expander shares its error type with the other grid search
+/// expanders.
+pub type AnyAngleInitError = GridSearchInitError;
+
+/// The any-angle expander shares its solution type with the other grid search
+/// expanders.
+pub type AnyAngleSolution<Cost> = GridSearchSolution<Cost>;
+
+impl<G, C> InitTargeted<Cell, Cell> for AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type InitTargetedError = GridSearchInitError;
+    type InitialTargetedNodes<'a> = impl Iterator<Item=Result<Arc<Self::Node>, GridSearchInitError>> + 'a where G: 'a, C: 'a;
+
+    fn start<'a>(&'a self, start: &'a Cell, goal: &'a Cell) -> Self::InitialTargetedNodes<'a> {
+        [(start, goal)].into_iter().map(move |(start, goal)| {
+            if !self.is_cell_free(start) {
+                return Err(GridSearchInitError::OccupiedStart(*start));
+            }
+
+            if !self.is_cell_free(goal) {
+                return Err(GridSearchInitError::OccupiedGoal(*goal));
+            }
+
+            let p = self.grid.cell_center(start);
+            let state = r2::timed_position::Waypoint {
+                time: TimePoint::zero(),
+                position: p,
+            };
+            let h = self.estimate_remaining_cost(&p, goal);
+
+            Ok(Arc::new(Node::<C::Cost, Cell>::start_from(
+                state,
+                Some(*start),
+                C::Cost::zero(),
+                h,
+                None,
+            )))
+        })
+    }
+}
+
+impl<G, C> Targeted<Cell> for AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type TargetedError = NoError;
+    type TargetedExpansion<'a> = impl Iterator<Item=Result<Arc<Self::Node>, NoError>> + 'a where G: 'a, C: 'a;
+
+    fn expand<'a>(
+        &'a self,
+        parent: &'a Arc<Self::Node>,
+        goal: &'a Cell,
+    ) -> Self::TargetedExpansion<'a> {
+        [parent.partial_key()]
+            .into_iter()
+            .flatten()
+            .flat_map(move |cell| {
+                NEIGHBORS
+                    .iter()
+                    .map(move |(dx, dy)| cell.shifted(*dx, *dy))
+                    .filter(move |n| self.is_cell_free(n))
+                    .filter_map(move |n| self.connect(parent, n, goal))
+            })
+            .map(Ok)
+    }
+}
+
+impl<G, C> Solvable for AnyAngleExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type Solution = GridSearchSolution<C::Cost>;
+    type SolveError = NoError;
+
+    fn make_solution(
+        &self,
+        solution_node: &Arc<Self::Node>,
+    ) -> Result<Self::Solution, Self::SolveError> {
+        let motion = Trajectory::from_iter(ReconstructMotion::new(solution_node.clone())).ok();
+        let cost = motion
+            .as_ref()
+            .map(|t| self.cost_calculator.compute_cost(t))
+            .unwrap_or(C::Cost::zero());
+        Ok(GridSearchSolution { cost, motion })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star, algorithm::Status, motion::trajectory::DurationCostCalculator,
+        occupancy::SparseGrid, planner::make_planner,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_any_angle_path_around_wall() {
+        // A vertical wall at x = 3 that the agent must go around
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for y in -3..=2 {
+            changes.insert(Cell::new(3, y), true);
+        }
+        grid.change_cells(&changes);
+        let grid = Arc::new(grid);
+
+        let expander = AnyAngleExpander::new(
+            grid.clone(),
+            0.25,
+            Arc::new(LineFollow::new(1.0).unwrap()),
+            Arc::new(DurationCostCalculator),
+        );
+
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner.plan(&Cell::new(0, 0), Cell::new(6, 0)).unwrap();
+
+        match progress.solve().unwrap() {
+            Status::Solved(solution) => {
+                let motion = solution.motion().as_ref().unwrap();
+                let goal = Cell::new(6, 0).to_center_point(1.0);
+                assert!((motion.finish().position - goal).norm() < 1e-8);
+
+                // An 8-connected grid path would need many more waypoints.
+                assert!(motion.len() < 6);
+                for pair in motion.windows(2) {
+                    assert!(grid
+                        .is_sweep_occupied(pair[0].position, pair[1].position, 0.5)
+                        .is_none());
+                }
+            }
+            Status::Impossible | Status::Incomplete => {
+                panic!("The search failed to find a solution");
+            }
+        }
+    }
+
+    #[test]
+    fn test_occupied_start() {
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        changes.insert(Cell::new(0, 0), true);
+        grid.change_cells(&changes);
+
+        let expander = AnyAngleExpander::new(
+            Arc::new(grid),
+            0.25,
+            Arc::new(LineFollow::new(1.0).unwrap()),
+            Arc::new(DurationCostCalculator),
+        );
+
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        assert!(planner.plan(&Cell::new(0, 0), Cell::new(4, 0)).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    error::NoError,
+    expander::{AimlessConstraint, TargetedConstraint},
+    motion::{self, se2, TimePoint, Trajectory},
+    node::Agent,
+    occupancy::{Cell, Grid, Point, Vector},
+};
+use std::sync::Arc;
+
+/// The shape of an agent as seen from above.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Footprint {
+    /// A circle centered on the position of the agent.
+    Circle { radius: f64 },
+
+    /// A rectangle in the frame of the agent, where the agent faces in the
+    /// positive x direction. The front and back values are the distances from
+    /// the position of the agent to the front and back edges of the rectangle.
+    Rectangle {
+        front: f64,
+        back: f64,
+        half_width: f64,
+    },
+}
+
+impl Footprint {
+    /// Get the radius of the smallest circle centered on the agent's position
+    /// which contains the whole footprint.
+    pub fn circumradius(&self) -> f64 {
+        match self {
+            Footprint::Circle { radius } => *radius,
+            Footprint::Rectangle {
+                front,
+                back,
+                half_width,
+            } => front.abs().max(back.abs()).hypot(*half_width),
+        }
+    }
+}
+
+/// Information about what blocked a motion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OccupancyBlocker {
+    /// The occupied cell that the motion would have collided with.
+    pub cell: Cell,
+
+    /// The index of the waypoint that the blocked segment of the trajectory
+    /// was approaching.
+    pub waypoint_index: usize,
+
+    /// The time at which the blocked segment of the trajectory begins.
+    pub time: TimePoint,
+}
+
+/// A constraint that rejects any node whose motion from its parent would sweep
+/// its footprint through an occupied cell of a grid.
+///
+/// This provides a final validation of the actual motions being produced by an
+/// expander, including rotations in place, which the connectivity of the
+/// visibility graph does not account for.
+pub struct OccupancyConstraint<G: Grid> {
+    pub grid: Arc<G>,
+    pub footprint: Footprint,
+}
+
+impl<G: Grid> OccupancyConstraint<G> {
+    pub fn new(grid: Arc<G>, footprint: Footprint) -> Self {
+        Self { grid, footprint }
+    }
+
+    /// Check whether a trajectory is blocked by the grid. If it is, get back
+    /// information about where the trajectory was blocked. This is used by the
+    /// constraint implementation, but it can also be used to debug why a motion
+    /// was rejected.
+    pub fn find_blocker(&self, trajectory: &se2::LinearTrajectory) -> Option<OccupancyBlocker> {
+        for index in 1..trajectory.len() {
+            let wp0 = &trajectory[index - 1].0;
+            let wp1 = &trajectory[index].0;
+            if let Some(cell) = self.check_segment(&wp0.position, &wp1.position) {
+                return Some(OccupancyBlocker {
+                    cell,
+                    waypoint_index: index,
+                    time: wp0.time,
+                });
+            }
+        }
+
+        return None;
+    }
+
+    fn check_segment(&self, pose0: &se2::Position, pose1: &se2::Position) -> Option<Cell> {
+        let p0 = Point::from(pose0.translation.vector);
+        let p1 = Point::from(pose1.translation.vector);
+        let delta_yaw = (pose1.rotation / pose0.rotation).angle().abs();
+
+        match self.footprint {
+            Footprint::Circle { radius } => self.check_circle_sweep(p0, p1, radius),
+            Footprint::Rectangle {
+                front,
+                back,
+                half_width,
+            } => {
+                if delta_yaw > motion::DEFAULT_ROTATIONAL_THRESHOLD {
+                    // While rotating, the footprint may sweep through any part
+                    // of its circumscribed circle.
+                    return self.check_circle_sweep(p0, p1, self.footprint.circumradius());
+                }
+
+                let heading = pose0.rotation * Vector::x();
+                let lateral = Vector::new(-heading.y, heading.x);
+                let corners = [
+                    heading * front + lateral * half_width,
+                    heading * front - lateral * half_width,
+                    heading * -back + lateral * half_width,
+                    heading * -back - lateral * half_width,
+                ];
+
+                let dist = (p1 - p0).norm();
+                let (u, n) = if dist < 1e-8 {
+                    (heading, lateral)
+                } else {
+                    let u = (p1 - p0) / dist;
+                    (u, Vector::new(-u.y, u.x))
+                };
+
+                // Project the corners of the footprint onto the direction of
+                // travel to find a rectangle that contains the whole sweep.
+                let mut min_u = f64::INFINITY;
+                let mut max_u = -f64::INFINITY;
+                let mut max_n: f64 = 0.0;
+                for corner in corners {
+                    let along = corner.dot(&u);
+                    min_u = min_u.min(along);
+                    max_u = max_u.max(along);
+                    max_n = max_n.max(corner.dot(&n).abs());
+                }
+
+                self.grid
+                    .is_sweep_occupied(p0 + u * min_u, p1 + u * max_u, 2.0 * max_n)
+            }
+        }
+    }
+
+    fn check_circle_sweep(&self, p0: Point, p1: Point, radius: f64) -> Option<Cell> {
+        let width = 2.0 * radius;
+        self.grid
+            .is_square_occupied(p0, width)
+            .or_else(|| self.grid.is_sweep_occupied(p0, p1, width))
+            .or_else(|| self.grid.is_square_occupied(p1, width))
+    }
+}
+
+impl<G, N> AimlessConstraint<N> for OccupancyConstraint<G>
+where
+    G: Grid,
+    N: Agent<se2::timed_position::Waypoint, Trajectory<se2::timed_position::Waypoint>>,
+{
+    type ConstraintError = NoError;
+    fn constrain(&self, node: Arc<N>) -> Result<Option<Arc<N>>, Self::ConstraintError> {
+        if let Some(trajectory) = node.action() {
+            if self.find_blocker(trajectory).is_some() {
+                return Ok(None);
+            }
+        }
+
+        return Ok(Some(node));
+    }
+}
+
+impl<G, N, Goal> TargetedConstraint<N, Goal> for OccupancyConstraint<G>
+where
+    G: Grid,
+    N: Agent<se2::timed_position::Waypoint, Trajectory<se2::timed_position::Waypoint>>,
+{
+    type ConstraintError = NoError;
+    fn constrain(&self, node: Arc<N>, _: &Goal) -> Result<Option<Arc<N>>, Self::ConstraintError> {
+        AimlessConstraint::constrain(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::occupancy::SparseGrid;
+    use se2::timed_position::Waypoint as WaypointSE2;
+    use std::collections::HashMap;
+
+    fn make_wall_grid() -> SparseGrid {
+        // A vertical wall of cells at x = 5
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for y in -5..=5 {
+            changes.insert(Cell::new(5, y), true);
+        }
+        grid.change_cells(&changes);
+        grid
+    }
+
+    #[test]
+    fn test_occupancy_constraint_finds_blocker() {
+        let constraint = OccupancyConstraint::new(
+            Arc::new(make_wall_grid()),
+            Footprint::Circle { radius: 0.4 },
+        );
+
+        let blocked = se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(TimePoint::from_secs_f64(0.0), 0.5, 0.5, 0.0),
+            WaypointSE2::new(TimePoint::from_secs_f64(1.0), 2.5, 0.5, 0.0),
+            WaypointSE2::new(TimePoint::from_secs_f64(2.0), 8.5, 0.5, 0.0),
+        ])
+        .unwrap();
+
+        let blocker = constraint.find_blocker(&blocked).unwrap();
+        assert_eq!(blocker.cell.x, 5);
+        assert_eq!(blocker.waypoint_index, 2);
+
+        let clear = se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(TimePoint::from_secs_f64(0.0), 0.5, 0.5, 0.0),
+            WaypointSE2::new(TimePoint::from_secs_f64(1.0), 0.5, 4.5, 0.0),
+        ])
+        .unwrap();
+        assert!(constraint.find_blocker(&clear).is_none());
+    }
+
+    #[test]
+    fn test_rectangle_rotation_is_checked() {
+        let footprint = Footprint::Rectangle {
+            front: 1.5,
+            back: 0.3,
+            half_width: 0.3,
+        };
+        let constraint = OccupancyConstraint::new(Arc::new(make_wall_grid()), footprint);
+
+        // Facing away from the wall, the footprint is clear
+        let hold = se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(TimePoint::from_secs_f64(0.0), 4.5, 0.5, 180f64.to_radians()),
+            WaypointSE2::new(TimePoint::from_secs_f64(1.0), 4.5, 0.5, 180f64.to_radians()),
+        ])
+        .unwrap();
+        assert!(constraint.find_blocker(&hold).is_none());
+
+        // Turning around in place would swing the front into the wall
+        let rotate = se2::LinearTrajectory::from_iter([
+            WaypointSE2::new(TimePoint::from_secs_f64(0.0), 4.5, 0.5, 180f64.to_radians()),
+            WaypointSE2::new(TimePoint::from_secs_f64(1.0), 4.5, 0.5, 0.0),
+        ])
+        .unwrap();
+        assert!(constraint.find_blocker(&rotate).is_some());
+    }
+}
@@ -0,0 +1,520 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::util::{update_corner_status, LineSegment, SearchF64};
+use super::{Cell, ChangedCorners, ConfirmedChanges, CornerStatus, Grid, Point, Vector};
+use std::collections::{hash_map, HashMap, HashSet};
+
+/// A fixed-size bitset of cell occupancy, stored row by row.
+#[derive(Clone)]
+struct Bitmap {
+    lower: Cell,
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
+}
+
+impl Bitmap {
+    fn new(lower: Cell, width: usize, height: usize) -> Self {
+        let words_per_row = width.div_ceil(64);
+        Self {
+            lower,
+            width,
+            height,
+            words_per_row,
+            bits: vec![0; words_per_row * height],
+        }
+    }
+
+    /// Get the (row, column) of a cell if it is inside the bounds.
+    fn index(&self, cell: &Cell) -> Option<(usize, usize)> {
+        let col = cell.x - self.lower.x;
+        let row = cell.y - self.lower.y;
+        if col < 0 || row < 0 || col as usize >= self.width || row as usize >= self.height {
+            return None;
+        }
+
+        return Some((row as usize, col as usize));
+    }
+
+    fn get(&self, cell: &Cell) -> bool {
+        if let Some((row, col)) = self.index(cell) {
+            let word = self.bits[row * self.words_per_row + col / 64];
+            return (word >> (col % 64)) & 1 == 1;
+        }
+
+        return false;
+    }
+
+    /// Set the value of a cell. Returns true if the value changed. Cells that
+    /// are outside of the bounds cannot be changed.
+    fn set(&mut self, cell: &Cell, value: bool) -> bool {
+        if let Some((row, col)) = self.index(cell) {
+            let word = &mut self.bits[row * self.words_per_row + col / 64];
+            let mask = 1u64 << (col % 64);
+            let previous = *word & mask != 0;
+            if value {
+                *word |= mask;
+            } else {
+                *word &= !mask;
+            }
+            return previous != value;
+        }
+
+        return false;
+    }
+
+    /// Find the first occupied cell in row y whose x index is in the range
+    /// [x_min, x_max).
+    fn first_in_row(&self, y: i64, x_min: i64, x_max: i64) -> Option<Cell> {
+        let row = y - self.lower.y;
+        if row < 0 || row as usize >= self.height {
+            return None;
+        }
+
+        let c0 = (x_min - self.lower.x).max(0) as usize;
+        let c1 = (x_max - self.lower.x).min(self.width as i64);
+        if c1 <= c0 as i64 {
+            return None;
+        }
+        let c1 = c1 as usize;
+
+        let base = row as usize * self.words_per_row;
+        let mut c = c0;
+        while c < c1 {
+            let offset = c % 64;
+            let span = (c1 - c).min(64 - offset);
+            let mut word = self.bits[base + c / 64] >> offset;
+            if span < 64 {
+                word &= (1u64 << span) - 1;
+            }
+
+            if word != 0 {
+                let col = c + word.trailing_zeros() as usize;
+                return Some(Cell::new(self.lower.x + col as i64, y));
+            }
+
+            c += span;
+        }
+
+        return None;
+    }
+}
+
+/// A grid whose occupancy is stored in a dense bitset with fixed bounds. This
+/// uses far less memory than SparseGrid for large maps where a significant
+/// portion of the cells are occupied, and occupancy queries are answered by
+/// scanning rows of the bitset.
+///
+/// Every cell outside of the bounds is considered to be unoccupied, and
+/// requests to occupy cells outside of the bounds will be ignored.
+#[derive(Clone)]
+pub struct DenseGrid {
+    cell_size: f64,
+    origin: Point,
+    bitmap: Bitmap,
+    corners: HashMap<Cell, CornerStatus>,
+}
+
+impl DenseGrid {
+    /// Create a new empty grid with `width` columns and `height` rows of cells,
+    /// where `lower` is the cell in the bottom left corner of the bounds.
+    pub fn new(cell_size: f64, lower: Cell, width: usize, height: usize) -> Self {
+        Self {
+            cell_size,
+            origin: Point::origin(),
+            bitmap: Bitmap::new(lower, width, height),
+            corners: HashMap::default(),
+        }
+    }
+
+    /// Set the location of the bottom left corner of cell (0, 0). Cells are
+    /// always laid out relative to this origin, so changing it moves the
+    /// whole occupancy without modifying any cells.
+    pub fn set_origin(&mut self, origin: Point) {
+        self.origin = origin;
+    }
+
+    /// The cell in the bottom left corner of the bounds.
+    pub fn lower(&self) -> Cell {
+        return self.bitmap.lower;
+    }
+
+    /// The cell in the top right corner of the bounds.
+    pub fn upper(&self) -> Cell {
+        return self
+            .bitmap
+            .lower
+            .shifted(self.bitmap.width as i64 - 1, self.bitmap.height as i64 - 1);
+    }
+
+    /// The number of columns of cells in the grid.
+    pub fn width(&self) -> usize {
+        return self.bitmap.width;
+    }
+
+    /// The number of rows of cells in the grid.
+    pub fn height(&self) -> usize {
+        return self.bitmap.height;
+    }
+
+    /// Check whether a cell is inside the bounds of the grid.
+    pub fn contains(&self, cell: &Cell) -> bool {
+        return self.bitmap.index(cell).is_some();
+    }
+
+    fn update_corner_status(&mut self, delta: &mut ChangedCorners, cell: &Cell) {
+        let bitmap = &self.bitmap;
+        update_corner_status(&mut self.corners, delta, cell, |c| bitmap.get(c));
+    }
+}
+
+impl std::fmt::Debug for DenseGrid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DenseGrid")
+            .field("cell_size", &self.cell_size)
+            .field("origin", &self.origin)
+            .field("lower", &self.bitmap.lower)
+            .field("width", &self.bitmap.width)
+            .field("height", &self.bitmap.height)
+            .finish()
+    }
+}
+
+/// Iterates over the occupied cells of a DenseGrid, skipping empty words of
+/// the bitset.
+pub struct DenseOccupiedCells<'a> {
+    bitmap: &'a Bitmap,
+    current: usize,
+    word: u64,
+}
+
+impl<'a> Iterator for DenseOccupiedCells<'a> {
+    type Item = Cell;
+
+    fn next(&mut self) -> Option<Cell> {
+        loop {
+            if self.word != 0 {
+                let bit = self.word.trailing_zeros() as usize;
+                self.word &= self.word - 1;
+                let row = self.current / self.bitmap.words_per_row;
+                let col = (self.current % self.bitmap.words_per_row) * 64 + bit;
+                return Some(self.bitmap.lower.shifted(col as i64, row as i64));
+            }
+
+            self.current += 1;
+            if self.current >= self.bitmap.bits.len() {
+                return None;
+            }
+            self.word = self.bitmap.bits[self.current];
+        }
+    }
+}
+
+impl Grid for DenseGrid {
+    type OccupiedIterator<'a> = DenseOccupiedCells<'a>;
+    type CornerIterator<'a> = hash_map::Iter<'a, Cell, CornerStatus>;
+
+    fn change_cells(
+        &mut self,
+        changes: &HashMap<Cell, bool>,
+    ) -> (ConfirmedChanges, ChangedCorners) {
+        let mut confirmed_changes = Vec::with_capacity(changes.len());
+
+        for (cell, value) in changes {
+            if self.bitmap.set(cell, *value) {
+                confirmed_changes.push((*cell, *value));
+            }
+        }
+
+        let mut delta = ChangedCorners::default();
+        let mut checked = HashSet::new();
+        for (check, _) in &confirmed_changes {
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    let cell = check.shifted(i, j);
+                    if checked.insert(cell) {
+                        self.update_corner_status(&mut delta, &cell);
+                    }
+                }
+            }
+        }
+
+        return (confirmed_changes, delta);
+    }
+
+    fn cell_size(&self) -> f64 {
+        return self.cell_size;
+    }
+
+    fn origin(&self) -> Point {
+        return self.origin;
+    }
+
+    fn is_occupied(&self, cell: &Cell) -> bool {
+        return self.bitmap.get(cell);
+    }
+
+    fn occupied_cells<'b>(&'b self) -> Self::OccupiedIterator<'b> {
+        return DenseOccupiedCells {
+            bitmap: &self.bitmap,
+            current: 0,
+            word: self.bitmap.bits.first().copied().unwrap_or(0),
+        };
+    }
+
+    fn corners<'b>(&'b self) -> Self::CornerIterator<'b> {
+        return self.corners.iter();
+    }
+
+    fn is_point_occupied(&self, p: Point) -> Option<Cell> {
+        let cell = self.cell_of(&p);
+        if self.bitmap.get(&cell) {
+            return Some(cell);
+        }
+
+        return None;
+    }
+
+    fn is_square_occupied(&self, p: Point, width: f64) -> Option<Cell> {
+        let p = p - self.origin.coords;
+        let d = width / 2.0;
+        let delta = Vector::new(d, d);
+        let min_p = p - delta;
+        let max_p = p + delta;
+        let min_cell = Cell::from_point(min_p, self.cell_size);
+        let max_cell = Cell {
+            x: (max_p.x / self.cell_size).ceil() as i64,
+            y: (max_p.y / self.cell_size).ceil() as i64,
+        };
+
+        for y in min_cell.y..max_cell.y {
+            if let Some(cell) = self.bitmap.first_in_row(y, min_cell.x, max_cell.x) {
+                return Some(cell);
+            }
+        }
+
+        return None;
+    }
+
+    fn is_sweep_occupied(&self, p0: Point, p1: Point, width: f64) -> Option<Cell> {
+        let d = width / 2.0;
+        let dist = (p1 - p0).norm();
+        if dist < 1e-8 {
+            return self.is_point_occupied(p0);
+        }
+
+        let (p0, p1) = (p0 - self.origin.coords, p1 - self.origin.coords);
+
+        let v = (p1 - p0) / dist;
+        let n = Vector::new(-v.y, v.x);
+
+        let points = [p0 + n * d, p0 - n * d, p1 + n * d, p1 - n * d];
+
+        let lines = [
+            LineSegment::new(points[0], points[1]),
+            LineSegment::new(points[0], points[2]),
+            LineSegment::new(points[1], points[3]),
+            LineSegment::new(points[2], points[3]),
+        ];
+
+        let cell_y_min = (points
+            .iter()
+            .min_by(|p_l, p_r| p_l.y.partial_cmp(&p_r.y).unwrap())
+            .unwrap()
+            .y
+            / self.cell_size)
+            .floor() as i64;
+
+        let cell_y_max = (points
+            .iter()
+            .max_by(|p_l, p_r| p_l.y.partial_cmp(&p_r.y).unwrap())
+            .unwrap()
+            .y
+            / self.cell_size)
+            .ceil() as i64;
+
+        for cell_y in cell_y_min..cell_y_max {
+            let y_low = cell_y as f64 * self.cell_size;
+            let y_high = (cell_y + 1) as f64 * self.cell_size;
+            let mut x_low = SearchF64::new();
+            let mut x_high = SearchF64::new();
+
+            for p in points {
+                if y_low <= p.y && p.y <= y_high {
+                    x_low.check_min(p.x);
+                    x_high.check_max(p.x);
+                }
+            }
+
+            for y in [y_low, y_high] {
+                for line in &lines {
+                    for x in line.horizontal_intersect(y) {
+                        x_low.check_min(x);
+                        x_high.check_max(x);
+                    }
+                }
+            }
+
+            if let (Some(x_low), Some(x_high)) = (x_low.value, x_high.value) {
+                let cell_x_min = (x_low / self.cell_size).floor() as i64;
+                let cell_x_max = (x_high / self.cell_size).ceil() as i64;
+                if let Some(cell) = self.bitmap.first_in_row(cell_y, cell_x_min, cell_x_max) {
+                    return Some(cell);
+                }
+            }
+        }
+
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::occupancy::{
+        graph::{NeighborhoodGraph, VisibilityGraph},
+        SparseGrid, Visibility,
+    };
+    use approx::assert_relative_eq;
+    use std::sync::Arc;
+
+    fn make_grids() -> (SparseGrid, DenseGrid) {
+        let mut changes = HashMap::new();
+        for x in -10i64..90 {
+            for y in -5i64..20 {
+                // A scattered pattern that spans several words of each row
+                if (x * 7 + y * 13).rem_euclid(5) < 2 {
+                    changes.insert(Cell::new(x, y), true);
+                }
+            }
+        }
+
+        let mut sparse = SparseGrid::new(0.5);
+        sparse.change_cells(&changes);
+        let mut dense = DenseGrid::new(0.5, Cell::new(-10, -5), 100, 25);
+        dense.change_cells(&changes);
+        (sparse, dense)
+    }
+
+    #[test]
+    fn test_dense_grid_matches_sparse_grid() {
+        let (sparse, dense) = make_grids();
+
+        let sparse_cells: HashSet<Cell> = sparse.occupied_cells().collect();
+        let dense_cells: HashSet<Cell> = dense.occupied_cells().collect();
+        assert_eq!(sparse_cells, dense_cells);
+
+        let sparse_corners: HashMap<Cell, CornerStatus> =
+            sparse.corners().map(|(c, s)| (*c, *s)).collect();
+        let dense_corners: HashMap<Cell, CornerStatus> =
+            dense.corners().map(|(c, s)| (*c, *s)).collect();
+        assert_eq!(sparse_corners, dense_corners);
+
+        // The offsets are chosen so that the queries do not line up exactly
+        // with the edges of cells, where the two implementations are allowed
+        // to disagree about whether touching counts as occupied.
+        for i in 0..200 {
+            let x = -5.9877 + (i as f64 * 0.3719) % 50.0;
+            let y = -2.9913 + (i as f64 * 0.6131) % 13.0;
+            let p0 = Point::new(x, y);
+            let p1 = Point::new(
+                x + ((i % 7) as f64 - 3.0) * 1.0131,
+                y + ((i % 5) as f64 - 2.0) * 0.9871,
+            );
+            for width in [0.1037, 0.4519, 1.3071] {
+                assert_eq!(
+                    sparse.is_square_occupied(p0, width).is_some(),
+                    dense.is_square_occupied(p0, width).is_some(),
+                );
+                assert_eq!(
+                    sparse.is_sweep_occupied(p0, p1, width).is_some(),
+                    dense.is_sweep_occupied(p0, p1, width).is_some(),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dense_grid_bounds() {
+        let mut grid = DenseGrid::new(1.0, Cell::new(0, 0), 70, 3);
+        let mut changes = HashMap::new();
+        changes.insert(Cell::new(65, 1), true);
+        changes.insert(Cell::new(70, 1), true);
+        changes.insert(Cell::new(-1, 0), true);
+        let (confirmed, _) = grid.change_cells(&changes);
+        assert_eq!(confirmed, vec![(Cell::new(65, 1), true)]);
+        assert!(grid.is_occupied(&Cell::new(65, 1)));
+        assert!(!grid.is_occupied(&Cell::new(70, 1)));
+        assert_eq!(grid.upper(), Cell::new(69, 2));
+        assert_eq!(
+            grid.occupied_cells().collect::<Vec<_>>(),
+            [Cell::new(65, 1)]
+        );
+
+        assert_eq!(
+            grid.is_sweep_occupied(Point::new(0.5, 1.5), Point::new(69.5, 1.5), 0.5),
+            Some(Cell::new(65, 1))
+        );
+        assert!(grid
+            .is_sweep_occupied(Point::new(0.5, 0.5), Point::new(69.5, 0.5), 0.5)
+            .is_none());
+
+        // The grid can be used anywhere that a SparseGrid is accepted
+        let visibility = Arc::new(Visibility::new(grid, 0.25));
+        assert!(visibility.grid().is_occupied(&Cell::new(65, 1)));
+        assert!(visibility.iter_points().count() > 0);
+        VisibilityGraph::new(visibility.clone(), [Cell::new(1, 1)].into_iter());
+        NeighborhoodGraph::new(visibility, [Cell::new(1, 1)].into_iter());
+    }
+
+    fn check_shifted_grid<G: Grid>(grid: &G, p: Point) {
+        assert_eq!(grid.cell_of(&p), Cell::new(3, 2));
+        assert_eq!(grid.is_point_occupied(p), Some(Cell::new(3, 2)));
+        assert!(grid.is_point_occupied(Point::new(1.75, 1.25)).is_none());
+        assert_eq!(grid.is_square_occupied(p, 0.2), Some(Cell::new(3, 2)));
+        assert_eq!(
+            grid.is_sweep_occupied(p - Vector::new(2.0, 0.0), p + Vector::new(2.0, 0.0), 0.1),
+            Some(Cell::new(3, 2))
+        );
+        assert!(grid
+            .is_sweep_occupied(p - Vector::new(2.0, 1.0), p + Vector::new(2.0, -1.0), 0.1)
+            .is_none());
+    }
+
+    #[test]
+    fn test_grid_origin() {
+        let origin = Point::new(-10.3, 4.7);
+        let mut sparse = SparseGrid::new(0.5);
+        sparse.set_origin(origin);
+        let mut dense = DenseGrid::new(0.5, Cell::new(0, 0), 10, 10);
+        dense.set_origin(origin);
+
+        let mut changes = HashMap::new();
+        changes.insert(Cell::new(3, 2), true);
+        sparse.change_cells(&changes);
+        dense.change_cells(&changes);
+
+        let p = sparse.cell_center(&Cell::new(3, 2));
+        assert_relative_eq!(p.x, -10.3 + 1.75);
+        assert_relative_eq!(p.y, 4.7 + 1.25);
+        assert_relative_eq!((dense.cell_center(&Cell::new(3, 2)) - p).norm(), 0.0);
+        check_shifted_grid(&sparse, p);
+        check_shifted_grid(&dense, p);
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    inflation::{squared_distance_2d, Clearance, UNREACHED},
+    Cell, Grid, Point, Vector,
+};
+use std::collections::HashMap;
+
+/// A signed Euclidean distance field over an occupancy grid. Cells that are
+/// free have a positive distance to the nearest occupied cell, and cells that
+/// are occupied have a negative distance to the nearest free cell, so the
+/// field crosses zero at the boundaries of obstacles. Distances saturate at a
+/// maximum magnitude, so only the region near the boundaries needs to be
+/// stored.
+///
+/// The distances are computed with the same exact transform as
+/// [`DistanceTransform`](super::inflation::DistanceTransform). Pass the
+/// confirmed changes that come out of [`Grid::change_cells`] into
+/// [`DistanceField::update`] and the transform will only be recomputed over
+/// the bounding box of the changes, expanded by the maximum distance. Changes
+/// that are far apart from each other are best passed in separate updates.
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    cell_size: f64,
+    origin: Point,
+    max_distance: f64,
+    max_squared: i64,
+    /// How many cells away a change can affect the field
+    reach: i64,
+    /// Squared distances (in cells) from free cells to the nearest occupied
+    /// cell. Free cells that are too far from every occupied cell are left out.
+    outer: HashMap<Cell, i64>,
+    /// Squared distances (in cells) from every occupied cell to the nearest
+    /// free cell, saturated at the maximum distance.
+    inner: HashMap<Cell, i64>,
+}
+
+impl DistanceField {
+    /// Compute the distance field of a grid. Distances will be reported with a
+    /// magnitude no greater than `max_distance`.
+    pub fn new<G: Grid>(grid: &G, max_distance: f64) -> Self {
+        // Cell distances are measured between cell centers, which is half a
+        // cell farther than the boundary that the field reports on.
+        let max_cells = max_distance / grid.cell_size() + 0.5;
+        let mut field = Self {
+            cell_size: grid.cell_size(),
+            origin: grid.origin(),
+            max_distance,
+            max_squared: (max_cells * max_cells).ceil() as i64,
+            reach: max_cells.ceil() as i64,
+            outer: HashMap::new(),
+            inner: HashMap::new(),
+        };
+
+        let changes: Vec<(Cell, bool)> = grid
+            .occupied_cells()
+            .into_iter()
+            .map(|cell| (cell, true))
+            .collect();
+        field.update(grid, &changes);
+        return field;
+    }
+
+    /// Update the field after the occupancy of the grid has changed. The grid
+    /// should already contain the changes, which are usually the confirmed
+    /// changes returned by [`Grid::change_cells`].
+    pub fn update<G: Grid>(&mut self, grid: &G, changes: &[(Cell, bool)]) {
+        let mut bounds: Option<(Cell, Cell)> = None;
+        for (cell, _) in changes {
+            bounds = Some(match bounds {
+                Some((lower, upper)) => (
+                    Cell::new(lower.x.min(cell.x), lower.y.min(cell.y)),
+                    Cell::new(upper.x.max(cell.x), upper.y.max(cell.y)),
+                ),
+                None => (*cell, *cell),
+            });
+        }
+
+        let (lower, upper) = match bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        // Only cells within reach of a change can have a different distance.
+        // Their nearest sources are within reach of them, so the transform is
+        // computed over a window that is expanded by the reach twice.
+        let (lower, upper) = (
+            lower.shifted(-self.reach, -self.reach),
+            upper.shifted(self.reach, self.reach),
+        );
+        let window_lower = lower.shifted(-self.reach, -self.reach);
+        let width = (upper.x - lower.x + 1 + 2 * self.reach) as usize;
+        let height = (upper.y - lower.y + 1 + 2 * self.reach) as usize;
+
+        let mut outer = vec![UNREACHED; width * height];
+        let mut inner = vec![UNREACHED; width * height];
+        let mut occupied = vec![false; width * height];
+        for row in 0..height {
+            for col in 0..width {
+                let i = row * width + col;
+                occupied[i] = grid.is_occupied(&window_lower.shifted(col as i64, row as i64));
+                if occupied[i] {
+                    outer[i] = 0.0;
+                } else {
+                    inner[i] = 0.0;
+                }
+            }
+        }
+
+        squared_distance_2d(&mut outer, width, height);
+        squared_distance_2d(&mut inner, width, height);
+
+        let margin = self.reach as usize;
+        for row in margin..height - margin {
+            for col in margin..width - margin {
+                let i = row * width + col;
+                let cell = window_lower.shifted(col as i64, row as i64);
+                if occupied[i] {
+                    self.outer.remove(&cell);
+                    let squared = (inner[i] as i64).min(self.max_squared);
+                    self.inner.insert(cell, squared);
+                } else {
+                    self.inner.remove(&cell);
+                    let squared = outer[i] as i64;
+                    if squared <= self.max_squared {
+                        self.outer.insert(cell, squared);
+                    } else {
+                        self.outer.remove(&cell);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The greatest magnitude that the field will report.
+    pub fn max_distance(&self) -> f64 {
+        return self.max_distance;
+    }
+
+    /// Get the signed distance from the center of a cell to the boundary of
+    /// the nearest obstacle.
+    pub fn cell_distance(&self, cell: &Cell) -> f64 {
+        let half_cell = self.cell_size / 2.0;
+        let to_boundary = |squared: Option<&i64>| {
+            squared
+                .map(|sq| (*sq as f64).sqrt() * self.cell_size - half_cell)
+                .unwrap_or(self.max_distance)
+                .min(self.max_distance)
+        };
+
+        if let Some(squared) = self.inner.get(cell) {
+            return -to_boundary(Some(squared));
+        }
+
+        return to_boundary(self.outer.get(cell));
+    }
+
+    /// Get the signed distance from a point to the boundary of the nearest
+    /// obstacle, bilinearly interpolated between the centers of the cells
+    /// around the point.
+    pub fn signed_distance(&self, p: &Point) -> f64 {
+        let local = (*p - self.origin.coords) / self.cell_size - Vector::new(0.5, 0.5);
+        let (x, y) = (local.x.floor(), local.y.floor());
+        let (tx, ty) = (local.x - x, local.y - y);
+        let cell = Cell::new(x as i64, y as i64);
+
+        let d00 = self.cell_distance(&cell);
+        let d10 = self.cell_distance(&cell.shifted(1, 0));
+        let d01 = self.cell_distance(&cell.shifted(0, 1));
+        let d11 = self.cell_distance(&cell.shifted(1, 1));
+        return (1.0 - ty) * ((1.0 - tx) * d00 + tx * d10) + ty * ((1.0 - tx) * d01 + tx * d11);
+    }
+
+    /// Get the radius of the largest circle centered at `p` that is certain
+    /// not to overlap any occupied cell. Unlike [`signed_distance`], this
+    /// never overestimates, so it can be used to quickly rule out collisions
+    /// before doing an exact check. A square with a width of `w` fits inside
+    /// of a circle with a radius of `w/√2`.
+    ///
+    /// [`signed_distance`]: Self::signed_distance
+    pub fn max_radius_at(&self, p: &Point) -> f64 {
+        let local = *p - self.origin.coords;
+        let cell = Cell::from_point(local, self.cell_size);
+        if self.inner.contains_key(&cell) {
+            return 0.0;
+        }
+
+        // Free cells that are left out of the field are farther than the
+        // maximum from every occupied cell.
+        let squared = self.outer.get(&cell).copied().unwrap_or(self.max_squared);
+        let center_distance = (squared as f64).sqrt() * self.cell_size;
+
+        // The point may be anywhere inside its cell, and the nearest point of
+        // an occupied cell may be at one of its corners.
+        let offset = (local - cell.to_center_point(self.cell_size)).norm();
+        let corner = self.cell_size * std::f64::consts::FRAC_1_SQRT_2;
+        return (center_distance - offset - corner).clamp(0.0, self.max_distance);
+    }
+
+    /// Check whether a circle centered at `p` is certainly free of any
+    /// occupied cells. A false result means the circle might be occupied.
+    pub fn is_circle_clear(&self, p: &Point, radius: f64) -> bool {
+        return self.max_radius_at(p) >= radius;
+    }
+}
+
+impl Clearance for DistanceField {
+    fn clearance(&self, p: &Point) -> f64 {
+        return self.signed_distance(p).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::occupancy::SparseGrid;
+    use approx::assert_relative_eq;
+
+    /// Compute the expected signed distance of a cell by brute force.
+    fn brute_force_distance(grid: &SparseGrid, cell: &Cell, max_distance: f64) -> f64 {
+        let occupied = grid.is_occupied(cell);
+        let range = (max_distance / grid.cell_size()).ceil() as i64 + 2;
+        let mut nearest = f64::INFINITY;
+        for x in -range..=range {
+            for y in -range..=range {
+                let other = cell.shifted(x, y);
+                if grid.is_occupied(&other) != occupied {
+                    nearest = nearest.min(((x * x + y * y) as f64).sqrt());
+                }
+            }
+        }
+
+        let d = (nearest * grid.cell_size() - grid.cell_size() / 2.0).min(max_distance);
+        return if occupied { -d } else { d };
+    }
+
+    fn assert_field_matches(field: &DistanceField, grid: &SparseGrid) {
+        for x in -10..30 {
+            for y in -10..25 {
+                let cell = Cell::new(x, y);
+                let expected = brute_force_distance(grid, &cell, field.max_distance());
+                assert_relative_eq!(field.cell_distance(&cell), expected, epsilon = 1e-9);
+            }
+        }
+    }
+
+    fn change(grid: &mut SparseGrid, field: &mut DistanceField, cells: &[(Cell, bool)]) {
+        let changes = cells.iter().copied().collect();
+        let (confirmed, _) = grid.change_cells(&changes);
+        field.update(grid, &confirmed);
+    }
+
+    #[test]
+    fn test_distance_field_updates() {
+        let mut grid = SparseGrid::new(0.5);
+        grid.set_origin(Point::new(1.0, -2.0));
+        let mut cells = Vec::new();
+        for x in 0..20 {
+            cells.push((Cell::new(x, 0), true));
+            cells.push((Cell::new(x, 1), true));
+        }
+        for x in 5..12 {
+            for y in 6..13 {
+                cells.push((Cell::new(x, y), true));
+            }
+        }
+        grid.change_cells(&cells.iter().copied().collect());
+
+        let mut field = DistanceField::new(&grid, 2.0);
+        assert_field_matches(&field, &grid);
+
+        // A pallet appears in the aisle
+        change(
+            &mut grid,
+            &mut field,
+            &[
+                (Cell::new(15, 5), true),
+                (Cell::new(16, 5), true),
+                (Cell::new(15, 6), true),
+            ],
+        );
+        assert_field_matches(&field, &grid);
+
+        // Part of the block is removed, and a hole is opened in the wall
+        let mut removals = Vec::new();
+        for x in 5..12 {
+            removals.push((Cell::new(x, 12), false));
+            removals.push((Cell::new(x, 11), false));
+        }
+        removals.push((Cell::new(8, 8), false));
+        removals.push((Cell::new(3, 0), false));
+        removals.push((Cell::new(3, 1), false));
+        change(&mut grid, &mut field, &removals);
+        assert_field_matches(&field, &grid);
+
+        // Remove everything
+        let everything: Vec<_> = grid.occupied_cells().map(|cell| (cell, false)).collect();
+        change(&mut grid, &mut field, &everything);
+        assert_field_matches(&field, &grid);
+        assert!(field.outer.is_empty());
+        assert!(field.inner.is_empty());
+        assert_relative_eq!(field.cell_distance(&Cell::new(3, 3)), 2.0);
+    }
+
+    #[test]
+    fn test_distance_field_queries() {
+        let mut grid = SparseGrid::new(1.0);
+        grid.set_origin(Point::new(-0.5, 0.25));
+        let mut changes = HashMap::new();
+        for x in 0..5 {
+            for y in 0..5 {
+                changes.insert(Cell::new(x, y), true);
+            }
+        }
+        grid.change_cells(&changes);
+        let field = DistanceField::new(&grid, 3.0);
+
+        // The field crosses zero at the boundary of the obstacle
+        let boundary = grid.cell_bottom_left(&Cell::new(5, 2)) + Vector::new(0.0, 0.5);
+        assert_relative_eq!(field.signed_distance(&boundary), 0.0, epsilon = 1e-9);
+        let outside = boundary + Vector::new(1.25, 0.0);
+        assert_relative_eq!(field.signed_distance(&outside), 1.25, epsilon = 1e-9);
+        let inside = boundary - Vector::new(1.5, 0.0);
+        assert_relative_eq!(field.signed_distance(&inside), -1.5, epsilon = 1e-9);
+        assert_relative_eq!(field.clearance(&inside), 0.0);
+
+        // The maximum radius never overestimates the true clearance
+        for i in 0..40 {
+            let p = boundary + Vector::new(0.1 * i as f64, 0.37);
+            let radius = field.max_radius_at(&p);
+            assert!(radius <= field.signed_distance(&p).max(0.0) + 1e-9);
+            assert!(field.is_circle_clear(&p, radius));
+            if radius > 0.0 {
+                assert!(grid
+                    .is_square_occupied(p, radius * std::f64::consts::SQRT_2)
+                    .is_none());
+            }
+        }
+        assert_relative_eq!(
+            field.max_radius_at(&(boundary - Vector::new(0.5, 0.0))),
+            0.0
+        );
+    }
+}
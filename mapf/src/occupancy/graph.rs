@@ -18,73 +18,126 @@
 use crate::{
     graph::{Edge, Graph},
     occupancy::{Cell, Grid, Point, Visibility},
-    util::triangular_for,
 };
 use std::{
     collections::{HashMap, HashSet},
-    sync::Arc,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
-#[derive(Debug)]
-pub struct VisibilityGraph<G: Grid> {
-    visibility: Arc<Visibility<G>>,
-    visibility_of_interest: HashMap<Cell, HashSet<Cell>>,
-    points_of_interest: HashSet<Cell>,
+/// The points of interest of a graph along with the visibility of each one.
+#[derive(Debug, Default)]
+struct Interest {
+    points: HashSet<Cell>,
+    visibility: HashMap<Cell, HashSet<Cell>>,
 }
 
-fn gather_points_of_interest<G: Grid>(
-    visibility: &Visibility<G>,
-    points_of_interest: impl Iterator<Item = Cell>,
-) -> (HashSet<Cell>, HashMap<Cell, HashSet<Cell>>) {
-    let mut connections: HashMap<Cell, HashSet<Cell>> = Default::default();
-    let mut valid = HashSet::new();
-    for interest in points_of_interest {
+impl Interest {
+    fn new<G: Grid>(
+        visibility: &Visibility<G>,
+        points_of_interest: impl Iterator<Item = Cell>,
+    ) -> Self {
+        let mut interest = Self::default();
+        for point in points_of_interest {
+            interest.insert(visibility, point);
+        }
+
+        return interest;
+    }
+
+    /// Returns false if the agent cannot stand on the point of interest.
+    fn insert<G: Grid>(&mut self, visibility: &Visibility<G>, interest: Cell) -> bool {
+        if self.points.contains(&interest) {
+            return true;
+        }
+
+        let p = visibility.grid().cell_center(&interest);
         if visibility
             .grid()
-            .is_square_occupied(
-                interest.to_center_point(visibility.grid().cell_size()),
-                visibility.agent_radius(),
-            )
+            .is_square_occupied(p, visibility.agent_radius())
             .is_some()
         {
-            continue;
+            return false;
         }
 
         for v in visibility.calculate_visibility(interest) {
-            // connections.insert(v, interest);
-            connections.entry(v).or_default().insert(interest);
+            self.visibility.entry(v).or_default().insert(interest);
+        }
+
+        for other in &self.points {
+            if visibility
+                .grid()
+                .is_sweep_occupied(
+                    p,
+                    visibility.grid().cell_center(other),
+                    2.0 * visibility.agent_radius(),
+                )
+                .is_none()
+            {
+                self.visibility.entry(interest).or_default().insert(*other);
+                self.visibility.entry(*other).or_default().insert(interest);
+            }
         }
 
-        valid.insert(interest);
+        self.points.insert(interest);
+        return true;
     }
 
-    triangular_for(valid.iter(), |interest_i, interest_j| {
-        if **interest_i == *interest_j {
-            return;
+    /// Returns false if the cell was not a point of interest.
+    fn remove(&mut self, interest: &Cell) -> bool {
+        if !self.points.remove(interest) {
+            return false;
         }
 
-        let cell_size = visibility.grid().cell_size();
-        if visibility
-            .grid()
-            .is_sweep_occupied(
-                interest_i.to_center_point(cell_size),
-                interest_j.to_center_point(cell_size),
-                2.0 * visibility.agent_radius(),
-            )
-            .is_none()
-        {
-            connections
-                .entry(**interest_i)
-                .or_default()
-                .insert(*interest_j);
-            connections
-                .entry(*interest_j)
-                .or_default()
-                .insert(**interest_i);
+        self.visibility.remove(interest);
+        self.visibility.retain(|_, points| {
+            points.remove(interest);
+            !points.is_empty()
+        });
+
+        return true;
+    }
+
+    fn edges_from<G: Grid>(
+        &self,
+        visibility: &Visibility<G>,
+        from_cell: Cell,
+    ) -> Vec<(Cell, Cell)> {
+        if let Some(points) = self.visibility.get(&from_cell) {
+            return points.iter().map(|poi| (from_cell, *poi)).collect();
         }
-    });
 
-    return (valid, connections);
+        let from_p = visibility.grid().cell_center(&from_cell);
+        self.points
+            .iter()
+            .filter(|poi| {
+                let to_p = visibility.grid().cell_center(poi);
+                visibility
+                    .grid()
+                    .is_sweep_occupied(from_p, to_p, 2.0 * visibility.agent_radius())
+                    .is_none()
+            })
+            .map(|poi| (from_cell, *poi))
+            .collect()
+    }
+}
+
+// The interest sets are never left in an inconsistent state by a panic, so it
+// is safe to keep using them after the lock has been poisoned.
+fn read(interest: &RwLock<Interest>) -> RwLockReadGuard<'_, Interest> {
+    interest.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write(interest: &RwLock<Interest>) -> RwLockWriteGuard<'_, Interest> {
+    interest.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Points of interest can be added to and removed from the graph while it is
+/// shared, so the same graph (and any heuristic built on it) can be reused for
+/// many start and goal queries.
+#[derive(Debug)]
+pub struct VisibilityGraph<G: Grid> {
+    visibility: Arc<Visibility<G>>,
+    interest: RwLock<Interest>,
 }
 
 impl<G: Grid> VisibilityGraph<G> {
@@ -92,14 +145,29 @@ impl<G: Grid> VisibilityGraph<G> {
         visibility: Arc<Visibility<G>>,
         points_of_interest: impl Iterator<Item = Cell>,
     ) -> Self {
-        let (points_of_interest, visibility_of_interest) =
-            gather_points_of_interest(visibility.as_ref(), points_of_interest);
+        let interest = RwLock::new(Interest::new(visibility.as_ref(), points_of_interest));
         Self {
             visibility,
-            visibility_of_interest,
-            points_of_interest,
+            interest,
         }
     }
+
+    /// Add a point of interest to the graph, connecting it to every point
+    /// that it can see. Returns false if the agent cannot stand on the point.
+    pub fn add_point_of_interest(&self, cell: Cell) -> bool {
+        write(&self.interest).insert(self.visibility.as_ref(), cell)
+    }
+
+    /// Remove a point of interest from the graph. Returns false if the cell
+    /// was not a point of interest.
+    pub fn remove_point_of_interest(&self, cell: &Cell) -> bool {
+        write(&self.interest).remove(cell)
+    }
+
+    /// Get the current points of interest of the graph.
+    pub fn points_of_interest(&self) -> Vec<Cell> {
+        read(&self.interest).points.iter().copied().collect()
+    }
 }
 
 /// From any unoccupied cell, latch onto the visibility graph but do not visit
@@ -114,7 +182,7 @@ impl<G: Grid> Graph for VisibilityGraph<G> {
         // We don't bother to filter out occupied cells because those cells will
         // not generate any valid edges anyway. If we filtered them out here we
         // would be frequently doing redundant occupancy checking.
-        Some(cell.to_center_point(self.visibility.grid().cell_size()))
+        Some(self.visibility.grid().cell_center(&cell))
     }
 
     fn edges_from_vertex<'a>(&'a self, from_cell: Self::Key) -> Self::EdgeIter<'a> {
@@ -126,7 +194,7 @@ impl<G: Grid> Graph for VisibilityGraph<G> {
                 self.visibility
                     .grid()
                     .is_square_occupied(
-                        from_cell.to_center_point(self.visibility.grid().cell_size()),
+                        self.visibility.grid().cell_center(from_cell),
                         2.0 * self.visibility.agent_radius(),
                     )
                     .is_none()
@@ -141,46 +209,14 @@ impl<G: Grid> Graph for VisibilityGraph<G> {
                     })
                     .filter(|(from_cell, to_cell)| from_cell != to_cell)
             })
-            .chain({
-                let interest = self.visibility_of_interest.get(&from_cell);
-                [interest]
-                    .into_iter()
-                    .filter_map(|x| x)
-                    .flat_map(|x| x)
-                    .map(move |point_of_interest| (from_cell, *point_of_interest))
-                    .chain(
-                        [interest]
-                            .into_iter()
-                            .filter(|x| x.is_none())
-                            .flat_map(move |_| {
-                                self.points_of_interest
-                                    .iter()
-                                    .filter(move |poi| {
-                                        let to_p =
-                                            poi.to_center_point(self.visibility.grid().cell_size());
-                                        let from_p = from_cell
-                                            .to_center_point(self.visibility.grid().cell_size());
-                                        self.visibility
-                                            .grid()
-                                            .is_sweep_occupied(
-                                                from_p,
-                                                to_p,
-                                                2.0 * self.visibility.agent_radius(),
-                                            )
-                                            .is_none()
-                                    })
-                                    .map(move |poi| (from_cell.clone(), *poi))
-                            }),
-                    )
-            })
+            .chain(read(&self.interest).edges_from(self.visibility.as_ref(), from_cell))
     }
 }
 
 #[derive(Debug)]
 pub struct NeighborhoodGraph<G: Grid> {
     visibility: Arc<Visibility<G>>,
-    visibility_of_interest: HashMap<Cell, HashSet<Cell>>,
-    points_of_interest: HashSet<Cell>,
+    interest: RwLock<Interest>,
 }
 
 impl<G: Grid> NeighborhoodGraph<G> {
@@ -188,14 +224,29 @@ impl<G: Grid> NeighborhoodGraph<G> {
         visibility: Arc<Visibility<G>>,
         points_of_interest: impl Iterator<Item = Cell>,
     ) -> Self {
-        let (points_of_interest, visibility_of_interest) =
-            gather_points_of_interest(visibility.as_ref(), points_of_interest);
+        let interest = RwLock::new(Interest::new(visibility.as_ref(), points_of_interest));
         Self {
             visibility,
-            visibility_of_interest,
-            points_of_interest,
+            interest,
         }
     }
+
+    /// Add a point of interest to the graph, connecting it to every point
+    /// that it can see. Returns false if the agent cannot stand on the point.
+    pub fn add_point_of_interest(&self, cell: Cell) -> bool {
+        write(&self.interest).insert(self.visibility.as_ref(), cell)
+    }
+
+    /// Remove a point of interest from the graph. Returns false if the cell
+    /// was not a point of interest.
+    pub fn remove_point_of_interest(&self, cell: &Cell) -> bool {
+        write(&self.interest).remove(cell)
+    }
+
+    /// Get the current points of interest of the graph.
+    pub fn points_of_interest(&self) -> Vec<Cell> {
+        read(&self.interest).points.iter().copied().collect()
+    }
 }
 
 /// From any unoccupied cell, expand towards both its adjacent cells and the
@@ -210,13 +261,13 @@ impl<G: Grid> Graph for NeighborhoodGraph<G> {
         if self.visibility.grid().is_occupied(&cell) {
             None
         } else {
-            Some(cell.to_center_point(self.visibility.grid().cell_size()))
+            Some(self.visibility.grid().cell_center(&cell))
         }
     }
 
     fn edges_from_vertex<'a>(&'a self, from_cell: Self::Key) -> Self::EdgeIter<'a> {
         // dbg!("neighborhood graph");
-        let from_p = from_cell.to_center_point(self.visibility.grid().cell_size());
+        let from_p = self.visibility.grid().cell_center(&from_cell);
         [from_cell]
             .into_iter()
             .filter(move |from_cell| {
@@ -248,34 +299,7 @@ impl<G: Grid> Graph for NeighborhoodGraph<G> {
                                 from_cell != to_cell
                             }),
                     )
-                    .chain({
-                        let interest = self.visibility_of_interest.get(&from_cell);
-                        [interest]
-                            .into_iter()
-                            .filter_map(|x| x)
-                            .flat_map(|x| x)
-                            .map(move |point_of_interest| (from_cell, *point_of_interest))
-                            .chain([interest].into_iter().filter(|x| x.is_none()).flat_map(
-                                move |_| {
-                                    self.points_of_interest
-                                        .iter()
-                                        .filter(move |poi| {
-                                            let to_p = poi.to_center_point(
-                                                self.visibility.grid().cell_size(),
-                                            );
-                                            self.visibility
-                                                .grid()
-                                                .is_sweep_occupied(
-                                                    from_p,
-                                                    to_p,
-                                                    2.0 * self.visibility.agent_radius(),
-                                                )
-                                                .is_none()
-                                        })
-                                        .map(move |poi| (from_cell.clone(), *poi))
-                                },
-                            ))
-                    })
+                    .chain(read(&self.interest).edges_from(self.visibility.as_ref(), from_cell))
             })
     }
 }
@@ -292,6 +316,78 @@ impl Edge<Cell> for (Cell, Cell) {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::occupancy::SparseGrid;
+
     #[test]
     fn build() {}
+
+    fn make_test_visibility() -> Arc<Visibility<SparseGrid>> {
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for y in -3..=3 {
+            changes.insert(Cell::new(5, y), true);
+        }
+        changes.insert(Cell::new(12, 6), true);
+        grid.change_cells(&changes);
+
+        return Arc::new(Visibility::new(grid, 0.25));
+    }
+
+    fn edge_sets<Gr>(graph: &Gr, cells: &[Cell]) -> Vec<HashSet<(Cell, Cell)>>
+    where
+        Gr: Graph<Key = Cell, Edge = (Cell, Cell)>,
+    {
+        cells
+            .iter()
+            .map(|cell| graph.edges_from_vertex(*cell).into_iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_dynamic_points_of_interest() {
+        let visibility = make_test_visibility();
+        let a = Cell::new(0, 0);
+        let b = Cell::new(10, 1);
+        let c = Cell::new(8, 8);
+
+        let mut cells: Vec<Cell> = visibility.iter_points().map(|(cell, _)| *cell).collect();
+        cells.extend([a, b, c, Cell::new(2, -5), Cell::new(14, 0)]);
+
+        let expected = VisibilityGraph::new(visibility.clone(), [a, b].into_iter());
+        let graph = VisibilityGraph::new(visibility.clone(), [a].into_iter());
+        assert!(graph.add_point_of_interest(b));
+        assert_eq!(edge_sets(&graph, &cells), edge_sets(&expected, &cells));
+
+        // An occupied cell cannot be a point of interest
+        assert!(!graph.add_point_of_interest(Cell::new(5, 0)));
+
+        let shared = Arc::new(graph);
+        assert!(shared.add_point_of_interest(c));
+        assert!(shared.remove_point_of_interest(&c));
+        assert!(!shared.remove_point_of_interest(&c));
+        assert_eq!(
+            edge_sets(shared.as_ref(), &cells),
+            edge_sets(&expected, &cells)
+        );
+
+        let expected = VisibilityGraph::new(visibility.clone(), [a].into_iter());
+        assert!(shared.remove_point_of_interest(&b));
+        assert_eq!(shared.points_of_interest(), vec![a]);
+        assert_eq!(
+            edge_sets(shared.as_ref(), &cells),
+            edge_sets(&expected, &cells)
+        );
+
+        let expected = NeighborhoodGraph::new(visibility.clone(), [a, b, c].into_iter());
+        let graph = NeighborhoodGraph::new(visibility.clone(), [].into_iter());
+        for cell in [c, a, b] {
+            assert!(graph.add_point_of_interest(cell));
+        }
+        assert_eq!(edge_sets(&graph, &cells), edge_sets(&expected, &cells));
+
+        let expected = NeighborhoodGraph::new(visibility, [a, c].into_iter());
+        assert!(graph.remove_point_of_interest(&b));
+        assert_eq!(edge_sets(&graph, &cells), edge_sets(&expected, &cells));
+    }
 }
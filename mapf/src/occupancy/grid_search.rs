@@ -0,0 +1,85 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    motion::{
+        r2::{self, timed_position::LineFollow},
+        trajectory::CostCalculator,
+        Extrapolator, TimePoint,
+    },
+    occupancy::{Cell, Point},
+};
+use num::Zero;
+use thiserror::Error as ThisError;
+
+/// The offsets of the eight cells that surround a cell.
+pub(crate) const NEIGHBORS: [(i64, i64); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// The result of planning with one of the expanders that search directly over
+/// the cells of an occupancy grid.
+#[derive(Debug, Clone)]
+pub struct GridSearchSolution<Cost> {
+    pub(crate) cost: Cost,
+    pub(crate) motion: Option<r2::LinearTrajectory>,
+}
+
+impl<Cost> GridSearchSolution<Cost> {
+    pub fn cost(&self) -> &Cost {
+        &self.cost
+    }
+
+    pub fn motion(&self) -> &Option<r2::LinearTrajectory> {
+        &self.motion
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum GridSearchInitError {
+    #[error("The requested start cell [{0:?}] is occupied")]
+    OccupiedStart(Cell),
+    #[error("The requested goal cell [{0:?}] is occupied")]
+    OccupiedGoal(Cell),
+}
+
+/// Calculate the cost of moving in a straight line between two points.
+pub(crate) fn straight_line_cost<C: CostCalculator<r2::timed_position::Waypoint>>(
+    extrapolator: &LineFollow,
+    cost_calculator: &C,
+    from: &Point,
+    to: &Point,
+) -> C::Cost {
+    let wp0 = r2::timed_position::Waypoint {
+        time: TimePoint::zero(),
+        position: *from,
+    };
+
+    return extrapolator
+        .make_trajectory(wp0, to)
+        .ok()
+        .flatten()
+        .map(|t| cost_calculator.compute_cost(&t))
+        .unwrap_or(C::Cost::zero());
+}
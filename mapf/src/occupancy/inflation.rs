@@ -0,0 +1,452 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    motion::{
+        r2, se2,
+        trajectory::{CostCalculator, Trajectory},
+    },
+    occupancy::{Cell, Grid, Point},
+};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+
+/// Reports how much free space surrounds a point.
+pub trait Clearance: std::fmt::Debug {
+    /// Get the distance from a point to the nearest occupied space. Values
+    /// may saturate at some maximum distance that the implementation cares
+    /// about.
+    fn clearance(&self, p: &Point) -> f64;
+}
+
+/// A Euclidean distance transform of an occupancy grid, giving the distance
+/// from every cell to the nearest occupied cell. Distances saturate at a
+/// maximum value so that only the region near occupied cells needs to be
+/// stored.
+///
+/// The transform is a snapshot of the grid at the time it was created. It
+/// needs to be recreated when the occupancy of the grid changes.
+#[derive(Debug, Clone)]
+pub struct DistanceTransform {
+    cell_size: f64,
+    origin: Point,
+    lower: Cell,
+    width: usize,
+    height: usize,
+    max_distance: f64,
+    /// Distance from the center of each cell to the center of the nearest
+    /// occupied cell, row-major starting from the lower cell.
+    distances: Vec<f64>,
+}
+
+impl DistanceTransform {
+    /// Compute the distance transform of a grid. Any distance greater than
+    /// `max_distance` will be reported as `max_distance`.
+    pub fn new<G: Grid>(grid: &G, max_distance: f64) -> Self {
+        let cell_size = grid.cell_size();
+        let mut bounds: Option<(Cell, Cell)> = None;
+        for cell in grid.occupied_cells() {
+            bounds = Some(match bounds {
+                Some((lower, upper)) => (
+                    Cell::new(lower.x.min(cell.x), lower.y.min(cell.y)),
+                    Cell::new(upper.x.max(cell.x), upper.y.max(cell.y)),
+                ),
+                None => (cell, cell),
+            });
+        }
+
+        let (lower, upper) = match bounds {
+            Some(bounds) => bounds,
+            None => {
+                // Nothing is occupied, so every cell has the maximum distance
+                return Self {
+                    cell_size,
+                    origin: grid.origin(),
+                    lower: Cell::new(0, 0),
+                    width: 0,
+                    height: 0,
+                    max_distance,
+                    distances: Vec::new(),
+                };
+            }
+        };
+
+        // Any cell beyond this margin must be at least max_distance away from
+        // every occupied cell, so it does not need to be stored.
+        let margin = (max_distance / cell_size).ceil() as i64 + 1;
+        let lower = lower.shifted(-margin, -margin);
+        let upper = upper.shifted(margin, margin);
+        let width = (upper.x - lower.x + 1) as usize;
+        let height = (upper.y - lower.y + 1) as usize;
+
+        // Squared distances measured in cells
+        let mut squared = vec![UNREACHED; width * height];
+        for cell in grid.occupied_cells() {
+            let col = (cell.x - lower.x) as usize;
+            let row = (cell.y - lower.y) as usize;
+            squared[row * width + col] = 0.0;
+        }
+
+        squared_distance_2d(&mut squared, width, height);
+
+        let distances = squared
+            .into_iter()
+            .map(|d2| (d2.sqrt() * cell_size).min(max_distance))
+            .collect();
+
+        return Self {
+            cell_size,
+            origin: grid.origin(),
+            lower,
+            width,
+            height,
+            max_distance,
+            distances,
+        };
+    }
+
+    /// The distance at which values saturate.
+    pub fn max_distance(&self) -> f64 {
+        return self.max_distance;
+    }
+
+    /// Get the distance from the center of a cell to the center of the
+    /// nearest occupied cell. Occupied cells have a distance of zero.
+    pub fn cell_distance(&self, cell: &Cell) -> f64 {
+        let col = cell.x - self.lower.x;
+        let row = cell.y - self.lower.y;
+        if col < 0 || row < 0 || col as usize >= self.width || row as usize >= self.height {
+            return self.max_distance;
+        }
+
+        return self.distances[row as usize * self.width + col as usize];
+    }
+}
+
+impl Clearance for DistanceTransform {
+    /// The clearance is approximated from the cell that contains the point:
+    /// the distance between cell centers, minus the half of the occupied cell
+    /// that extends towards the point.
+    fn clearance(&self, p: &Point) -> f64 {
+        let cell = Cell::from_point(*p - self.origin.coords, self.cell_size);
+        let distance = self.cell_distance(&cell);
+        if distance >= self.max_distance {
+            return self.max_distance;
+        }
+
+        return (distance - self.cell_size / 2.0).max(0.0);
+    }
+}
+
+/// A stand-in for infinity that keeps the arithmetic below finite.
+pub(super) const UNREACHED: f64 = 1e20;
+
+/// Transform a row-major grid of values, where each value is 0.0 for a source
+/// cell and [`UNREACHED`] for every other cell, into the squared distance (in
+/// cells) from each cell to the nearest source.
+pub(super) fn squared_distance_2d(squared: &mut [f64], width: usize, height: usize) {
+    let n = width.max(height);
+    let mut input = vec![0.0; n];
+    let mut output = vec![0.0; n];
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+
+    for col in 0..width {
+        for (row, value) in input.iter_mut().enumerate().take(height) {
+            *value = squared[row * width + col];
+        }
+
+        squared_distance_1d(&input[..height], &mut output, &mut v, &mut z);
+        for (row, value) in output.iter().enumerate().take(height) {
+            squared[row * width + col] = *value;
+        }
+    }
+
+    for row in 0..height {
+        let range = row * width..(row + 1) * width;
+        input[..width].copy_from_slice(&squared[range.clone()]);
+        squared_distance_1d(&input[..width], &mut output, &mut v, &mut z);
+        squared[range].copy_from_slice(&output[..width]);
+    }
+}
+
+/// Compute the one-dimensional squared distance transform of `f` using the
+/// lower envelope of parabolas from Felzenszwalb and Huttenlocher. `v` and `z`
+/// are scratch buffers that must hold at least `f.len()` and `f.len() + 1`
+/// elements.
+fn squared_distance_1d(f: &[f64], output: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    if f.is_empty() {
+        return;
+    }
+
+    let intersect = |q: usize, p: usize| {
+        let (qf, pf) = (q as f64, p as f64);
+        ((f[q] + qf * qf) - (f[p] + pf * pf)) / (2.0 * qf - 2.0 * pf)
+    };
+
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..f.len() {
+        let mut s = intersect(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(q, v[k]);
+        }
+
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+
+    k = 0;
+    for (q, value) in output.iter_mut().enumerate().take(f.len()) {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+
+        let dq = q as f64 - v[k] as f64;
+        *value = dq * dq + f[v[k]];
+    }
+}
+
+/// Calculates the cost of a trajectory as its duration plus a penalty for
+/// every moment that the agent spends close to obstacles. This makes agents
+/// prefer the middle of aisles over hugging walls, while still being able to
+/// squeeze through narrow gaps when there is no better option.
+///
+/// The penalty rate at a point is `weight * (1 - clearance/inflation_radius)^2`
+/// for any clearance less than the inflation radius, and zero otherwise. The
+/// rate is integrated over time, so a weight of 1.0 means that a second spent
+/// right against an obstacle costs as much as two seconds in open space.
+///
+/// Only se2 trajectories are penalized. For r2 trajectories this reports the
+/// plain duration, because se2::QuickestPath estimates the cost of se2 motions
+/// by planning r2 motions, possibly across a different graph than the one
+/// being searched. Leaving the penalty out of those estimates keeps the
+/// heuristic admissible.
+#[derive(Debug, Clone)]
+pub struct InflatedCostCalculator<F: Clearance> {
+    /// Where the clearance of each point comes from.
+    pub clearance: Arc<F>,
+    /// Points that are farther than this from any obstacle have no penalty.
+    pub inflation_radius: f64,
+    /// The penalty rate for a point that is touching an obstacle.
+    pub weight: f64,
+    /// The maximum distance between the points where the clearance gets
+    /// sampled along a trajectory.
+    pub sample_distance: f64,
+}
+
+/// Returned by [`InflatedCostCalculator::new`] when a parameter is invalid.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq)]
+pub enum InflatedCostError {
+    #[error("The inflation radius must be greater than zero, but it was {0}")]
+    InflationRadius(f64),
+    #[error("The weight must not be negative, but it was {0}")]
+    Weight(f64),
+}
+
+impl<F: Clearance> InflatedCostCalculator<F> {
+    /// Create a new cost calculator. The inflation radius must be positive
+    /// and the weight must not be negative.
+    pub fn new(
+        clearance: Arc<F>,
+        inflation_radius: f64,
+        weight: f64,
+    ) -> Result<Self, InflatedCostError> {
+        if inflation_radius.is_nan() || inflation_radius <= 0.0 {
+            return Err(InflatedCostError::InflationRadius(inflation_radius));
+        }
+
+        if weight.is_nan() || weight < 0.0 {
+            return Err(InflatedCostError::Weight(weight));
+        }
+
+        return Ok(Self {
+            clearance,
+            inflation_radius,
+            weight,
+            sample_distance: inflation_radius / 10.0,
+        });
+    }
+
+    /// Get the rate at which penalty accumulates while the agent is at `p`.
+    pub fn penalty_rate(&self, p: &Point) -> f64 {
+        let clearance = self.clearance.clearance(p);
+        if clearance >= self.inflation_radius {
+            return 0.0;
+        }
+
+        let closeness = 1.0 - clearance.max(0.0) / self.inflation_radius;
+        return self.weight * closeness * closeness;
+    }
+
+    /// Integrate the penalty rate over a segment of motion that travels in a
+    /// straight line from p0 to p1 over `dt` seconds.
+    fn segment_penalty(&self, p0: Point, p1: Point, dt: f64) -> f64 {
+        let samples = ((p1 - p0).norm() / self.sample_distance).ceil().max(1.0) as usize;
+        let mut penalty = 0.0;
+        for k in 0..samples {
+            let s = (k as f64 + 0.5) / samples as f64;
+            penalty += self.penalty_rate(&(p0 + (p1 - p0) * s));
+        }
+
+        return penalty * dt / samples as f64;
+    }
+}
+
+impl<F: Clearance> CostCalculator<se2::timed_position::Waypoint> for InflatedCostCalculator<F> {
+    type Cost = i64;
+    fn compute_cost(&self, trajectory: &Trajectory<se2::timed_position::Waypoint>) -> i64 {
+        let mut penalty = 0.0;
+        for pair in trajectory.windows(2) {
+            let (wp0, wp1) = (&pair[0], &pair[1]);
+            penalty += self.segment_penalty(
+                Point::from(wp0.position.translation.vector),
+                Point::from(wp1.position.translation.vector),
+                (wp1.time - wp0.time).as_secs_f64(),
+            );
+        }
+
+        return trajectory.duration().nanos + (penalty * 1e9).round() as i64;
+    }
+}
+
+impl<F: Clearance> CostCalculator<r2::timed_position::Waypoint> for InflatedCostCalculator<F> {
+    type Cost = i64;
+    fn compute_cost(&self, trajectory: &Trajectory<r2::timed_position::Waypoint>) -> i64 {
+        // See the documentation of InflatedCostCalculator for why there is no
+        // penalty here.
+        return trajectory.duration().nanos;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        motion::{se2::timed_position::Waypoint as WaypointSE2, TimePoint},
+        occupancy::SparseGrid,
+    };
+    use approx::assert_relative_eq;
+    use std::collections::HashMap;
+
+    fn make_grid() -> SparseGrid {
+        let mut grid = SparseGrid::new(0.5);
+        grid.set_origin(Point::new(0.2, -0.3));
+        let mut changes = HashMap::new();
+        for x in 0..20 {
+            // Walls of an aisle
+            changes.insert(Cell::new(x, 0), true);
+            changes.insert(Cell::new(x, 6), true);
+        }
+        changes.insert(Cell::new(15, 3), true);
+        changes.insert(Cell::new(-4, 11), true);
+        grid.change_cells(&changes);
+        return grid;
+    }
+
+    #[test]
+    fn test_distance_transform() {
+        let grid = make_grid();
+        let max_distance = 2.2;
+        let transform = DistanceTransform::new(&grid, max_distance);
+        let occupied: Vec<Cell> = grid.occupied_cells().collect();
+        for x in -12..30 {
+            for y in -8..20 {
+                let cell = Cell::new(x, y);
+                let expected = occupied
+                    .iter()
+                    .map(|o| {
+                        let dx = (o.x - x) as f64;
+                        let dy = (o.y - y) as f64;
+                        (dx * dx + dy * dy).sqrt() * grid.cell_size()
+                    })
+                    .fold(max_distance, f64::min);
+                assert_relative_eq!(transform.cell_distance(&cell), expected, epsilon = 1e-9);
+            }
+        }
+
+        let p = grid.cell_center(&Cell::new(3, 3));
+        assert_relative_eq!(transform.clearance(&p), 1.25);
+        let p = grid.cell_center(&Cell::new(3, 0));
+        assert_relative_eq!(transform.clearance(&p), 0.0);
+        let p = grid.cell_center(&Cell::new(100, 100));
+        assert_relative_eq!(transform.clearance(&p), max_distance);
+
+        let empty = DistanceTransform::new(&SparseGrid::new(1.0), 3.0);
+        assert_relative_eq!(empty.clearance(&Point::new(0.5, 0.5)), 3.0);
+    }
+
+    #[test]
+    fn test_inflated_cost() {
+        let grid = make_grid();
+        let transform = Arc::new(DistanceTransform::new(&grid, 2.0));
+        let calculator = InflatedCostCalculator::new(transform, 1.0, 2.0).unwrap();
+        assert_eq!(
+            InflatedCostCalculator::new(calculator.clearance.clone(), 0.0, 1.0).unwrap_err(),
+            InflatedCostError::InflationRadius(0.0)
+        );
+        assert_eq!(
+            InflatedCostCalculator::new(calculator.clearance.clone(), 1.0, -1.0).unwrap_err(),
+            InflatedCostError::Weight(-1.0)
+        );
+
+        let make_se2 = |y: f64| {
+            let p0 = grid.cell_center(&Cell::new(1, 0));
+            let p1 = grid.cell_center(&Cell::new(5, 0));
+            Trajectory::new(
+                WaypointSE2::new(TimePoint::from_secs_f64(0.0), p0.x, p0.y + y, 0.0),
+                WaypointSE2::new(TimePoint::from_secs_f64(2.0), p1.x, p1.y + y, 0.0),
+            )
+            .unwrap()
+        };
+
+        // The middle of the aisle is more than the inflation radius from both
+        // walls, so only the duration counts.
+        let middle = make_se2(1.5);
+        assert_eq!(calculator.compute_cost(&middle), middle.duration().nanos);
+
+        // Closer to a wall the penalty applies, and it grows as the agent
+        // gets closer.
+        let near = calculator.compute_cost(&make_se2(1.0));
+        let nearer = calculator.compute_cost(&make_se2(0.5));
+        assert!(middle.duration().nanos < near);
+        assert!(near < nearer);
+
+        // Sitting right against the wall doubles the cost per the weight.
+        let touching = make_se2(0.0);
+        assert_eq!(
+            calculator.compute_cost(&touching),
+            3 * touching.duration().nanos
+        );
+
+        // r2 costs are only the duration so that the heuristic stays admissible
+        let r2_touching = Trajectory::new(
+            r2::timed_position::Waypoint::new(TimePoint::from_secs_f64(0.0), 0.45, -0.05),
+            r2::timed_position::Waypoint::new(TimePoint::from_secs_f64(2.0), 2.45, -0.05),
+        )
+        .unwrap();
+        assert_eq!(
+            calculator.compute_cost(&r2_touching),
+            r2_touching.duration().nanos
+        );
+    }
+}
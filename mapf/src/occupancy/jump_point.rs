@@ -0,0 +1,482 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    error::NoError,
+    expander::{Closable, Expander, InitTargeted, Solvable, Targeted},
+    motion::{
+        graph_search::ReconstructMotion,
+        movable::{ArcMovable, StartingPoint},
+        r2::{self, graph_search::Node, timed_position::LineFollow},
+        trajectory::CostCalculator,
+        Extrapolator, TimePoint, Trajectory,
+    },
+    node::{Agent, PartialKeyed, PartialKeyedClosedSet, PathSearch},
+    occupancy::{
+        grid_search::{straight_line_cost, GridSearchInitError, GridSearchSolution, NEIGHBORS},
+        Cell, Grid,
+    },
+};
+use arrayvec::ArrayVec;
+use num::Zero;
+use std::sync::Arc;
+
+/// A Jump Point Search expander over the cells of an occupancy grid with
+/// 8-connectivity.
+///
+/// Instead of adding every neighboring cell to the search queue, the expander
+/// jumps along straight and diagonal lines until it reaches a cell that has a
+/// forced neighbor or the goal. Only those jump points become nodes, so the
+/// search finds the same optimal cost as A* over the full grid with far fewer
+/// expansions.
+///
+/// A cell can be traversed if an agent of the given radius fits at its center.
+/// Diagonal moves are only allowed when both of the cells beside the diagonal
+/// can be traversed, so the agent never cuts around the corner of an obstacle.
+///
+/// The grid cannot be changed while the expander is in use, because the
+/// expander keeps track of the bounds of the occupied cells to know when a jump
+/// has left all of the obstacles behind.
+pub struct JumpPointExpander<G: Grid, C: CostCalculator<r2::timed_position::Waypoint>> {
+    grid: Arc<G>,
+    agent_radius: f64,
+    extrapolator: Arc<LineFollow>,
+    cost_calculator: Arc<C>,
+    /// The lowest and highest cell indices that contain an obstacle, if any.
+    bounds: Option<(Cell, Cell)>,
+    /// How far beyond the bounds an obstacle can influence the traversability
+    /// of a cell, measured in cells.
+    margin: i64,
+}
+
+impl<G, C> JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    pub fn new(
+        grid: Arc<G>,
+        agent_radius: f64,
+        extrapolator: Arc<LineFollow>,
+        cost_calculator: Arc<C>,
+    ) -> Self {
+        let mut bounds: Option<(Cell, Cell)> = None;
+        for cell in grid.occupied_cells() {
+            bounds = Some(match bounds {
+                Some((lower, upper)) => (
+                    Cell::new(lower.x.min(cell.x), lower.y.min(cell.y)),
+                    Cell::new(upper.x.max(cell.x), upper.y.max(cell.y)),
+                ),
+                None => (cell, cell),
+            });
+        }
+
+        let margin = 1 + (agent_radius / grid.cell_size()).ceil() as i64;
+        Self {
+            grid,
+            agent_radius,
+            extrapolator,
+            cost_calculator,
+            bounds,
+            margin,
+        }
+    }
+
+    pub fn grid(&self) -> &Arc<G> {
+        &self.grid
+    }
+
+    pub fn agent_radius(&self) -> f64 {
+        self.agent_radius
+    }
+
+    /// Check whether the agent can sit at the center of a cell.
+    pub fn is_cell_free(&self, cell: &Cell) -> bool {
+        let p = self.grid.cell_center(cell);
+        return self
+            .grid
+            .is_square_occupied(p, 2.0 * self.agent_radius)
+            .is_none();
+    }
+
+    /// Check whether the agent can step from a cell in the given direction.
+    fn can_step(&self, from: &Cell, dx: i64, dy: i64) -> bool {
+        if !self.is_cell_free(&from.shifted(dx, dy)) {
+            return false;
+        }
+
+        if dx != 0 && dy != 0 {
+            return self.is_cell_free(&from.shifted(dx, 0))
+                && self.is_cell_free(&from.shifted(0, dy));
+        }
+
+        return true;
+    }
+
+    /// Once a jump has passed every obstacle and the goal in its direction of
+    /// travel, it can never find another jump point.
+    fn is_beyond_limits(&self, cell: &Cell, dx: i64, dy: i64, goal: &Cell) -> bool {
+        let (lower, upper) = match self.bounds {
+            Some((lower, upper)) => (
+                Cell::new(lower.x.min(goal.x), lower.y.min(goal.y)),
+                Cell::new(upper.x.max(goal.x), upper.y.max(goal.y)),
+            ),
+            None => (*goal, *goal),
+        };
+
+        return (dx > 0 && cell.x > upper.x + self.margin)
+            || (dx < 0 && cell.x < lower.x - self.margin)
+            || (dy > 0 && cell.y > upper.y + self.margin)
+            || (dy < 0 && cell.y < lower.y - self.margin);
+    }
+
+    /// Check whether a cell that was reached by a straight move has a
+    /// neighbor that can only be reached optimally by passing through it.
+    fn has_forced_neighbor(&self, cell: &Cell, dx: i64, dy: i64) -> bool {
+        if dx != 0 {
+            return (self.is_cell_free(&cell.shifted(0, 1))
+                && !self.is_cell_free(&cell.shifted(-dx, 1)))
+                || (self.is_cell_free(&cell.shifted(0, -1))
+                    && !self.is_cell_free(&cell.shifted(-dx, -1)));
+        }
+
+        return (self.is_cell_free(&cell.shifted(1, 0))
+            && !self.is_cell_free(&cell.shifted(1, -dy)))
+            || (self.is_cell_free(&cell.shifted(-1, 0))
+                && !self.is_cell_free(&cell.shifted(-1, -dy)));
+    }
+
+    /// Jump from a cell in the given direction until a jump point is found.
+    fn jump(&self, from: Cell, dx: i64, dy: i64, goal: &Cell) -> Option<Cell> {
+        let mut current = from;
+        loop {
+            if !self.can_step(&current, dx, dy) {
+                return None;
+            }
+
+            let next = current.shifted(dx, dy);
+            if next == *goal {
+                return Some(next);
+            }
+
+            if dx != 0 && dy != 0 {
+                if self.jump(next, dx, 0, goal).is_some() || self.jump(next, 0, dy, goal).is_some() {
+                    return Some(next);
+                }
+            } else if self.has_forced_neighbor(&next, dx, dy) {
+                return Some(next);
+            }
+
+            if self.is_beyond_limits(&next, dx, dy, goal) {
+                return None;
+            }
+
+            current = next;
+        }
+    }
+
+    /// Get the directions that need to be searched from a node, pruning the
+    /// directions that could be reached more cheaply without passing through
+    /// the node.
+    fn directions(&self, node: &Node<C::Cost, Cell>, cell: &Cell) -> ArrayVec<(i64, i64), 8> {
+        let mut directions = ArrayVec::new();
+        let from = node
+            .parent()
+            .as_ref()
+            .and_then(|parent| parent.partial_key().cloned());
+
+        let (dx, dy) = match from {
+            Some(from) => ((cell.x - from.x).signum(), (cell.y - from.y).signum()),
+            None => (0, 0),
+        };
+
+        if dx != 0 && dy != 0 {
+            directions.push((dx, 0));
+            directions.push((0, dy));
+            directions.push((dx, dy));
+        } else if dx != 0 {
+            directions.push((dx, 0));
+            for side in [-1, 1] {
+                if self.is_cell_free(&cell.shifted(0, side)) {
+                    directions.push((0, side));
+                    directions.push((dx, side));
+                }
+            }
+        } else if dy != 0 {
+            directions.push((0, dy));
+            for side in [-1, 1] {
+                if self.is_cell_free(&cell.shifted(side, 0)) {
+                    directions.push((side, 0));
+                    directions.push((side, dy));
+                }
+            }
+        } else {
+            directions.extend(NEIGHBORS);
+        }
+
+        return directions;
+    }
+
+    fn make_child(
+        &self,
+        parent: &Arc<Node<C::Cost, Cell>>,
+        cell: Cell,
+        goal: &Cell,
+    ) -> Option<Arc<Node<C::Cost, Cell>>> {
+        let p = self.grid.cell_center(&cell);
+        let trajectory = self
+            .extrapolator
+            .make_trajectory(*parent.state(), &p)
+            .ok()??;
+        let cost = self.cost_calculator.compute_cost(&trajectory);
+        let h = straight_line_cost(
+            &self.extrapolator,
+            self.cost_calculator.as_ref(),
+            &p,
+            &self.grid.cell_center(goal),
+        );
+        return Some(
+            parent
+                .clone()
+                .moved_with(Some(cell), cost, h, Some(trajectory)),
+        );
+    }
+}
+
+impl<G, C> Expander for JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type Node = Node<C::Cost, Cell>;
+}
+
+impl<G, C> Closable for JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type ClosedSet = PartialKeyedClosedSet<Self::Node>;
+}
+
+impl<G, C> InitTargeted<Cell, Cell> for JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type InitTargetedError = GridSearchInitError;
+    type InitialTargetedNodes<'a> = impl Iterator<Item=Result<Arc<Self::Node>, GridSearchInitError>> + 'a where G: 'a, C: 'a;
+
+    fn start<'a>(&'a self, start: &'a Cell, goal: &'a Cell) -> Self::InitialTargetedNodes<'a> {
+        [(start, goal)].into_iter().map(move |(start, goal)| {
+            if !self.is_cell_free(start) {
+                return Err(GridSearchInitError::OccupiedStart(*start));
+            }
+
+            if !self.is_cell_free(goal) {
+                return Err(GridSearchInitError::OccupiedGoal(*goal));
+            }
+
+            let p = self.grid.cell_center(start);
+            let state = r2::timed_position::Waypoint {
+                time: TimePoint::zero(),
+                position: p,
+            };
+            let h = straight_line_cost(
+                &self.extrapolator,
+                self.cost_calculator.as_ref(),
+                &p,
+                &self.grid.cell_center(goal),
+            );
+
+            Ok(Arc::new(Node::<C::Cost, Cell>::start_from(
+                state,
+                Some(*start),
+                C::Cost::zero(),
+                h,
+                None,
+            )))
+        })
+    }
+}
+
+impl<G, C> Targeted<Cell> for JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type TargetedError = NoError;
+    type TargetedExpansion<'a> = impl Iterator<Item=Result<Arc<Self::Node>, NoError>> + 'a where G: 'a, C: 'a;
+
+    fn expand<'a>(
+        &'a self,
+        parent: &'a Arc<Self::Node>,
+        goal: &'a Cell,
+    ) -> Self::TargetedExpansion<'a> {
+        [parent.partial_key()]
+            .into_iter()
+            .flatten()
+            .flat_map(move |cell| {
+                self.directions(parent, cell)
+                    .into_iter()
+                    .filter_map(move |(dx, dy)| self.jump(*cell, dx, dy, goal))
+                    .filter_map(move |jump_point| self.make_child(parent, jump_point, goal))
+            })
+            .map(Ok)
+    }
+}
+
+impl<G, C> Solvable for JumpPointExpander<G, C>
+where
+    G: Grid,
+    C: CostCalculator<r2::timed_position::Waypoint>,
+{
+    type Solution = GridSearchSolution<C::Cost>;
+    type SolveError = NoError;
+
+    fn make_solution(
+        &self,
+        solution_node: &Arc<Self::Node>,
+    ) -> Result<Self::Solution, Self::SolveError> {
+        let motion = Trajectory::from_iter(ReconstructMotion::new(solution_node.clone())).ok();
+        let cost = motion
+            .as_ref()
+            .map(|t| self.cost_calculator.compute_cost(t))
+            .unwrap_or(C::Cost::zero());
+        Ok(GridSearchSolution { cost, motion })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star, algorithm::Status, motion::trajectory::DurationCostCalculator,
+        occupancy::SparseGrid, planner::make_planner,
+    };
+    use std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+    };
+
+    /// Find the optimal octile distance between two cells with a plain
+    /// Dijkstra search over every cell, using the same movement rules as the
+    /// jump point expander. Costs are measured in micrometers.
+    fn brute_force_cost<G: Grid>(
+        expander: &JumpPointExpander<G, DurationCostCalculator>,
+        start: Cell,
+        goal: Cell,
+    ) -> Option<i64> {
+        let diagonal = (2f64.sqrt() * 1e6).round() as i64;
+        let mut visited = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, start.x, start.y)));
+        while let Some(Reverse((cost, x, y))) = queue.pop() {
+            let cell = Cell::new(x, y);
+            if visited.contains_key(&cell) {
+                continue;
+            }
+            visited.insert(cell, cost);
+            if cell == goal {
+                return Some(cost);
+            }
+
+            for (dx, dy) in NEIGHBORS {
+                let next = cell.shifted(dx, dy);
+                if next.x.abs() > 20 || next.y.abs() > 20 {
+                    continue;
+                }
+
+                if expander.can_step(&cell, dx, dy) && !visited.contains_key(&next) {
+                    let step = if dx != 0 && dy != 0 { diagonal } else { 1_000_000 };
+                    queue.push(Reverse((cost + step, next.x, next.y)));
+                }
+            }
+        }
+
+        return None;
+    }
+
+    #[test]
+    fn test_jump_point_search_matches_brute_force() {
+        // Two staggered walls with gaps on opposite ends
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for y in -4..=3 {
+            changes.insert(Cell::new(2, y), true);
+        }
+        for y in -2..=5 {
+            changes.insert(Cell::new(6, y), true);
+        }
+        changes.insert(Cell::new(4, 1), true);
+        grid.change_cells(&changes);
+
+        let expander = Arc::new(JumpPointExpander::new(
+            Arc::new(grid),
+            0.25,
+            Arc::new(LineFollow::new(1.0).unwrap()),
+            Arc::new(DurationCostCalculator),
+        ));
+
+        let planner = make_planner(expander.clone(), Arc::new(a_star::Algorithm));
+        for (start, goal) in [
+            (Cell::new(0, 0), Cell::new(9, 0)),
+            (Cell::new(-3, 6), Cell::new(8, -5)),
+            (Cell::new(4, 0), Cell::new(4, 3)),
+        ] {
+            let expected = brute_force_cost(&expander, start, goal).unwrap() as f64 / 1e6;
+            let mut progress = planner.plan(&start, goal).unwrap();
+            match progress.solve().unwrap() {
+                Status::Solved(solution) => {
+                    let motion = solution.motion().as_ref().unwrap();
+                    let goal_p = goal.to_center_point(1.0);
+                    assert!((motion.finish().position - goal_p).norm() < 1e-8);
+                    let actual = motion.duration().as_secs_f64();
+                    assert!(
+                        (actual - expected).abs() < 1e-4,
+                        "Expected {expected}, found {actual} from {start:?} to {goal:?}",
+                    );
+                }
+                Status::Impossible | Status::Incomplete => {
+                    panic!("The search failed to find a path from {start:?} to {goal:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unreachable_goal() {
+        // Enclose the goal in a ring of occupied cells
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        for (dx, dy) in NEIGHBORS {
+            changes.insert(Cell::new(5 + dx, 5 + dy), true);
+        }
+        grid.change_cells(&changes);
+
+        let expander = JumpPointExpander::new(
+            Arc::new(grid),
+            0.25,
+            Arc::new(LineFollow::new(1.0).unwrap()),
+            Arc::new(DurationCostCalculator),
+        );
+
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner.plan(&Cell::new(0, 0), Cell::new(5, 5)).unwrap();
+        assert!(matches!(progress.solve().unwrap(), Status::Impossible));
+    }
+}
@@ -25,6 +25,7 @@ pub type Point = nalgebra::geometry::Point2<f64>;
 pub type Vector = nalgebra::Vector2<f64>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cell {
     pub x: i64,
     pub y: i64,
@@ -39,6 +40,9 @@ impl Cell {
     /// Get the cell that this point is inside of. Points that are perfectly on
     /// the edge between two cells will be biased towards the cell with the
     /// higher index value.
+    ///
+    /// The point must be expressed relative to the origin of the grid. Use
+    /// [`Grid::cell_of`] to find the cell of a point in the world frame.
     pub fn from_point(p: Point, cell_size: f64) -> Self {
         Self {
             x: (p.x / cell_size).floor() as i64,
@@ -208,9 +212,8 @@ type ConfirmedChanges = Vec<(Cell, bool)>;
 type ChangedCorners = Vec<(Cell, CornerStatus)>;
 
 pub trait Grid: std::fmt::Debug {
-    type OccupiedIterator<'a>: IntoIterator<Item = &'a Cell>
+    type OccupiedIterator<'a>: IntoIterator<Item = Cell>
     where
-        Cell: 'a,
         Self: 'a;
 
     type CornerIterator<'a>: IntoIterator<Item = (&'a Cell, &'a CornerStatus)>
@@ -227,6 +230,27 @@ pub trait Grid: std::fmt::Debug {
     /// Get the size (width and height) of a cell.
     fn cell_size(&self) -> f64;
 
+    /// Get the location of the bottom left corner of cell (0, 0). Grids that
+    /// cannot be moved keep cell (0, 0) at the origin of the world.
+    fn origin(&self) -> Point {
+        return Point::origin();
+    }
+
+    /// Get the cell that contains a point.
+    fn cell_of(&self, p: &Point) -> Cell {
+        return Cell::from_point(*p - self.origin().coords, self.cell_size());
+    }
+
+    /// Get the point at the center of a cell.
+    fn cell_center(&self, cell: &Cell) -> Point {
+        return cell.to_center_point(self.cell_size()) + self.origin().coords;
+    }
+
+    /// Get the point at the bottom left corner of a cell.
+    fn cell_bottom_left(&self, cell: &Cell) -> Point {
+        return cell.to_bottom_left_point(self.cell_size()) + self.origin().coords;
+    }
+
     /// Check if a single cell is occupied.
     fn is_occupied(&self, cell: &Cell) -> bool;
 
@@ -255,6 +279,52 @@ pub trait Grid: std::fmt::Debug {
 
 type BlockedBy = Option<Cell>;
 
+/// A report of everything that changed in a [`Visibility`] graph after a call
+/// to [`Visibility::change_cells`]. Each visibility edge is reported once, with
+/// its two points in no particular order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VisibilityChanges {
+    /// The cells whose occupancy actually changed, along with their new
+    /// occupancy value.
+    pub cells: ConfirmedChanges,
+    /// Corner points that were added to the visibility graph.
+    pub added_points: Vec<Cell>,
+    /// Corner points that were removed from the visibility graph along with
+    /// all of their edges. A point can appear here and in added_points if its
+    /// corners were rearranged, in which case its edges were all recomputed.
+    pub removed_points: Vec<Cell>,
+    /// Existing points that are now too close to an occupied cell for the
+    /// agent to stand on.
+    pub blocked_points: Vec<Cell>,
+    /// Existing points that were blocked but are now clear.
+    pub unblocked_points: Vec<Cell>,
+    /// Edges that were created between two points, along with the cell that
+    /// blocks the edge, if any.
+    pub created_edges: Vec<(Cell, Cell, BlockedBy)>,
+    /// Edges that were removed, either because one of their points was
+    /// removed or because the points can no longer see each other's corners.
+    pub removed_edges: Vec<(Cell, Cell)>,
+    /// Existing edges that became blocked by an occupied cell.
+    pub blocked_edges: Vec<(Cell, Cell)>,
+    /// Existing edges that were blocked but are now clear.
+    pub unblocked_edges: Vec<(Cell, Cell)>,
+}
+
+impl VisibilityChanges {
+    /// True if nothing changed at all.
+    pub fn is_empty(&self) -> bool {
+        return self.cells.is_empty()
+            && self.added_points.is_empty()
+            && self.removed_points.is_empty()
+            && self.blocked_points.is_empty()
+            && self.unblocked_points.is_empty()
+            && self.created_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.blocked_edges.is_empty()
+            && self.unblocked_edges.is_empty();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Visibility<G: Grid> {
     grid: G,
@@ -290,10 +360,10 @@ impl<G: Grid> Visibility<G> {
         return output;
     }
 
-    /// Change the values for a set of cells.
-    // TODO(MXG): Have this return more detailed information about what changed
-    // instead of only returning a boolean.
-    pub fn change_cells(&mut self, changes: &HashMap<Cell, bool>) -> bool {
+    /// Change the values for a set of cells. Get back a report of how the
+    /// visibility graph changed, which can be used to invalidate anything that
+    /// depends on the affected points and edges.
+    pub fn change_cells(&mut self, changes: &HashMap<Cell, bool>) -> VisibilityChanges {
         let (confirmed_changes, corner_changes) = self.grid.change_cells(changes);
         if confirmed_changes.is_empty() {
             // If none of the cells actually changed, then no corners should
@@ -302,10 +372,10 @@ impl<G: Grid> Visibility<G> {
 
             // If no changes actually happened, then don't bother with the rest
             // of this function.
-            return false;
+            return VisibilityChanges::default();
         }
 
-        return Self::update_corners(
+        let mut report = Self::update_corners(
             &self.grid,
             &confirmed_changes,
             corner_changes.iter().map(|(c, s)| (c, s)),
@@ -314,6 +384,8 @@ impl<G: Grid> Visibility<G> {
             &mut self.points,
             &mut self.edges,
         );
+        report.cells = confirmed_changes;
+        return report;
     }
 
     pub fn iter_points(&self) -> impl Iterator<Item = (&Cell, &CornerStatus)> {
@@ -364,9 +436,8 @@ impl<G: Grid> Visibility<G> {
                                     return false;
                                 }
 
-                                let cell_size = self.grid.cell_size();
-                                let p0 = cell.to_center_point(cell_size);
-                                let p1 = v_cell.to_center_point(cell_size);
+                                let p0 = self.grid.cell_center(&cell);
+                                let p1 = self.grid.cell_center(v_cell);
                                 return self
                                     .grid
                                     .is_sweep_occupied(p0, p1, 2.0 * self.agent_radius)
@@ -384,7 +455,7 @@ impl<G: Grid> Visibility<G> {
             .filter(|of_cell| {
                 self.grid()
                     .is_square_occupied(
-                        of_cell.to_center_point(self.grid().cell_size()),
+                        self.grid().cell_center(of_cell),
                         2.0 * self.agent_radius,
                     )
                     .is_none()
@@ -398,13 +469,12 @@ impl<G: Grid> Visibility<G> {
                         .filter(move |j| !(i == 0 && *j == 0))
                         .filter_map(move |j| {
                             // dbg!(j);
-                            let cell_size = self.grid().cell_size();
                             let neighbor = of_cell.shifted(i, j);
                             if self
                                 .grid()
                                 .is_sweep_occupied(
-                                    of_cell.to_center_point(cell_size),
-                                    neighbor.to_center_point(cell_size),
+                                    self.grid().cell_center(&of_cell),
+                                    self.grid().cell_center(&neighbor),
                                     2.0 * self.agent_radius(),
                                 )
                                 .is_none()
@@ -463,8 +533,8 @@ impl<G: Grid> Visibility<G> {
         cell_shift: i64,
         points: &mut HashMap<Cell, (BlockedBy, CornerStatus)>,
         edges: &mut HashMap<Cell, HashMap<Cell, BlockedBy>>,
-    ) -> bool {
-        let mut changed = !confirmed_changes.is_empty();
+    ) -> VisibilityChanges {
+        let mut report = VisibilityChanges::default();
         let mut new_points = Vec::new();
         for (base_cell, status) in corners {
             for (corner, valid) in status {
@@ -477,7 +547,7 @@ impl<G: Grid> Visibility<G> {
                             // If this corner point is currently vacant, then we
                             // need to check whether it has any blockers.
                             let blocked_by = grid.is_square_occupied(
-                                cell.to_center_point(grid.cell_size()),
+                                grid.cell_center(&cell),
                                 2.0 * agent_radius,
                             );
 
@@ -486,6 +556,7 @@ impl<G: Grid> Visibility<G> {
                                 .1
                                 .set(corner, true);
                             new_points.push(cell);
+                            report.added_points.push(cell);
                         }
                         hash_map::Entry::Occupied(mut entry) => {
                             entry.get_mut().1.set(corner, true);
@@ -499,6 +570,7 @@ impl<G: Grid> Visibility<G> {
                             for other in remove_connections {
                                 edges.entry(cell).or_default().remove(&other);
                                 edges.entry(other).or_default().remove(&cell);
+                                report.removed_edges.push((cell, other));
                             }
                         }
                     }
@@ -513,7 +585,7 @@ impl<G: Grid> Visibility<G> {
                                 // This entry is no longer a corner, so we need
                                 // to remove it.
                                 entry.remove();
-                                changed = true;
+                                report.removed_points.push(cell);
                                 if let Some(remove_from) = edges.remove(&cell) {
                                     for other in remove_from {
                                         edges
@@ -522,6 +594,7 @@ impl<G: Grid> Visibility<G> {
                                                 "Missing complementary edge in visibility graph",
                                             )
                                             .remove(&cell);
+                                        report.removed_edges.push((cell, other.0));
                                     }
                                 }
                             } else {
@@ -537,6 +610,9 @@ impl<G: Grid> Visibility<G> {
             }
         }
 
+        // Remember whether each point was blocked before it gets modified so we
+        // only report points whose status actually flipped.
+        let mut initially_blocked: HashMap<Cell, bool> = HashMap::new();
         let visibility_point_reach = cell_shift - 1;
         for (changed_cell, changed_cell_occupied) in confirmed_changes {
             // Check if any of these changed cells will cause a change in
@@ -550,8 +626,8 @@ impl<G: Grid> Visibility<G> {
                         if dist.0.abs() <= visibility_point_reach
                             && dist.1.abs() <= visibility_point_reach
                         {
+                            initially_blocked.entry(*point_cell).or_insert(false);
                             *point_blocked_by = Some(*changed_cell);
-                            changed = true;
                         }
                     }
                 } else {
@@ -562,18 +638,28 @@ impl<G: Grid> Visibility<G> {
                             // If the changed cell was known to be blocking this
                             // visibility point, then the visibility point might
                             // be unoccupied now, but we need to test that.
+                            initially_blocked.entry(*point_cell).or_insert(true);
                             *point_blocked_by = grid.is_square_occupied(
-                                point_cell.to_center_point(grid.cell_size()),
+                                grid.cell_center(point_cell),
                                 2.0 * agent_radius,
                             );
-
-                            changed |= point_blocked_by.is_none();
                         }
                     }
                 }
             }
         }
 
+        for (cell, was_blocked) in initially_blocked {
+            if let Some((blocked_by, _)) = points.get(&cell) {
+                if was_blocked && blocked_by.is_none() {
+                    report.unblocked_points.push(cell);
+                } else if !was_blocked && blocked_by.is_some() {
+                    report.blocked_points.push(cell);
+                }
+            }
+        }
+
+        let mut created_pairs = HashSet::new();
         for cell in new_points {
             // Note: It is possible for an entry in new_points to no longer be
             // in the points map because this may happen:
@@ -605,8 +691,8 @@ impl<G: Grid> Visibility<G> {
                     }
 
                     let blocked_by = grid.is_sweep_occupied(
-                        cell.to_center_point(grid.cell_size()),
-                        other.to_center_point(grid.cell_size()),
+                        grid.cell_center(&cell),
+                        grid.cell_center(other),
                         2.0 * agent_radius,
                     );
 
@@ -621,7 +707,8 @@ impl<G: Grid> Visibility<G> {
                         }
                     }
                     new_connections.push((*other, blocked_by));
-                    changed = true;
+                    report.created_edges.push((cell, *other, blocked_by));
+                    created_pairs.insert((cell, *other));
                 }
 
                 for (other_cell, blocked_by) in new_connections {
@@ -636,10 +723,15 @@ impl<G: Grid> Visibility<G> {
         if confirmed_changes.is_empty() {
             // Skip the triangular-for-loop below if there are no changes to
             // consider because the inner-most loop will be empty anyway.
-            return changed;
+            return report;
         }
 
         triangular_for(points.iter(), |(cell_i, _), (cell_j, _)| {
+            let initial_blocker = edges
+                .get(*cell_i)
+                .and_then(|connections| connections.get(cell_j))
+                .copied();
+            let mut final_blocker = initial_blocker;
             for (changed_cell, occupied) in confirmed_changes {
                 let mut changed_blocker: Option<Option<Cell>> = None;
                 if let hash_map::Entry::Occupied(entry) =
@@ -654,21 +746,19 @@ impl<G: Grid> Visibility<G> {
                         if line.passes_near_cell(changed_cell, grid.cell_size(), agent_radius) {
                             entry.insert(Some(*changed_cell));
                             changed_blocker = Some(Some(*changed_cell));
-                            changed = true;
                         }
                     } else {
                         // Check if this entry was blocked by this newly opened cell
                         if let Some(blocked_by) = *entry.get() {
                             if blocked_by == *changed_cell {
                                 let new_blocker = grid.is_sweep_occupied(
-                                    cell_i.to_center_point(grid.cell_size()),
-                                    cell_j.to_center_point(grid.cell_size()),
+                                    grid.cell_center(cell_i),
+                                    grid.cell_center(cell_j),
                                     2.0 * agent_radius,
                                 );
 
                                 entry.insert(new_blocker);
                                 changed_blocker = Some(new_blocker);
-                                changed = true;
                             }
                         }
                     }
@@ -679,11 +769,34 @@ impl<G: Grid> Visibility<G> {
                         .entry(*cell_j)
                         .or_default()
                         .insert(**cell_i, new_blocker);
+                    final_blocker = Some(new_blocker);
+                }
+            }
+
+            if created_pairs.contains(&(**cell_i, *cell_j))
+                || created_pairs.contains(&(*cell_j, **cell_i))
+            {
+                // Edges created during this change are reported in
+                // created_edges with whatever blocker they end up with.
+                return;
+            }
+
+            if let (Some(initial_blocker), Some(final_blocker)) = (initial_blocker, final_blocker) {
+                if initial_blocker.is_none() && final_blocker.is_some() {
+                    report.blocked_edges.push((**cell_i, *cell_j));
+                } else if initial_blocker.is_some() && final_blocker.is_none() {
+                    report.unblocked_edges.push((**cell_i, *cell_j));
                 }
             }
         });
 
-        return changed;
+        for created in &mut report.created_edges {
+            if let Some(blocked_by) = edges.get(&created.0).and_then(|c| c.get(&created.1)) {
+                created.2 = *blocked_by;
+            }
+        }
+
+        return report;
     }
 
     fn calculate_cell_shift(agent_radius: f64, cell_size: f64) -> i64 {
@@ -789,6 +902,169 @@ impl<'a, G: Grid> Iterator for VisibilityEdgeIter<'a, G> {
 
 pub mod sparse_grid;
 pub use sparse_grid::SparseGrid;
+pub mod dense_grid;
+pub use dense_grid::DenseGrid;
+pub mod quadtree;
+pub use quadtree::{QuadBlock, QuadtreeGraph, QuadtreeGrid};
+#[cfg(feature = "ros_map")]
+pub mod ros_map;
+#[cfg(feature = "ros_map")]
+pub use ros_map::{RosMap, RosMapError, RosMapMetadata};
 pub mod graph;
 pub use graph::VisibilityGraph;
+pub mod constraint;
+pub use constraint::{Footprint, OccupancyConstraint};
+pub mod inflation;
+pub use inflation::{Clearance, DistanceTransform, InflatedCostCalculator, InflatedCostError};
+pub mod distance_field;
+pub use distance_field::DistanceField;
+
+pub mod smoothing;
+pub use smoothing::Smoother;
+
+pub mod grid_search;
+pub use grid_search::{GridSearchInitError, GridSearchSolution};
+
+pub mod any_angle;
+pub use any_angle::AnyAngleExpander;
+
+pub mod jump_point;
+pub use jump_point::JumpPointExpander;
 mod util;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Snapshot = (HashMap<Cell, bool>, HashMap<(Cell, Cell), bool>);
+
+    fn ordered(a: Cell, b: Cell) -> (Cell, Cell) {
+        if (a.x, a.y) < (b.x, b.y) {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    fn snapshot<G: Grid>(visibility: &Visibility<G>) -> Snapshot {
+        let points = visibility
+            .debug_points()
+            .iter()
+            .map(|(cell, (blocked_by, _))| (*cell, blocked_by.is_some()))
+            .collect();
+
+        let mut edges = HashMap::new();
+        for (cell_i, connections) in visibility.debug_edges() {
+            for (cell_j, blocked_by) in connections {
+                edges.insert(ordered(*cell_i, *cell_j), blocked_by.is_some());
+            }
+        }
+
+        return (points, edges);
+    }
+
+    fn check_report(before: &Snapshot, after: &Snapshot, report: &VisibilityChanges) {
+        let cells = |cells: &Vec<Cell>| cells.iter().copied().collect::<HashSet<_>>();
+        let pairs = |edges: &Vec<(Cell, Cell)>| {
+            edges
+                .iter()
+                .map(|(a, b)| ordered(*a, *b))
+                .collect::<HashSet<_>>()
+        };
+        let flipped_points = |blocked: bool| {
+            before
+                .0
+                .iter()
+                .filter(|(cell, was)| **was != blocked && after.0.get(*cell) == Some(&blocked))
+                .map(|(cell, _)| *cell)
+                .collect::<HashSet<_>>()
+        };
+        let flipped_edges = |blocked: bool| {
+            before
+                .1
+                .iter()
+                .filter(|(edge, was)| **was != blocked && after.1.get(*edge) == Some(&blocked))
+                .map(|(edge, _)| *edge)
+                .collect::<HashSet<_>>()
+        };
+
+        let added: HashSet<_> = after
+            .0
+            .keys()
+            .filter(|cell| !before.0.contains_key(*cell))
+            .copied()
+            .collect();
+        assert_eq!(cells(&report.added_points), added);
+
+        let removed: HashSet<_> = before
+            .0
+            .keys()
+            .filter(|cell| !after.0.contains_key(*cell))
+            .copied()
+            .collect();
+        assert_eq!(cells(&report.removed_points), removed);
+
+        assert_eq!(cells(&report.blocked_points), flipped_points(true));
+        assert_eq!(cells(&report.unblocked_points), flipped_points(false));
+
+        let created: HashSet<_> = after
+            .1
+            .keys()
+            .filter(|edge| !before.1.contains_key(*edge))
+            .copied()
+            .collect();
+        assert_eq!(
+            report
+                .created_edges
+                .iter()
+                .map(|(a, b, _)| ordered(*a, *b))
+                .collect::<HashSet<_>>(),
+            created
+        );
+        for (a, b, blocked_by) in &report.created_edges {
+            assert_eq!(after.1[&ordered(*a, *b)], blocked_by.is_some());
+        }
+
+        let removed: HashSet<_> = before
+            .1
+            .keys()
+            .filter(|edge| !after.1.contains_key(*edge))
+            .copied()
+            .collect();
+        assert_eq!(pairs(&report.removed_edges), removed);
+
+        assert_eq!(pairs(&report.blocked_edges), flipped_edges(true));
+        assert_eq!(pairs(&report.unblocked_edges), flipped_edges(false));
+    }
+
+    #[test]
+    fn test_visibility_change_report() {
+        let mut grid = SparseGrid::new(1.0);
+        grid.change_cells(&[(Cell::new(0, 0), true), (Cell::new(10, 0), true)].into());
+        let mut visibility = Visibility::new(grid, 0.25);
+
+        // Drop an obstacle into the middle of the line of sight between the
+        // two existing obstacles.
+        let before = snapshot(&visibility);
+        let report = visibility.change_cells(&[(Cell::new(5, 1), true)].into());
+        let after = snapshot(&visibility);
+        check_report(&before, &after, &report);
+        assert_eq!(report.cells, vec![(Cell::new(5, 1), true)]);
+        assert!(!report.added_points.is_empty());
+        assert!(!report.created_edges.is_empty());
+        assert!(!report.blocked_edges.is_empty());
+
+        // Nothing happens if the cell is already occupied
+        let report = visibility.change_cells(&[(Cell::new(5, 1), true)].into());
+        assert!(report.is_empty());
+
+        // Taking the obstacle away restores the original graph
+        let report = visibility.change_cells(&[(Cell::new(5, 1), false)].into());
+        let restored = snapshot(&visibility);
+        check_report(&after, &restored, &report);
+        assert!(!report.removed_points.is_empty());
+        assert!(!report.removed_edges.is_empty());
+        assert!(!report.unblocked_edges.is_empty());
+        assert_eq!(restored, before);
+    }
+}
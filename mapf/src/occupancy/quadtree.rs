@@ -0,0 +1,702 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{
+    util::update_corner_status, Cell, ChangedCorners, ConfirmedChanges, CornerStatus, Grid, Point,
+    Vector,
+};
+use crate::graph::{Edge, Graph};
+use std::{
+    collections::{hash_map, HashMap, HashSet},
+    sync::Arc,
+};
+
+/// A square block of cells that is `2^level` cells wide, with `lower` as the
+/// cell in its bottom left corner. Every node of a quadtree covers one block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuadBlock {
+    pub lower: Cell,
+    pub level: u32,
+}
+
+impl QuadBlock {
+    /// The number of cells along each side of the block.
+    pub fn width(&self) -> i64 {
+        return 1 << self.level;
+    }
+
+    /// The cell in the top right corner of the block.
+    pub fn upper(&self) -> Cell {
+        return self.lower.shifted(self.width() - 1, self.width() - 1);
+    }
+
+    pub fn contains(&self, cell: &Cell) -> bool {
+        let w = self.width();
+        return self.lower.x <= cell.x
+            && cell.x < self.lower.x + w
+            && self.lower.y <= cell.y
+            && cell.y < self.lower.y + w;
+    }
+
+    fn child(&self, index: usize) -> QuadBlock {
+        let half = self.width() / 2;
+        return QuadBlock {
+            lower: self
+                .lower
+                .shifted((index % 2) as i64 * half, (index / 2) as i64 * half),
+            level: self.level - 1,
+        };
+    }
+
+    fn quadrant_of(&self, cell: &Cell) -> usize {
+        let half = self.width() / 2;
+        let dx = (cell.x - self.lower.x >= half) as usize;
+        let dy = (cell.y - self.lower.y >= half) as usize;
+        return dx + 2 * dy;
+    }
+
+    /// The bottom left and top right corners of the block, relative to the
+    /// origin of the grid.
+    fn extents(&self, cell_size: f64) -> (Point, Point) {
+        let w = self.width() as f64 * cell_size;
+        let min = self.lower.to_bottom_left_point(cell_size);
+        return (min, min + Vector::new(w, w));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Quad {
+    Free,
+    Occupied,
+    Split(Box<[Quad; 4]>),
+}
+
+impl Quad {
+    fn leaf(occupied: bool) -> Self {
+        if occupied {
+            Quad::Occupied
+        } else {
+            Quad::Free
+        }
+    }
+
+    fn as_leaf(&self) -> Option<bool> {
+        match self {
+            Quad::Free => Some(false),
+            Quad::Occupied => Some(true),
+            Quad::Split(_) => None,
+        }
+    }
+
+    fn get(&self, block: QuadBlock, cell: &Cell) -> (QuadBlock, bool) {
+        let (mut node, mut block) = (self, block);
+        loop {
+            match node {
+                Quad::Free => return (block, false),
+                Quad::Occupied => return (block, true),
+                Quad::Split(children) => {
+                    let i = block.quadrant_of(cell);
+                    node = &children[i];
+                    block = block.child(i);
+                }
+            }
+        }
+    }
+
+    /// Set the value of a single cell, splitting and merging blocks as needed.
+    /// Returns true if the value changed.
+    fn set(&mut self, block: QuadBlock, cell: &Cell, occupied: bool) -> bool {
+        if self.as_leaf() == Some(occupied) {
+            return false;
+        }
+
+        if block.level == 0 {
+            *self = Quad::leaf(occupied);
+            return true;
+        }
+
+        if let Some(value) = self.as_leaf() {
+            *self = Quad::Split(Box::new(std::array::from_fn(|_| Quad::leaf(value))));
+        }
+
+        let children = match self {
+            Quad::Split(children) => children,
+            _ => unreachable!(),
+        };
+
+        let i = block.quadrant_of(cell);
+        let changed = children[i].set(block.child(i), cell, occupied);
+        if let Some(value) = children[0].as_leaf() {
+            if children.iter().all(|c| c.as_leaf() == Some(value)) {
+                *self = Quad::leaf(value);
+            }
+        }
+
+        return changed;
+    }
+
+    /// Find an occupied cell inside of a block that passes the overlap test.
+    fn find(&self, block: QuadBlock, overlaps: &impl Fn(&QuadBlock) -> bool) -> Option<Cell> {
+        if !overlaps(&block) {
+            return None;
+        }
+
+        match self {
+            Quad::Free => None,
+            Quad::Occupied => {
+                if block.level == 0 {
+                    return Some(block.lower);
+                }
+
+                // Narrow down to a single cell of the occupied block
+                (0..4).find_map(|i| self.find(block.child(i), overlaps))
+            }
+            Quad::Split(children) => {
+                (0..4).find_map(|i| children[i].find(block.child(i), overlaps))
+            }
+        }
+    }
+
+    fn collect_leaves(
+        &self,
+        block: QuadBlock,
+        min: &Cell,
+        max: &Cell,
+        leaves: &mut Vec<(QuadBlock, bool)>,
+    ) {
+        let upper = block.upper();
+        if block.lower.x > max.x || upper.x < min.x || block.lower.y > max.y || upper.y < min.y {
+            return;
+        }
+
+        match self {
+            Quad::Free => leaves.push((block, false)),
+            Quad::Occupied => leaves.push((block, true)),
+            Quad::Split(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    child.collect_leaves(block.child(i), min, max, leaves);
+                }
+            }
+        }
+    }
+}
+
+/// An occupancy grid backed by a quadtree. Square blocks of cells that are
+/// entirely free or entirely occupied are stored as a single leaf, so large
+/// open regions and large obstacles cost very little memory, while small
+/// details can still be described down to the size of a single cell.
+///
+/// The tree grows automatically to cover any cell that becomes occupied.
+/// Everything outside of the tree is free.
+#[derive(Debug, Clone)]
+pub struct QuadtreeGrid {
+    cell_size: f64,
+    origin: Point,
+    bounds: QuadBlock,
+    root: Quad,
+    corners: HashMap<Cell, CornerStatus>,
+}
+
+impl QuadtreeGrid {
+    /// Create a new empty grid where nothing is occupied. `cell_size` is the
+    /// size of the smallest blocks that the tree can describe.
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            origin: Point::origin(),
+            bounds: QuadBlock {
+                lower: Cell::new(0, 0),
+                level: 0,
+            },
+            root: Quad::Free,
+            corners: HashMap::default(),
+        }
+    }
+
+    /// Set the location of the bottom left corner of cell (0, 0). Cells are
+    /// always laid out relative to this origin, so changing it moves the
+    /// whole occupancy without modifying any cells.
+    pub fn set_origin(&mut self, origin: Point) {
+        self.origin = origin;
+    }
+
+    /// The block covered by the root of the tree.
+    pub fn bounds(&self) -> QuadBlock {
+        return self.bounds;
+    }
+
+    /// Get every leaf of the tree along with whether it is occupied.
+    pub fn leaves(&self) -> Vec<(QuadBlock, bool)> {
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(
+            self.bounds,
+            &self.bounds.lower,
+            &self.bounds.upper(),
+            &mut leaves,
+        );
+        return leaves;
+    }
+
+    /// Get the leaf that contains a cell along with whether it is occupied,
+    /// or None if the cell is outside of the tree.
+    pub fn leaf_containing(&self, cell: &Cell) -> Option<(QuadBlock, bool)> {
+        if !self.bounds.contains(cell) {
+            return None;
+        }
+
+        return Some(self.root.get(self.bounds, cell));
+    }
+
+    /// Get every leaf that shares an edge or a corner with the given block.
+    pub fn leaves_touching(&self, block: &QuadBlock) -> Vec<(QuadBlock, bool)> {
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(
+            self.bounds,
+            &block.lower.shifted(-1, -1),
+            &block.upper().shifted(1, 1),
+            &mut leaves,
+        );
+        leaves.retain(|(leaf, _)| leaf != block);
+        return leaves;
+    }
+
+    fn grow_to_contain(&mut self, cell: &Cell) {
+        while !self.bounds.contains(cell) {
+            let width = self.bounds.width();
+            let dx = (cell.x < self.bounds.lower.x) as i64;
+            let dy = (cell.y < self.bounds.lower.y) as i64;
+            let old_root = std::mem::replace(&mut self.root, Quad::Free);
+            if old_root != Quad::Free {
+                let mut children: [Quad; 4] = std::array::from_fn(|_| Quad::Free);
+                children[(dx + 2 * dy) as usize] = old_root;
+                self.root = Quad::Split(Box::new(children));
+            }
+
+            self.bounds = QuadBlock {
+                lower: self.bounds.lower.shifted(-dx * width, -dy * width),
+                level: self.bounds.level + 1,
+            };
+        }
+    }
+
+    fn update_cell(&mut self, cell: &Cell, occupied: bool) -> bool {
+        if occupied {
+            self.grow_to_contain(cell);
+        } else if !self.bounds.contains(cell) {
+            return false;
+        }
+
+        return self.root.set(self.bounds, cell, occupied);
+    }
+
+    fn update_corner_status(&mut self, delta: &mut ChangedCorners, cell: &Cell) {
+        let (root, bounds) = (&self.root, self.bounds);
+        update_corner_status(&mut self.corners, delta, cell, |c| {
+            bounds.contains(c) && root.get(bounds, c).1
+        });
+    }
+
+    fn find(&self, overlaps: impl Fn(&QuadBlock) -> bool) -> Option<Cell> {
+        return self.root.find(self.bounds, &overlaps);
+    }
+}
+
+impl Grid for QuadtreeGrid {
+    type OccupiedIterator<'a> = QuadtreeOccupiedCells<'a>;
+    type CornerIterator<'a> = hash_map::Iter<'a, Cell, CornerStatus>;
+
+    fn change_cells(
+        &mut self,
+        changes: &HashMap<Cell, bool>,
+    ) -> (ConfirmedChanges, ChangedCorners) {
+        let mut confirmed_changes = Vec::with_capacity(changes.len());
+        for (cell, occupied) in changes {
+            if self.update_cell(cell, *occupied) {
+                confirmed_changes.push((*cell, *occupied));
+            }
+        }
+
+        let mut delta = ChangedCorners::default();
+        let mut checked = HashSet::new();
+        for (check, _) in &confirmed_changes {
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    let cell = check.shifted(i, j);
+                    if checked.insert(cell) {
+                        self.update_corner_status(&mut delta, &cell);
+                    }
+                }
+            }
+        }
+
+        return (confirmed_changes, delta);
+    }
+
+    fn cell_size(&self) -> f64 {
+        return self.cell_size;
+    }
+
+    fn origin(&self) -> Point {
+        return self.origin;
+    }
+
+    fn is_occupied(&self, cell: &Cell) -> bool {
+        return self.bounds.contains(cell) && self.root.get(self.bounds, cell).1;
+    }
+
+    fn occupied_cells<'b>(&'b self) -> Self::OccupiedIterator<'b> {
+        return QuadtreeOccupiedCells {
+            stack: vec![(&self.root, self.bounds)],
+            block: None,
+        };
+    }
+
+    fn corners<'b>(&'b self) -> Self::CornerIterator<'b> {
+        return self.corners.iter();
+    }
+
+    fn is_point_occupied(&self, p: Point) -> Option<Cell> {
+        let cell = self.cell_of(&p);
+        if self.is_occupied(&cell) {
+            return Some(cell);
+        }
+
+        return None;
+    }
+
+    fn is_square_occupied(&self, p: Point, width: f64) -> Option<Cell> {
+        let p = p - self.origin.coords;
+        let d = width / 2.0;
+        let (min_p, max_p) = (p - Vector::new(d, d), p + Vector::new(d, d));
+        return self.find(|block| {
+            let (b_min, b_max) = block.extents(self.cell_size);
+            b_min.x < max_p.x && min_p.x < b_max.x && b_min.y < max_p.y && min_p.y < b_max.y
+        });
+    }
+
+    fn is_sweep_occupied(&self, p0: Point, p1: Point, width: f64) -> Option<Cell> {
+        let d = width / 2.0;
+        let dist = (p1 - p0).norm();
+        if dist < 1e-8 {
+            return self.is_point_occupied(p0);
+        }
+
+        let (p0, p1) = (p0 - self.origin.coords, p1 - self.origin.coords);
+        let u = (p1 - p0) / dist;
+        let n = Vector::new(-u.y, u.x);
+        let center = p0 + (p1 - p0) / 2.0;
+        let half_length = dist / 2.0;
+
+        // Separating axis test between the swept rectangle and each block
+        let rect_x = u.x.abs() * half_length + n.x.abs() * d;
+        let rect_y = u.y.abs() * half_length + n.y.abs() * d;
+        return self.find(|block| {
+            let (b_min, b_max) = block.extents(self.cell_size);
+            let half_width = (b_max.x - b_min.x) / 2.0;
+            let offset = center - (b_min + Vector::new(half_width, half_width));
+            let box_u = half_width * (u.x.abs() + u.y.abs());
+            let box_n = half_width * (n.x.abs() + n.y.abs());
+            offset.x.abs() < half_width + rect_x
+                && offset.y.abs() < half_width + rect_y
+                && offset.dot(&u).abs() < half_length + box_u
+                && offset.dot(&n).abs() < d + box_n
+        });
+    }
+}
+
+/// Iterates over every occupied cell of a QuadtreeGrid, expanding occupied
+/// blocks into their individual cells.
+pub struct QuadtreeOccupiedCells<'a> {
+    stack: Vec<(&'a Quad, QuadBlock)>,
+    /// An occupied block whose cells are being visited, and the index of the
+    /// next cell to visit.
+    block: Option<(QuadBlock, i64)>,
+}
+
+impl<'a> Iterator for QuadtreeOccupiedCells<'a> {
+    type Item = Cell;
+
+    fn next(&mut self) -> Option<Cell> {
+        loop {
+            if let Some((block, index)) = &mut self.block {
+                let w = block.width();
+                if *index < w * w {
+                    let cell = block.lower.shifted(*index % w, *index / w);
+                    *index += 1;
+                    return Some(cell);
+                }
+
+                self.block = None;
+            }
+
+            let (node, block) = self.stack.pop()?;
+            match node {
+                Quad::Free => {}
+                Quad::Occupied => self.block = Some((block, 0)),
+                Quad::Split(children) => {
+                    for (i, child) in children.iter().enumerate().rev() {
+                        self.stack.push((child, block.child(i)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A graph whose vertices are the free leaves of a quadtree. Each leaf is
+/// connected to every free leaf that shares an edge or a corner with it, as
+/// long as an agent of the given radius can travel in a straight line between
+/// the centers of the two leaves. Large open regions are crossed in a few big
+/// steps while narrow passages are still resolved cell by cell.
+///
+/// The graph only covers the region spanned by the tree.
+#[derive(Debug, Clone)]
+pub struct QuadtreeGraph {
+    grid: Arc<QuadtreeGrid>,
+    agent_radius: f64,
+}
+
+impl QuadtreeGraph {
+    pub fn new(grid: Arc<QuadtreeGrid>, agent_radius: f64) -> Self {
+        Self { grid, agent_radius }
+    }
+
+    pub fn grid(&self) -> &QuadtreeGrid {
+        return &self.grid;
+    }
+
+    pub fn agent_radius(&self) -> f64 {
+        return self.agent_radius;
+    }
+
+    /// Get the key of the free leaf that contains a point, if there is one.
+    pub fn block_at(&self, p: &Point) -> Option<QuadBlock> {
+        match self.grid.leaf_containing(&self.grid.cell_of(p)) {
+            Some((block, false)) => Some(block),
+            _ => None,
+        }
+    }
+
+    fn center(&self, block: &QuadBlock) -> Point {
+        let half = block.width() as f64 * self.grid.cell_size() / 2.0;
+        return self.grid.cell_bottom_left(&block.lower) + Vector::new(half, half);
+    }
+}
+
+impl Graph for QuadtreeGraph {
+    type Key = QuadBlock;
+    type Vertex = Point;
+    type Edge = (QuadBlock, QuadBlock);
+    type EdgeIter<'a> = impl Iterator<Item=(QuadBlock, QuadBlock)> + 'a where Self: 'a;
+
+    fn vertex(&self, key: QuadBlock) -> Option<Point> {
+        if self.grid.leaf_containing(&key.lower) != Some((key, false)) {
+            return None;
+        }
+
+        return Some(self.center(&key));
+    }
+
+    fn edges_from_vertex<'a>(&'a self, key: QuadBlock) -> Self::EdgeIter<'a> {
+        let neighbors = match self.vertex(key) {
+            Some(_) => self.grid.leaves_touching(&key),
+            None => Vec::new(),
+        };
+
+        let from_p = self.center(&key);
+        neighbors
+            .into_iter()
+            .filter(|(_, occupied)| !*occupied)
+            .filter(move |(to_block, _)| {
+                self.grid
+                    .is_sweep_occupied(from_p, self.center(to_block), 2.0 * self.agent_radius)
+                    .is_none()
+            })
+            .map(move |(to_block, _)| (key, to_block))
+    }
+}
+
+impl Edge<QuadBlock> for (QuadBlock, QuadBlock) {
+    fn from_vertex(&self) -> &QuadBlock {
+        &self.0
+    }
+
+    fn to_vertex(&self) -> &QuadBlock {
+        &self.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::occupancy::SparseGrid;
+    use std::collections::VecDeque;
+
+    fn make_changes() -> HashMap<Cell, bool> {
+        let mut changes = HashMap::new();
+        for x in -13i64..70 {
+            for y in -7i64..30 {
+                // Scattered cells mixed with solid blocks
+                let scattered = (x * 7 + y * 13).rem_euclid(11) < 2;
+                let block = (8..24).contains(&x) && (0..16).contains(&y);
+                if scattered || block {
+                    changes.insert(Cell::new(x, y), true);
+                }
+            }
+        }
+        changes
+    }
+
+    #[test]
+    fn test_quadtree_matches_sparse_grid() {
+        let mut sparse = SparseGrid::new(0.5);
+        let mut quadtree = QuadtreeGrid::new(0.5);
+        sparse.set_origin(Point::new(0.3, -1.1));
+        quadtree.set_origin(Point::new(0.3, -1.1));
+
+        let changes = make_changes();
+        let (sparse_confirmed, _) = sparse.change_cells(&changes);
+        let (quadtree_confirmed, _) = quadtree.change_cells(&changes);
+        assert_eq!(sparse_confirmed.len(), quadtree_confirmed.len());
+
+        // Open some holes, including one in the middle of the solid block
+        let mut removals = HashMap::new();
+        for x in 12..15 {
+            for y in 3..6 {
+                removals.insert(Cell::new(x, y), false);
+            }
+        }
+        removals.insert(Cell::new(-13, -7), false);
+        removals.insert(Cell::new(200, 200), false);
+        let (sparse_confirmed, sparse_delta) = sparse.change_cells(&removals);
+        let (quadtree_confirmed, quadtree_delta) = quadtree.change_cells(&removals);
+        assert_eq!(sparse_confirmed.len(), quadtree_confirmed.len());
+        assert_eq!(sparse_delta.len(), quadtree_delta.len());
+
+        let sparse_cells: HashSet<Cell> = sparse.occupied_cells().collect();
+        let quadtree_cells: HashSet<Cell> = quadtree.occupied_cells().collect();
+        assert_eq!(sparse_cells.len(), quadtree.occupied_cells().count());
+        assert_eq!(sparse_cells, quadtree_cells);
+
+        let sparse_corners: HashMap<Cell, CornerStatus> =
+            sparse.corners().map(|(c, s)| (*c, *s)).collect();
+        let quadtree_corners: HashMap<Cell, CornerStatus> =
+            quadtree.corners().map(|(c, s)| (*c, *s)).collect();
+        assert_eq!(sparse_corners, quadtree_corners);
+
+        // The solid parts of the block are stored as large leaves
+        assert!(quadtree
+            .leaves()
+            .iter()
+            .any(|(block, occupied)| *occupied && block.level >= 2));
+
+        // The offsets are chosen so that the queries do not line up exactly
+        // with the edges of cells, where the implementations are allowed to
+        // disagree about whether touching counts as occupied.
+        for i in 0..300 {
+            let x = -5.9877 + (i as f64 * 0.3719) % 40.0;
+            let y = -4.9913 + (i as f64 * 0.6131) % 18.0;
+            let p0 = Point::new(x, y);
+            let p1 = Point::new(
+                x + ((i % 7) as f64 - 3.0) * 1.0131,
+                y + ((i % 5) as f64 - 2.0) * 0.9871,
+            );
+            assert_eq!(sparse.is_point_occupied(p0), quadtree.is_point_occupied(p0));
+            for width in [0.1037, 0.4519, 1.3071] {
+                assert_eq!(
+                    sparse.is_square_occupied(p0, width).is_some(),
+                    quadtree.is_square_occupied(p0, width).is_some(),
+                );
+                let sweep = quadtree.is_sweep_occupied(p0, p1, width);
+                assert_eq!(
+                    sparse.is_sweep_occupied(p0, p1, width).is_some(),
+                    sweep.is_some()
+                );
+                if let Some(cell) = sweep {
+                    assert!(sparse.is_occupied(&cell));
+                }
+            }
+        }
+
+        // Clearing everything collapses the tree back into a single free leaf
+        let everything = sparse_cells.iter().map(|cell| (*cell, false)).collect();
+        quadtree.change_cells(&everything);
+        assert_eq!(quadtree.leaves(), vec![(quadtree.bounds(), false)]);
+        assert!(quadtree.corners().next().is_none());
+    }
+
+    fn reachable(graph: &QuadtreeGraph, from: QuadBlock, to: QuadBlock) -> bool {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(block) = queue.pop_front() {
+            if block == to {
+                return true;
+            }
+
+            for (_, next) in graph.edges_from_vertex(block) {
+                if visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        return false;
+    }
+
+    #[test]
+    fn test_quadtree_graph() {
+        let mut grid = QuadtreeGrid::new(0.25);
+        let mut changes = HashMap::new();
+        // The corners of the map give the tree its extent
+        changes.insert(Cell::new(0, 0), true);
+        changes.insert(Cell::new(63, 63), true);
+        // A wall with a gap that is one meter wide
+        for y in 0..64 {
+            if !(40..44).contains(&y) {
+                changes.insert(Cell::new(32, y), true);
+            }
+        }
+        grid.change_cells(&changes);
+        let grid = Arc::new(grid);
+
+        let small = QuadtreeGraph::new(grid.clone(), 0.2);
+        let start = small.block_at(&Point::new(2.0, 5.0)).unwrap();
+        let goal = small.block_at(&Point::new(14.0, 5.0)).unwrap();
+        assert!(small.vertex(start).is_some());
+        assert!(small.block_at(&Point::new(8.1, 3.0)).is_none());
+        assert!(reachable(&small, start, goal));
+
+        // Open regions are covered by leaves much larger than a cell, and the
+        // graph connects leaves of different sizes.
+        assert!(start.level >= 3);
+        assert!(small
+            .edges_from_vertex(start)
+            .into_iter()
+            .any(|(_, to)| to.level != start.level));
+
+        // An agent that is too large for the gap cannot get through
+        let large = QuadtreeGraph::new(grid.clone(), 0.55);
+        assert!(!reachable(&large, start, goal));
+
+        // Occupied leaves and blocks that are not leaves are not vertices
+        let wall = grid.leaf_containing(&Cell::new(32, 10)).unwrap().0;
+        assert!(small.vertex(wall).is_none());
+        assert!(small.edges_from_vertex(wall).next().is_none());
+        assert!(small.vertex(grid.bounds()).is_none());
+    }
+}
@@ -0,0 +1,714 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::{Cell, DenseGrid, Grid, Point, SparseGrid};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error as ThisError;
+
+/// The occupancy threshold that ROS uses when none is specified.
+pub const DEFAULT_OCCUPIED_THRESH: f64 = 0.65;
+
+/// The free threshold that ROS uses when none is specified.
+pub const DEFAULT_FREE_THRESH: f64 = 0.196;
+
+#[derive(ThisError, Debug)]
+pub enum RosMapError {
+    #[error("An error occurred while reading or writing a map file:\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error("An error occurred while parsing the map metadata:\n{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("An error occurred while decoding a png image:\n{0}")]
+    PngDecoding(#[from] png::DecodingError),
+    #[error("An error occurred while encoding a png image:\n{0}")]
+    PngEncoding(#[from] png::EncodingError),
+    #[error("The map image is malformed: {0}")]
+    MalformedImage(String),
+    #[error("Unsupported map image format: {0:?}")]
+    UnsupportedImage(PathBuf),
+    #[error("The map origin has a yaw of {0} radians, but only axis-aligned maps are supported")]
+    RotatedOrigin(f64),
+    #[error("Invalid map metadata: {0}")]
+    InvalidMetadata(String),
+}
+
+/// The contents of the YAML file that describes a ROS occupancy map, as used
+/// by map_server and map_saver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RosMapMetadata {
+    /// Path to the image file. Relative paths are relative to the directory of
+    /// the YAML file.
+    pub image: PathBuf,
+    /// Size of each pixel in meters.
+    pub resolution: f64,
+    /// Location of the bottom left pixel of the map as `[x, y, yaw]`.
+    pub origin: [f64; 3],
+    /// Whether the meaning of white and black pixels should be reversed.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_negate",
+        serialize_with = "serialize_negate"
+    )]
+    pub negate: bool,
+    /// Pixels with an occupancy probability above this are occupied.
+    pub occupied_thresh: f64,
+    /// Pixels with an occupancy probability below this are free.
+    pub free_thresh: f64,
+    /// One of `trinary` (the default), `scale`, or `raw`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+impl RosMapMetadata {
+    /// Create metadata with the default ROS thresholds.
+    pub fn new(image: impl Into<PathBuf>, resolution: f64, origin: Point) -> Self {
+        Self {
+            image: image.into(),
+            resolution,
+            origin: [origin.x, origin.y, 0.0],
+            negate: false,
+            occupied_thresh: DEFAULT_OCCUPIED_THRESH,
+            free_thresh: DEFAULT_FREE_THRESH,
+            mode: None,
+        }
+    }
+
+    fn is_raw(&self) -> bool {
+        return self.mode.as_deref() == Some("raw");
+    }
+
+    fn validate(&self) -> Result<(), RosMapError> {
+        if self.resolution.is_nan() || self.resolution <= 0.0 {
+            return Err(RosMapError::InvalidMetadata(format!(
+                "resolution must be positive, but got {}",
+                self.resolution
+            )));
+        }
+
+        if self.origin[2].abs() > 1e-6 {
+            return Err(RosMapError::RotatedOrigin(self.origin[2]));
+        }
+
+        let ordered = 0.0 < self.free_thresh
+            && self.free_thresh < self.occupied_thresh
+            && self.occupied_thresh < 1.0;
+        if !ordered {
+            return Err(RosMapError::InvalidMetadata(format!(
+                "thresholds must satisfy 0 < free_thresh ({}) < occupied_thresh ({}) < 1",
+                self.free_thresh, self.occupied_thresh,
+            )));
+        }
+
+        match self.mode.as_deref() {
+            None | Some("trinary") | Some("scale") | Some("raw") => {}
+            Some(other) => {
+                return Err(RosMapError::InvalidMetadata(format!(
+                    "unrecognized mode [{other}]"
+                )));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Classify an occupancy probability using the thresholds.
+    fn classify(&self, occupancy: f64) -> MapCell {
+        if occupancy > self.occupied_thresh {
+            return MapCell::Occupied;
+        }
+
+        if occupancy < self.free_thresh {
+            return MapCell::Free;
+        }
+
+        return MapCell::Unknown;
+    }
+
+    /// Classify a pixel, where `value` is the average of its color channels.
+    fn classify_pixel(&self, value: u8) -> MapCell {
+        if self.is_raw() {
+            if value > 100 {
+                return MapCell::Unknown;
+            }
+
+            return self.classify(value as f64 / 100.0);
+        }
+
+        let value = if self.negate { 255 - value } else { value };
+        return self.classify((255 - value) as f64 / 255.0);
+    }
+
+    /// Choose a pixel value that will be classified as the given cell.
+    fn pixel_for(&self, cell: MapCell) -> u8 {
+        if self.is_raw() {
+            return match cell {
+                MapCell::Free => 0,
+                MapCell::Occupied => 100,
+                MapCell::Unknown => 255,
+            };
+        }
+
+        let occupancy = match cell {
+            MapCell::Free => 0.0,
+            MapCell::Occupied => 1.0,
+            MapCell::Unknown => (self.free_thresh + self.occupied_thresh) / 2.0,
+        };
+
+        let value = 255 - (occupancy * 255.0).round() as u8;
+        return if self.negate { 255 - value } else { value };
+    }
+}
+
+fn deserialize_negate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    // map_server writes negate as 0 or 1, but some tools write a bool
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Negate {
+        Bool(bool),
+        Int(i64),
+    }
+
+    return Ok(match Negate::deserialize(deserializer)? {
+        Negate::Bool(value) => value,
+        Negate::Int(value) => value != 0,
+    });
+}
+
+fn serialize_negate<S: Serializer>(negate: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(i64::from(*negate))
+}
+
+/// The occupancy of a single pixel of a ROS map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapCell {
+    Free,
+    Occupied,
+    Unknown,
+}
+
+/// An occupancy map in the format used by ROS map_server: an image (PGM or
+/// PNG) together with a YAML file describing its resolution and origin.
+///
+/// Cell (0, 0) of the map is the bottom left pixel of the image, and the
+/// bottom left corner of that pixel is located at the origin of the metadata.
+#[derive(Debug, Clone)]
+pub struct RosMap {
+    pub metadata: RosMapMetadata,
+    width: usize,
+    height: usize,
+    /// Row-major cells, where row 0 is the bottom of the map
+    cells: Vec<MapCell>,
+}
+
+impl RosMap {
+    /// Load a map from the path of its YAML file.
+    pub fn load(yaml_path: impl AsRef<Path>) -> Result<Self, RosMapError> {
+        let yaml_path = yaml_path.as_ref();
+        let metadata: RosMapMetadata = serde_yaml::from_reader(File::open(yaml_path)?)?;
+        metadata.validate()?;
+
+        let image_path = resolve_image_path(yaml_path, &metadata.image);
+        let image = Image::load(&image_path)?;
+        let mut cells = Vec::with_capacity(image.width * image.height);
+        // Image rows are stored from the top down, but cells count upwards
+        for row in (0..image.height).rev() {
+            for col in 0..image.width {
+                cells.push(metadata.classify_pixel(image.value(row, col)));
+            }
+        }
+
+        return Ok(Self {
+            metadata,
+            width: image.width,
+            height: image.height,
+            cells,
+        });
+    }
+
+    /// Create a map that covers every occupied cell of a grid. Everything in
+    /// the map that is not occupied will be free.
+    pub fn from_grid<G: Grid>(grid: &G, image: impl Into<PathBuf>) -> Self {
+        let mut bounds: Option<(Cell, Cell)> = None;
+        for cell in grid.occupied_cells() {
+            bounds = Some(match bounds {
+                Some((lower, upper)) => (
+                    Cell::new(lower.x.min(cell.x), lower.y.min(cell.y)),
+                    Cell::new(upper.x.max(cell.x), upper.y.max(cell.y)),
+                ),
+                None => (cell, cell),
+            });
+        }
+
+        let (lower, upper) = bounds.unwrap_or((Cell::new(0, 0), Cell::new(0, 0)));
+        return Self::from_grid_region(
+            grid,
+            lower,
+            (upper.x - lower.x + 1) as usize,
+            (upper.y - lower.y + 1) as usize,
+            image,
+        );
+    }
+
+    /// Create a map from a rectangular region of a grid, where `lower` is the
+    /// cell in the bottom left corner of the region.
+    pub fn from_grid_region<G: Grid>(
+        grid: &G,
+        lower: Cell,
+        width: usize,
+        height: usize,
+        image: impl Into<PathBuf>,
+    ) -> Self {
+        let cells = (0..height as i64)
+            .flat_map(|y| (0..width as i64).map(move |x| lower.shifted(x, y)))
+            .map(|cell| {
+                if grid.is_occupied(&cell) {
+                    MapCell::Occupied
+                } else {
+                    MapCell::Free
+                }
+            })
+            .collect();
+
+        return Self {
+            metadata: RosMapMetadata::new(image, grid.cell_size(), grid.cell_bottom_left(&lower)),
+            width,
+            height,
+            cells,
+        };
+    }
+
+    /// Save the map to the given YAML path. The image will be saved next to
+    /// it according to `metadata.image`, as a PNG if its extension is `png`
+    /// and otherwise as a binary PGM.
+    pub fn save(&self, yaml_path: impl AsRef<Path>) -> Result<(), RosMapError> {
+        self.metadata.validate()?;
+        let yaml_path = yaml_path.as_ref();
+        let image_path = resolve_image_path(yaml_path, &self.metadata.image);
+
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for row in (0..self.height).rev() {
+            for col in 0..self.width {
+                pixels.push(self.metadata.pixel_for(self.cells[row * self.width + col]));
+            }
+        }
+
+        let file = BufWriter::new(File::create(&image_path)?);
+        if has_extension(&image_path, "png") {
+            let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&pixels)?;
+        } else {
+            let mut file = file;
+            write!(file, "P5\n{} {}\n255\n", self.width, self.height)?;
+            file.write_all(&pixels)?;
+            file.flush()?;
+        }
+
+        std::fs::write(yaml_path, serde_yaml::to_string(&self.metadata)?)?;
+        return Ok(());
+    }
+
+    /// The number of columns of cells in the map.
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    /// The number of rows of cells in the map.
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    /// The location of the bottom left corner of cell (0, 0).
+    pub fn origin(&self) -> Point {
+        return Point::new(self.metadata.origin[0], self.metadata.origin[1]);
+    }
+
+    /// Get the value of a cell, or None if it is outside of the map.
+    pub fn get(&self, cell: &Cell) -> Option<MapCell> {
+        if cell.x < 0 || cell.y < 0 {
+            return None;
+        }
+
+        let (x, y) = (cell.x as usize, cell.y as usize);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        return Some(self.cells[y * self.width + x]);
+    }
+
+    /// Convert the map into a sparse grid whose origin matches the map.
+    pub fn to_sparse_grid(&self, unknown_is_occupied: bool) -> SparseGrid {
+        let mut grid = SparseGrid::new(self.metadata.resolution);
+        grid.set_origin(self.origin());
+        grid.change_cells(&self.occupied_cells(unknown_is_occupied));
+        return grid;
+    }
+
+    /// Convert the map into a dense grid whose bounds and origin match the map.
+    pub fn to_dense_grid(&self, unknown_is_occupied: bool) -> DenseGrid {
+        let mut grid = DenseGrid::new(
+            self.metadata.resolution,
+            Cell::new(0, 0),
+            self.width,
+            self.height,
+        );
+        grid.set_origin(self.origin());
+        grid.change_cells(&self.occupied_cells(unknown_is_occupied));
+        return grid;
+    }
+
+    fn occupied_cells(&self, unknown_is_occupied: bool) -> HashMap<Cell, bool> {
+        let mut changes = HashMap::new();
+        for (i, value) in self.cells.iter().enumerate() {
+            let occupied = match value {
+                MapCell::Occupied => true,
+                MapCell::Unknown => unknown_is_occupied,
+                MapCell::Free => false,
+            };
+
+            if occupied {
+                let cell = Cell::new((i % self.width) as i64, (i / self.width) as i64);
+                changes.insert(cell, true);
+            }
+        }
+
+        return changes;
+    }
+}
+
+fn resolve_image_path(yaml_path: &Path, image: &Path) -> PathBuf {
+    // Joining an absolute image path will simply return the image path
+    return yaml_path
+        .parent()
+        .map(|dir| dir.join(image))
+        .unwrap_or_else(|| image.to_path_buf());
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    return path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case(extension))
+        .unwrap_or(false);
+}
+
+/// An 8-bit image with interleaved channels, stored from the top row down.
+struct Image {
+    width: usize,
+    height: usize,
+    channels: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn load(path: &Path) -> Result<Self, RosMapError> {
+        if has_extension(path, "png") {
+            return Self::load_png(path);
+        }
+
+        if has_extension(path, "pgm") {
+            return Self::parse_pgm(&std::fs::read(path)?);
+        }
+
+        return Err(RosMapError::UnsupportedImage(path.to_path_buf()));
+    }
+
+    fn load_png(path: &Path) -> Result<Self, RosMapError> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let channels = info.color_type.samples();
+        let (width, height) = (info.width as usize, info.height as usize);
+
+        // Rows may be padded, so repack them tightly
+        let row_size = image_size(width, 1, channels)?;
+        let mut data = Vec::with_capacity(image_size(width, height, channels)?);
+        for row in buffer.chunks(info.line_size).take(height) {
+            data.extend_from_slice(&row[..row_size]);
+        }
+
+        return Ok(Self {
+            width,
+            height,
+            channels,
+            data,
+        });
+    }
+
+    fn parse_pgm(bytes: &[u8]) -> Result<Self, RosMapError> {
+        let mut cursor = 0;
+        let magic = pgm_token(bytes, &mut cursor)?;
+        let binary = match magic.as_str() {
+            "P5" => true,
+            "P2" => false,
+            _ => {
+                return Err(RosMapError::MalformedImage(format!(
+                    "expected a P5 or P2 pgm, but the magic number is [{magic}]"
+                )));
+            }
+        };
+
+        let width = pgm_number(bytes, &mut cursor)?;
+        let height = pgm_number(bytes, &mut cursor)?;
+        let max_value = pgm_number(bytes, &mut cursor)?;
+        if max_value == 0 || max_value > u16::MAX as usize {
+            return Err(RosMapError::MalformedImage(format!(
+                "invalid maximum gray value {max_value}"
+            )));
+        }
+
+        let count = image_size(width, height, 1)?;
+        let scale = |value: usize| ((value.min(max_value) * 255 + max_value / 2) / max_value) as u8;
+        let mut data;
+        if binary {
+            // Exactly one whitespace byte separates the header from the data
+            cursor += 1;
+            let sample_size = if max_value < 256 { 1 } else { 2 };
+            let raster_size = image_size(width, height, sample_size)?;
+            let raster = cursor
+                .checked_add(raster_size)
+                .and_then(|end| bytes.get(cursor..end))
+                .ok_or_else(|| {
+                    RosMapError::MalformedImage(format!(
+                        "expected {} bytes of pixel data but only {} remain",
+                        raster_size,
+                        bytes.len().saturating_sub(cursor),
+                    ))
+                })?;
+
+            data = Vec::with_capacity(count);
+            for sample in raster.chunks(sample_size) {
+                let value = sample.iter().fold(0, |v, b| (v << 8) | *b as usize);
+                data.push(scale(value));
+            }
+        } else {
+            // Every sample takes up at least two bytes, so a truncated file
+            // cannot make us reserve more than the file itself.
+            data = Vec::with_capacity(count.min(bytes.len() / 2));
+            for _ in 0..count {
+                data.push(scale(pgm_number(bytes, &mut cursor)?));
+            }
+        }
+
+        return Ok(Self {
+            width,
+            height,
+            channels: 1,
+            data,
+        });
+    }
+
+    /// The average of the color channels of a pixel, ignoring alpha.
+    fn value(&self, row: usize, col: usize) -> u8 {
+        let start = (row * self.width + col) * self.channels;
+        let pixel = &self.data[start..start + self.channels];
+        let colors = match self.channels {
+            2 | 4 => &pixel[..self.channels - 1],
+            _ => pixel,
+        };
+
+        let sum: usize = colors.iter().map(|c| *c as usize).sum();
+        return (sum / colors.len()) as u8;
+    }
+}
+
+/// Get the next whitespace-separated token of a pgm header, skipping comments.
+/// Get the number of bytes in an image with the given dimensions, or an error
+/// if a malformed header describes an image too large to be addressed.
+fn image_size(width: usize, height: usize, sample_size: usize) -> Result<usize, RosMapError> {
+    return width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(sample_size))
+        .ok_or_else(|| {
+            RosMapError::MalformedImage(format!("an image of {width}x{height} pixels is too large"))
+        });
+}
+
+fn pgm_token(bytes: &[u8], cursor: &mut usize) -> Result<String, RosMapError> {
+    loop {
+        match bytes.get(*cursor) {
+            Some(b) if b.is_ascii_whitespace() => *cursor += 1,
+            Some(b'#') => {
+                while bytes.get(*cursor).map(|b| *b != b'\n').unwrap_or(false) {
+                    *cursor += 1;
+                }
+            }
+            Some(_) => break,
+            None => {
+                return Err(RosMapError::MalformedImage(
+                    "the pgm ended unexpectedly".to_owned(),
+                ));
+            }
+        }
+    }
+
+    let start = *cursor;
+    while bytes
+        .get(*cursor)
+        .map(|b| !b.is_ascii_whitespace())
+        .unwrap_or(false)
+    {
+        *cursor += 1;
+    }
+
+    return Ok(String::from_utf8_lossy(&bytes[start..*cursor]).into_owned());
+}
+
+fn pgm_number(bytes: &[u8], cursor: &mut usize) -> Result<usize, RosMapError> {
+    let token = pgm_token(bytes, cursor)?;
+    return token.parse().map_err(|_| {
+        RosMapError::MalformedImage(format!("expected a number but found [{token}]"))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mapf_ros_map_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    #[test]
+    fn test_ros_map_round_trip() {
+        let mut grid = SparseGrid::new(0.5);
+        grid.set_origin(Point::new(-3.25, 1.5));
+        let mut changes = HashMap::new();
+        for cell in [
+            Cell::new(-2, 4),
+            Cell::new(-2, 5),
+            Cell::new(3, 4),
+            Cell::new(7, 9),
+            Cell::new(0, 6),
+        ] {
+            changes.insert(cell, true);
+        }
+        grid.change_cells(&changes);
+
+        for extension in ["pgm", "png"] {
+            let dir = test_dir(extension);
+            let yaml_path = dir.join("map.yaml");
+            let map = RosMap::from_grid(&grid, format!("map.{extension}"));
+            assert_eq!(map.width(), 10);
+            assert_eq!(map.height(), 6);
+            map.save(&yaml_path).unwrap();
+
+            let loaded = RosMap::load(&yaml_path).unwrap();
+            assert_eq!(loaded.metadata, map.metadata);
+            assert_eq!(loaded.width(), map.width());
+            assert_eq!(loaded.height(), map.height());
+
+            let sparse = loaded.to_sparse_grid(false);
+            let dense = loaded.to_dense_grid(false);
+            assert_eq!(sparse.occupied_cells().count(), changes.len());
+            assert_eq!(dense.occupied_cells().count(), changes.len());
+            for cell in changes.keys() {
+                let p = grid.cell_center(cell);
+                assert!(sparse.is_point_occupied(p).is_some());
+                assert!(dense.is_point_occupied(p).is_some());
+            }
+
+            let free_p = grid.cell_center(&Cell::new(1, 6));
+            assert!(sparse.is_point_occupied(free_p).is_none());
+            assert!(dense.is_point_occupied(free_p).is_none());
+
+            std::fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_ros_map_load_ascii_pgm() {
+        let dir = test_dir("ascii");
+        std::fs::write(
+            dir.join("map.pgm"),
+            "P2\n# A comment\n3 2\n15\n0 15 8\n15 15 0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("map.yaml"),
+            "image: map.pgm\nresolution: 0.1\norigin: [1.0, 2.0, 0.0]\nnegate: 1\noccupied_thresh: 0.65\nfree_thresh: 0.196\n",
+        )
+        .unwrap();
+
+        let map = RosMap::load(dir.join("map.yaml")).unwrap();
+        assert!(map.metadata.negate);
+        assert_eq!(map.width(), 3);
+        assert_eq!(map.height(), 2);
+        // Negated, so black pixels are free and white pixels are occupied.
+        // The top row of the image is the highest row of cells.
+        assert_eq!(map.get(&Cell::new(0, 1)), Some(MapCell::Free));
+        assert_eq!(map.get(&Cell::new(1, 1)), Some(MapCell::Occupied));
+        assert_eq!(map.get(&Cell::new(2, 1)), Some(MapCell::Unknown));
+        assert_eq!(map.get(&Cell::new(0, 0)), Some(MapCell::Occupied));
+        assert_eq!(map.get(&Cell::new(2, 0)), Some(MapCell::Free));
+        assert_eq!(map.get(&Cell::new(3, 0)), None);
+
+        let grid = map.to_sparse_grid(false);
+        assert_eq!(grid.occupied_cells().count(), 3);
+        let grid = map.to_sparse_grid(true);
+        assert_eq!(grid.occupied_cells().count(), 4);
+        assert!(grid.is_point_occupied(Point::new(1.25, 2.15)).is_some());
+        assert!(grid.is_point_occupied(Point::new(0.25, 2.15)).is_none());
+
+        std::fs::write(
+            dir.join("rotated.yaml"),
+            "image: map.pgm\nresolution: 0.1\norigin: [1.0, 2.0, 0.5]\nnegate: 0\noccupied_thresh: 0.65\nfree_thresh: 0.196\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            RosMap::load(dir.join("rotated.yaml")),
+            Err(RosMapError::RotatedOrigin(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_ros_map_malformed_pgm_size() {
+        // The dimensions overflow when they are multiplied together
+        let huge = format!("P5\n{} {}\n255\n", usize::MAX / 2, 3);
+        assert!(matches!(
+            Image::parse_pgm(huge.as_bytes()),
+            Err(RosMapError::MalformedImage(_))
+        ));
+
+        // The size of the raster overflows when it is added to the cursor
+        let huge = format!("P5\n{} {}\n255\n", usize::MAX - 4, 1);
+        assert!(matches!(
+            Image::parse_pgm(huge.as_bytes()),
+            Err(RosMapError::MalformedImage(_))
+        ));
+
+        // A truncated ascii image
+        assert!(matches!(
+            Image::parse_pgm(b"P2\n1000000 1000000\n255\n0 1 2"),
+            Err(RosMapError::MalformedImage(_))
+        ));
+    }
+}
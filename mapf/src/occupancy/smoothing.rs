@@ -0,0 +1,395 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use crate::{
+    motion::{
+        self,
+        collide::detect_collision_circles_se2,
+        graph_search::{NodeCostOf, Policy, Solution},
+        se2::{self, timed_position::Waypoint as WaypointSE2},
+        trajectory::CostCalculator,
+        Duration, Extrapolator, Trajectory,
+    },
+    occupancy::{Grid, OccupancyConstraint, Point},
+};
+use std::sync::Arc;
+
+/// Post-processes planned motions by cutting corners wherever the occupancy
+/// grid allows. Graph search over grids tends to produce zig-zagging paths
+/// where every turn costs the agent a rotation in place. The smoother walks
+/// along the corners of a motion and connects each corner directly to the
+/// farthest later corner that can be reached without hitting anything, using
+/// the same extrapolator that the planner used so that the timing of the new
+/// motion is consistent with the original plan.
+pub struct Smoother<G: Grid, E, C> {
+    /// Used to check each shortcut against the occupancy grid.
+    pub occupancy: OccupancyConstraint<G>,
+
+    /// Used to compute the timing of each shortcut. This should be the same
+    /// extrapolator that was used to produce the original motion.
+    pub extrapolator: Arc<E>,
+
+    /// Used to decide whether the smoothed motion is an improvement.
+    pub cost_calculator: Arc<C>,
+
+    /// Circular obstacles that move through the environment, given as
+    /// (radius, trajectory). Shortcuts that would collide with any of these
+    /// will not be used. Leave this empty to only consider the occupancy grid.
+    pub dynamic_obstacles: Vec<(f64, se2::LinearTrajectory)>,
+}
+
+impl<G, E, C> Smoother<G, E, C>
+where
+    G: Grid,
+    E: Extrapolator<WaypointSE2, Point> + Extrapolator<WaypointSE2, se2::Position>,
+    C: CostCalculator<WaypointSE2>,
+{
+    pub fn new(
+        occupancy: OccupancyConstraint<G>,
+        extrapolator: Arc<E>,
+        cost_calculator: Arc<C>,
+    ) -> Self {
+        Self {
+            occupancy,
+            extrapolator,
+            cost_calculator,
+            dynamic_obstacles: Vec::new(),
+        }
+    }
+
+    /// Smooth the motion of a solution. If a smoothed motion is found which
+    /// costs less than the original, a new solution with that motion will be
+    /// returned. Otherwise a copy of the original solution is returned.
+    pub fn smooth<P>(&self, solution: &Solution<P>) -> Solution<P>
+    where
+        P: Policy<Waypoint = WaypointSE2>,
+        C: CostCalculator<WaypointSE2, Cost = NodeCostOf<P>>,
+    {
+        if let Some(original) = solution.motion() {
+            if let Some(smoothed) = self.smooth_trajectory(original) {
+                let cost = self.cost_calculator.compute_cost(&smoothed);
+                if cost < *solution.cost() {
+                    return Solution::new(cost, Some(smoothed));
+                }
+            }
+        }
+
+        return solution.clone();
+    }
+
+    /// Attempt to shortcut the corners of a trajectory. The smoothed
+    /// trajectory begins with the initial waypoint of the original and finishes
+    /// at the position and orientation of its final waypoint.
+    ///
+    /// Whenever no shortcut can be taken out of a corner, e.g. because the
+    /// original motion waited there for a dynamic obstacle to pass, the
+    /// original leg to the next corner is replayed instead. This returns None
+    /// if there are no corners to cut, or if even the original leg would
+    /// collide from where the smoothed motion arrives.
+    ///
+    /// Note that this does not check whether the smoothed trajectory is cheaper
+    /// than the original.
+    pub fn smooth_trajectory(
+        &self,
+        trajectory: &se2::LinearTrajectory,
+    ) -> Option<se2::LinearTrajectory> {
+        let corners = Self::find_corners(trajectory);
+        if corners.len() < 3 {
+            return None;
+        }
+
+        let mut current = *trajectory.initial();
+        let mut waypoints = vec![current];
+        let mut i = 0;
+        while i + 1 < corners.len() {
+            let mut next = None;
+            for j in (i + 1..corners.len()).rev() {
+                let leg = match Extrapolator::<WaypointSE2, Point>::make_trajectory(
+                    self.extrapolator.as_ref(),
+                    current,
+                    &corners[j].0,
+                ) {
+                    Ok(Some(leg)) => leg,
+                    Ok(None) | Err(_) => continue,
+                };
+
+                if self.is_clear(&leg) {
+                    next = Some((j, leg));
+                    break;
+                }
+            }
+
+            let (j, leg) = match next {
+                Some(next) => next,
+                None => (
+                    i + 1,
+                    self.replay_leg(trajectory, current, corners[i].1, corners[i + 1].1)?,
+                ),
+            };
+
+            current = *leg.finish();
+            waypoints.extend(leg.iter().skip(1).map(|wp| wp.0));
+            i = j;
+        }
+
+        // Turn to face the same direction that the original motion finished in
+        let final_turn = match Extrapolator::<WaypointSE2, se2::Position>::make_trajectory(
+            self.extrapolator.as_ref(),
+            current,
+            &trajectory.finish().position,
+        ) {
+            Ok(None) => None,
+            Ok(Some(turn)) if self.is_clear(&turn) => Some(turn),
+            _ => Some(self.replay_leg(
+                trajectory,
+                current,
+                corners.last().unwrap().1,
+                trajectory.len() - 1,
+            )?),
+        };
+
+        if let Some(final_turn) = final_turn {
+            waypoints.extend(final_turn.iter().skip(1).map(|wp| wp.0));
+        }
+
+        return Trajectory::from_iter(waypoints).ok();
+    }
+
+    /// Check whether a trajectory is free of collisions with both the
+    /// occupancy grid and the dynamic obstacles.
+    pub fn is_clear(&self, trajectory: &se2::LinearTrajectory) -> bool {
+        if self.occupancy.find_blocker(trajectory).is_some() {
+            return false;
+        }
+
+        let agent_radius = self.occupancy.footprint.circumradius();
+        return self.dynamic_obstacles.iter().all(|(radius, obstacle)| {
+            detect_collision_circles_se2(agent_radius, trajectory, *radius, obstacle).is_none()
+        });
+    }
+
+    /// Follow the original trajectory from waypoint `from` to waypoint `to`,
+    /// starting from a waypoint at the same position as waypoint `from`. The
+    /// agent first turns to face the way that the original motion did, then
+    /// waits until the original motion left, unless the agent is already
+    /// running behind it, in which case the leg is delayed. This returns None
+    /// if the replayed leg would collide with anything.
+    fn replay_leg(
+        &self,
+        trajectory: &se2::LinearTrajectory,
+        current: WaypointSE2,
+        from: usize,
+        to: usize,
+    ) -> Option<se2::LinearTrajectory> {
+        let start = trajectory.get(from)?;
+        let mut waypoints = vec![current];
+        if let Some(turn) = Extrapolator::<WaypointSE2, se2::Position>::make_trajectory(
+            self.extrapolator.as_ref(),
+            current,
+            &start.position,
+        )
+        .ok()?
+        {
+            waypoints.extend(turn.iter().skip(1).map(|wp| wp.0));
+        }
+
+        let ready = waypoints.last().unwrap().time;
+        let delay = if ready > start.time {
+            ready - start.time
+        } else {
+            Duration::zero()
+        };
+
+        for wp in trajectory.iter().take(to + 1).skip(from) {
+            let mut wp = wp.0;
+            wp.time += delay;
+            if waypoints.last().unwrap().time < wp.time {
+                waypoints.push(wp);
+            }
+        }
+
+        let leg = Trajectory::from_iter(waypoints).ok()?;
+        if !self.is_clear(&leg) {
+            return None;
+        }
+
+        return Some(leg);
+    }
+
+    /// Get the sequence of distinct points that a trajectory passes through,
+    /// along with the index of the first waypoint at each one. Waypoints that
+    /// only rotate or wait in place are skipped.
+    fn find_corners(trajectory: &se2::LinearTrajectory) -> Vec<(Point, usize)> {
+        let mut corners: Vec<(Point, usize)> = Vec::new();
+        for (i, wp) in trajectory.iter().enumerate() {
+            let p = Point::from(wp.position.translation.vector);
+            if let Some((last, _)) = corners.last() {
+                if (p - *last).norm() <= motion::DEFAULT_TRANSLATIONAL_THRESHOLD {
+                    continue;
+                }
+            }
+            corners.push((p, i));
+        }
+
+        return corners;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        motion::{
+            se2::timed_position::DifferentialDriveLineFollow, trajectory::DurationCostCalculator,
+        },
+        occupancy::{Cell, Footprint, SparseGrid},
+    };
+    use std::collections::HashMap;
+    use time_point::TimePoint;
+
+    fn make_smoother() -> Smoother<SparseGrid, DifferentialDriveLineFollow, DurationCostCalculator>
+    {
+        // A single occupied cell that the path has to go around
+        let mut grid = SparseGrid::new(1.0);
+        let mut changes = HashMap::new();
+        changes.insert(Cell::new(2, 0), true);
+        grid.change_cells(&changes);
+
+        Smoother::new(
+            OccupancyConstraint::new(Arc::new(grid), Footprint::Circle { radius: 0.3 }),
+            Arc::new(DifferentialDriveLineFollow::new(1.0, 1.0).unwrap()),
+            Arc::new(DurationCostCalculator),
+        )
+    }
+
+    fn make_zig_zag(extrapolator: &DifferentialDriveLineFollow) -> se2::LinearTrajectory {
+        make_zig_zag_with_hold(extrapolator, None)
+    }
+
+    /// Make the zig-zag path, optionally holding still at one of its corners
+    /// until the given time.
+    fn make_zig_zag_with_hold(
+        extrapolator: &DifferentialDriveLineFollow,
+        hold: Option<(usize, TimePoint)>,
+    ) -> se2::LinearTrajectory {
+        let mut current = WaypointSE2::new(TimePoint::new(0), 0.5, 0.5, 0.0);
+        let mut waypoints = vec![current];
+        for (corner, (x, y)) in [
+            (1.5, 0.5),
+            (1.5, 1.5),
+            (2.5, 1.5),
+            (3.5, 1.5),
+            (3.5, 0.5),
+            (4.5, 0.5),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if let Some((hold_corner, until)) = hold {
+                if hold_corner == corner {
+                    current.time = until;
+                    waypoints.push(current);
+                }
+            }
+
+            let leg = Extrapolator::<WaypointSE2, Point>::make_trajectory(
+                extrapolator,
+                current,
+                &Point::new(x, y),
+            )
+            .unwrap()
+            .unwrap();
+            current = *leg.finish();
+            waypoints.extend(leg.iter().skip(1).map(|wp| wp.0));
+        }
+
+        Trajectory::from_iter(waypoints).unwrap()
+    }
+
+    #[test]
+    fn test_smoothing_cuts_corners() {
+        let smoother = make_smoother();
+        let original = make_zig_zag(&smoother.extrapolator);
+        assert!(smoother.is_clear(&original));
+
+        let smoothed = smoother.smooth_trajectory(&original).unwrap();
+        assert!(smoother.is_clear(&smoothed));
+        assert!(smoothed.len() < original.len());
+        assert!(smoothed.duration() < original.duration());
+        assert_eq!(smoothed.initial(), original.initial());
+        assert!(
+            (smoothed.finish().position.translation.vector
+                - original.finish().position.translation.vector)
+                .norm()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn test_smoothing_respects_dynamic_obstacles() {
+        let mut smoother = make_smoother();
+        let original = make_zig_zag(&smoother.extrapolator);
+
+        // An obstacle parked beside the top of the detour leaves no way
+        // through for the agent.
+        smoother.dynamic_obstacles.push((
+            0.3,
+            se2::LinearTrajectory::hold(
+                WaypointSE2::new(TimePoint::new(0), 2.5, 1.9, 0.0),
+                TimePoint::from_secs_f64(100.0),
+            )
+            .unwrap(),
+        ));
+        assert!(smoother.smooth_trajectory(&original).is_none());
+    }
+
+    #[test]
+    fn test_smoothing_replays_legs_that_wait() {
+        let mut smoother = make_smoother();
+
+        // An obstacle blocks the top of the detour for the first ten seconds
+        // and then leaves, so the original motion waits at the corner (1.5,
+        // 1.5) for it. None of the shortcuts out of that corner can wait, so
+        // the original leg has to be replayed.
+        smoother.dynamic_obstacles.push((
+            0.1,
+            se2::LinearTrajectory::from_iter([
+                WaypointSE2::new(TimePoint::new(0), 2.0, 1.5, 0.0),
+                WaypointSE2::new(TimePoint::from_secs_f64(10.0), 2.0, 1.5, 0.0),
+                WaypointSE2::new(TimePoint::from_secs_f64(11.0), 2.0, 10.0, 0.0),
+            ])
+            .unwrap(),
+        ));
+
+        let original = make_zig_zag_with_hold(
+            &smoother.extrapolator,
+            Some((2, TimePoint::from_secs_f64(11.0))),
+        );
+        assert!(smoother.is_clear(&original));
+
+        let smoothed = smoother.smooth_trajectory(&original).unwrap();
+        assert!(smoother.is_clear(&smoothed));
+        assert!(smoothed.finish_time() <= original.finish_time());
+        assert_eq!(smoothed.initial(), original.initial());
+        assert!(
+            (smoothed.finish().position.translation.vector
+                - original.finish().position.translation.vector)
+                .norm()
+                < 1e-6
+        );
+    }
+}
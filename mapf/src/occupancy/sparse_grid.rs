@@ -15,13 +15,14 @@
  *
 */
 
-use super::util::{LineSegment, SearchF64};
-use super::{Cell, ChangedCorners, ConfirmedChanges, Corner, CornerStatus, Grid, Point, Vector};
+use super::util::{update_corner_status, LineSegment, SearchF64};
+use super::{Cell, ChangedCorners, ConfirmedChanges, CornerStatus, Grid, Point, Vector};
 use std::collections::{btree_map, hash_map, hash_set, BTreeMap, BTreeSet, HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct SparseGrid {
     cell_size: f64,
+    origin: Point,
     occupied: HashSet<Cell>,
     corners: HashMap<Cell, CornerStatus>,
     occupancy_map: BTreeMap<i64, BTreeSet<i64>>,
@@ -32,63 +33,23 @@ impl SparseGrid {
     pub fn new(cell_size: f64) -> SparseGrid {
         Self {
             cell_size,
+            origin: Point::origin(),
             occupied: HashSet::default(),
             corners: HashMap::default(),
             occupancy_map: BTreeMap::default(),
         }
     }
 
-    fn update_corner_status(&mut self, delta: &mut ChangedCorners, cell: &Cell) {
-        if !self.is_occupied(cell) {
-            if self.corners.remove(cell).is_some() {
-                delta.push((*cell, CornerStatus::default()));
-            }
-            return;
-        }
-
-        let mut status = CornerStatus::default();
-        let vertical_edges: [(i8, bool); 2] = [
-            (-1, !self.occupied.contains(&cell.shifted(-1, 0))),
-            (1, !self.occupied.contains(&cell.shifted(1, 0))),
-        ];
-
-        if vertical_edges[0].1 || vertical_edges[1].1 {
-            let horizontal_edges: [(i8, bool); 2] = [
-                (-1, !self.occupied.contains(&cell.shifted(0, -1))),
-                (1, !self.occupied.contains(&cell.shifted(0, 1))),
-            ];
-
-            for (i, vertical_edge) in vertical_edges {
-                for (j, horizontal_edge) in horizontal_edges {
-                    if vertical_edge && horizontal_edge {
-                        if !self.occupied.contains(&cell.shifted(i as i64, j as i64)) {
-                            status.set(Corner(i, j), true);
-                        }
-                    }
-                }
-            }
-        }
+    /// Set the location of the bottom left corner of cell (0, 0). Cells are
+    /// always laid out relative to this origin, so changing it moves the
+    /// whole occupancy without modifying any cells.
+    pub fn set_origin(&mut self, origin: Point) {
+        self.origin = origin;
+    }
 
-        if status.is_corner() {
-            match self.corners.entry(*cell) {
-                hash_map::Entry::Vacant(entry) => {
-                    entry.insert(status);
-                    delta.push((*cell, status));
-                }
-                hash_map::Entry::Occupied(mut entry) => {
-                    let existing_corner = entry.get_mut();
-                    let changed = status != *existing_corner;
-                    *existing_corner = status;
-                    if changed {
-                        delta.push((*cell, status));
-                    }
-                }
-            }
-        } else {
-            if self.corners.remove(cell).is_some() {
-                delta.push((*cell, status));
-            }
-        }
+    fn update_corner_status(&mut self, delta: &mut ChangedCorners, cell: &Cell) {
+        let occupied = &self.occupied;
+        update_corner_status(&mut self.corners, delta, cell, |c| occupied.contains(c));
     }
 
     fn update_cell(&mut self, cell: &Cell, occupied: bool) -> bool {
@@ -137,7 +98,7 @@ impl SparseGrid {
 }
 
 impl Grid for SparseGrid {
-    type OccupiedIterator<'a> = hash_set::Iter<'a, Cell>;
+    type OccupiedIterator<'a> = std::iter::Copied<hash_set::Iter<'a, Cell>>;
     type CornerIterator<'a> = hash_map::Iter<'a, Cell, CornerStatus>;
 
     /// Change whether a cell is occupied or not. Returns true if a change
@@ -175,12 +136,16 @@ impl Grid for SparseGrid {
         return self.cell_size;
     }
 
+    fn origin(&self) -> Point {
+        return self.origin;
+    }
+
     fn is_occupied(&self, cell: &Cell) -> bool {
         return self.occupied.contains(cell);
     }
 
     fn occupied_cells<'b>(&'b self) -> Self::OccupiedIterator<'b> {
-        return self.occupied.iter();
+        return self.occupied.iter().copied();
     }
 
     fn corners<'b>(&'b self) -> Self::CornerIterator<'b> {
@@ -188,7 +153,7 @@ impl Grid for SparseGrid {
     }
 
     fn is_point_occupied(&self, p: Point) -> Option<Cell> {
-        let cell = Cell::from_point(p, self.cell_size);
+        let cell = self.cell_of(&p);
         if self.occupied.contains(&cell) {
             return Some(cell);
         }
@@ -197,6 +162,7 @@ impl Grid for SparseGrid {
     }
 
     fn is_square_occupied(&self, p: Point, width: f64) -> Option<Cell> {
+        let p = p - self.origin.coords;
         let d = width / 2.0;
         let delta = Vector::new(d, d);
         let min_p = p - delta;
@@ -223,6 +189,8 @@ impl Grid for SparseGrid {
             return self.is_point_occupied(p0);
         }
 
+        let (p0, p1) = (p0 - self.origin.coords, p1 - self.origin.coords);
+
         let v = (p1 - p0) / dist;
         let n = Vector::new(-v.y, v.x);
 
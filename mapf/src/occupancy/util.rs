@@ -15,8 +15,71 @@
  *
 */
 
-use super::{Cell, Point};
+use super::{Cell, ChangedCorners, Corner, CornerStatus, Point};
 use arrayvec::ArrayVec;
+use std::collections::{hash_map, HashMap};
+
+/// Recalculate the corner status of a cell and record it in the map of
+/// corners. If the status of the corner changed, the change will be pushed
+/// into the delta.
+pub(crate) fn update_corner_status<F: Fn(&Cell) -> bool>(
+    corners: &mut HashMap<Cell, CornerStatus>,
+    delta: &mut ChangedCorners,
+    cell: &Cell,
+    is_occupied: F,
+) {
+    if !is_occupied(cell) {
+        if corners.remove(cell).is_some() {
+            delta.push((*cell, CornerStatus::default()));
+        }
+        return;
+    }
+
+    let mut status = CornerStatus::default();
+    let vertical_edges: [(i8, bool); 2] = [
+        (-1, !is_occupied(&cell.shifted(-1, 0))),
+        (1, !is_occupied(&cell.shifted(1, 0))),
+    ];
+
+    if vertical_edges[0].1 || vertical_edges[1].1 {
+        let horizontal_edges: [(i8, bool); 2] = [
+            (-1, !is_occupied(&cell.shifted(0, -1))),
+            (1, !is_occupied(&cell.shifted(0, 1))),
+        ];
+
+        for (i, vertical_edge) in vertical_edges {
+            for (j, horizontal_edge) in horizontal_edges {
+                if vertical_edge
+                    && horizontal_edge
+                    && !is_occupied(&cell.shifted(i as i64, j as i64))
+                {
+                    status.set(Corner(i, j), true);
+                }
+            }
+        }
+    }
+
+    if status.is_corner() {
+        match corners.entry(*cell) {
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(status);
+                delta.push((*cell, status));
+            }
+            hash_map::Entry::Occupied(mut entry) => {
+                let existing_corner = entry.get_mut();
+                let changed = status != *existing_corner;
+                *existing_corner = status;
+                if changed {
+                    delta.push((*cell, status));
+                }
+            }
+        }
+    } else {
+        if corners.remove(cell).is_some() {
+            delta.push((*cell, status));
+        }
+    }
+}
 
 pub(crate) struct SearchF64 {
     pub(crate) value: Option<f64>,
@@ -108,6 +108,10 @@ where
     pub fn memory(&self) -> &A::Memory {
         &self.memory
     }
+
+    pub fn trace(&self) -> &T {
+        &self.trace
+    }
 }
 
 impl<E: Solvable, A: Algorithm<E>, G, T: Trace<E::Node>> Progress<E, A, BasicOptions, G, T> {
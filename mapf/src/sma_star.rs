@@ -0,0 +1,611 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+use crate::{
+    algorithm::{self, Status},
+    error::NoError,
+    expander::{
+        CostOf, Expander, ExpansionErrorOf, Goal, InitTargeted, InitTargetedErrorOf, Solvable,
+        SolveErrorOf, Targeted,
+    },
+    node::Informed,
+    Trace,
+};
+use std::{cmp::Reverse, collections::BTreeSet, sync::Arc};
+
+/// A node that is being held in the memory of the search.
+struct Entry<N: Informed> {
+    node: Arc<N>,
+    parent: Option<usize>,
+    /// The position of this node in the expansion of its parent. Expanders
+    /// always produce the same children in the same order, so this is how a
+    /// pruned child gets recognized when its parent is expanded again.
+    index: usize,
+    depth: usize,
+    /// The total cost estimate of this node. This is never less than the
+    /// estimate of its parent.
+    f: N::Cost,
+    /// The f-value that this node is sorted by in the open set, or None if it
+    /// is not in the open set. A node that has not been expanded yet is sorted
+    /// by its own f-value. A node that has been expanded is only in the open
+    /// set while some of its children are forgotten, and it is sorted by the
+    /// lowest backed-up f-value among them.
+    queued: Option<N::Cost>,
+    /// Whether this node has been expanded before
+    expanded: bool,
+    /// How many children of this node are currently held in memory
+    children: usize,
+    /// The expansion index and backed-up f-value of each child of this node
+    /// that was pruned. Children that turned out to be dead ends are not
+    /// remembered, so they will not be generated again.
+    forgotten: Vec<(usize, N::Cost)>,
+}
+
+/// The open leaves of the search, sorted by their f-value. Ties are broken in
+/// favor of deeper nodes so that the best node is the deepest of the lowest
+/// f-values and the worst node is the shallowest of the highest f-values.
+type OpenSet<C> = BTreeSet<(C, Reverse<usize>, usize)>;
+
+pub struct Memory<N: Informed, E: Expander<Node = N>> {
+    entries: Vec<Option<Entry<N>>>,
+    vacant: Vec<usize>,
+    open: OpenSet<N::Cost>,
+    count: usize,
+    node_limit: usize,
+    expander: Arc<E>,
+}
+
+impl<N: Informed, E: Expander<Node = N>> Memory<N, E> {
+    /// The maximum number of nodes that the search will hold at once
+    pub fn node_limit(&self) -> usize {
+        self.node_limit
+    }
+
+    /// How many nodes are waiting to be expanded
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+
+    fn entry(&self, id: usize) -> &Entry<N> {
+        self.entries[id].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, id: usize) -> &mut Entry<N> {
+        self.entries[id].as_mut().unwrap()
+    }
+
+    fn insert(
+        &mut self,
+        node: Arc<N>,
+        parent: Option<usize>,
+        index: usize,
+        depth: usize,
+        f: N::Cost,
+    ) -> usize {
+        let entry = Entry {
+            node,
+            parent,
+            index,
+            depth,
+            f,
+            queued: Some(f),
+            expanded: false,
+            children: 0,
+            forgotten: Vec::new(),
+        };
+
+        let id = if let Some(id) = self.vacant.pop() {
+            self.entries[id] = Some(entry);
+            id
+        } else {
+            self.entries.push(Some(entry));
+            self.entries.len() - 1
+        };
+
+        self.open.insert((f, Reverse(depth), id));
+        self.count += 1;
+        return id;
+    }
+
+    fn pop_best(&mut self) -> Option<usize> {
+        let (_, _, id) = self.open.pop_first()?;
+        self.entry_mut(id).queued = None;
+        return Some(id);
+    }
+
+    /// Remove a node from the open set if it is there.
+    fn dequeue(&mut self, id: usize) {
+        let entry = self.entry_mut(id);
+        if let Some(f) = entry.queued.take() {
+            let key = (f, Reverse(entry.depth), id);
+            self.open.remove(&key);
+        }
+    }
+
+    /// Put a node that has been expanded back into the open set, sorted by the
+    /// best estimate among its forgotten children, so that those children can
+    /// be generated again once they are the most promising.
+    fn requeue(&mut self, id: usize) {
+        self.dequeue(id);
+        let entry = self.entry_mut(id);
+        if let Some(f) = entry.forgotten.iter().map(|(_, f)| *f).min() {
+            entry.queued = Some(f);
+            let key = (f, Reverse(entry.depth), id);
+            self.open.insert(key);
+        }
+    }
+
+    /// Drop a node from memory. If the node was pruned then its f-value gets
+    /// backed up to its parent, and the parent is reopened so the node can be
+    /// generated again. If the node was a dead end (backup is None) then
+    /// nothing is backed up, and a parent that is left with no children and
+    /// nothing to remember becomes a dead end too.
+    fn forget(&mut self, mut id: usize, mut backup: Option<N::Cost>) {
+        loop {
+            self.dequeue(id);
+            let entry = self.entries[id].take().unwrap();
+            self.vacant.push(id);
+            self.count -= 1;
+
+            let parent_id = match entry.parent {
+                Some(parent_id) => parent_id,
+                None => return,
+            };
+
+            let parent = self.entry_mut(parent_id);
+            parent.children -= 1;
+            if let Some(f) = backup {
+                parent.forgotten.push((entry.index, f));
+                self.requeue(parent_id);
+                return;
+            }
+
+            if parent.children > 0 || !parent.forgotten.is_empty() {
+                return;
+            }
+
+            id = parent_id;
+            backup = None;
+        }
+    }
+
+    /// Prune the worst open leaves until the memory fits within its limit. The
+    /// best open node is never pruned, and neither are root nodes since they
+    /// cannot be generated again. Nodes that still have children in memory
+    /// are not leaves, so they are only pruned after all their children are.
+    fn prune<T: Trace<N>>(&mut self, tracker: &mut T) {
+        while self.count > self.node_limit {
+            let best = self.open.first().copied();
+            let worst = self
+                .open
+                .iter()
+                .rev()
+                .find(|key| {
+                    let entry = self.entry(key.2);
+                    Some(**key) != best && entry.parent.is_some() && entry.children == 0
+                })
+                .copied();
+
+            let (f, _, id) = match worst {
+                Some(worst) => worst,
+                None => return,
+            };
+
+            tracker.pruned(&self.entry(id).node);
+            self.forget(id, Some(f));
+        }
+    }
+}
+
+impl<N: Informed, E: Expander<Node = N>> algorithm::Memory for Memory<N, E> {
+    fn node_count(&self) -> usize {
+        return self.count;
+    }
+}
+
+impl<N: Informed, E: Expander<Node = N>> algorithm::WeightSorted<E> for Memory<N, E> {
+    fn top_cost_estimate(&self) -> Option<CostOf<E>> {
+        self.open.first().map(|(f, _, _)| *f)
+    }
+}
+
+/// A memory-bounded variant of A* in the style of SMA*. The search holds at
+/// most `node_limit` nodes at once. When an expansion pushes it past that
+/// limit, the open leaves with the highest f-values are dropped and their
+/// f-values are backed up to their parents. As soon as a child is dropped, its
+/// parent is reopened with the best f-value among its dropped children, so the
+/// dropped branches will be explored again if they ever become the most
+/// promising.
+///
+/// Given an admissible heuristic, the search finds the cheapest solution whose
+/// path has no more than `node_limit` nodes. Paths that would need more nodes
+/// than that are treated as dead ends, so the search reports
+/// [`Status::Impossible`] if no solution fits within the limit.
+///
+/// Start nodes are never dropped because they cannot be generated again, so
+/// the limit can only be exceeded if the expander produces more start nodes
+/// than the limit allows.
+///
+/// There is no closed set, so states that can be reached in many ways will be
+/// generated many times. Each node in memory remembers the backed-up f-value
+/// of its pruned children, which is not counted against `node_limit`. Nodes
+/// that get dropped are reported to the trace with [`Trace::pruned`].
+#[derive(Debug, Clone)]
+pub struct Algorithm {
+    node_limit: usize,
+}
+
+impl Algorithm {
+    /// Create a memory-bounded search that holds at most `node_limit` nodes.
+    /// A limit of 0 is treated as 1.
+    pub fn new(node_limit: usize) -> Self {
+        Self {
+            node_limit: node_limit.max(1),
+        }
+    }
+
+    pub fn node_limit(&self) -> usize {
+        self.node_limit
+    }
+}
+
+impl<N, E> algorithm::Algorithm<E> for Algorithm
+where
+    N: Informed,
+    E: Expander<Node = N> + Solvable,
+{
+    type Memory = Memory<N, E>;
+    type InitError = NoError;
+    type StepError = NoError;
+
+    fn initialize<S, G: Goal<E::Node>, T: Trace<N>>(
+        &self,
+        expander: Arc<E>,
+        start: &S,
+        goal: &G,
+        tracker: &mut T,
+    ) -> Result<Self::Memory, algorithm::InitError<Self::StepError, InitTargetedErrorOf<E, S, G>>>
+    where
+        E: InitTargeted<S, G>,
+    {
+        let mut memory = Memory {
+            entries: Vec::new(),
+            vacant: Vec::new(),
+            open: BTreeSet::new(),
+            count: 0,
+            node_limit: self.node_limit,
+            expander: expander.clone(),
+        };
+
+        for node in expander.start(start, goal) {
+            let node = node.map_err(algorithm::InitError::Expander)?;
+            tracker.expanded_to(&node);
+            let f = node.total_cost_estimate();
+            memory.insert(node, None, 0, 0, f);
+        }
+
+        return Ok(memory);
+    }
+
+    fn step<G: Goal<E::Node>, T: Trace<N>>(
+        &self,
+        memory: &mut Self::Memory,
+        goal: &G,
+        tracker: &mut T,
+    ) -> Result<
+        Status<E::Solution>,
+        algorithm::StepError<Self::StepError, ExpansionErrorOf<E, G>, SolveErrorOf<E>>,
+    >
+    where
+        E: Targeted<G>,
+    {
+        tracker.step_begin();
+        let result = expand_best(memory, goal, tracker);
+        tracker.step_end();
+        return result;
+    }
+}
+
+type StepResultOf<E, G> = Result<
+    Status<<E as Solvable>::Solution>,
+    algorithm::StepError<NoError, ExpansionErrorOf<E, G>, SolveErrorOf<E>>,
+>;
+
+fn expand_best<N, E, G, T>(
+    memory: &mut Memory<N, E>,
+    goal: &G,
+    tracker: &mut T,
+) -> StepResultOf<E, G>
+where
+    N: Informed,
+    E: Expander<Node = N> + Solvable + Targeted<G>,
+    G: Goal<N>,
+    T: Trace<N>,
+{
+    let id = match memory.pop_best() {
+        Some(id) => id,
+        None => return Ok(Status::Impossible),
+    };
+
+    let (node, depth, f) = {
+        let entry = memory.entry(id);
+        (entry.node.clone(), entry.depth, entry.f)
+    };
+
+    if goal.is_satisfied(&node) {
+        tracker.solution_found_from(&node);
+        let solution = memory
+            .expander
+            .make_solution(&node)
+            .map_err(algorithm::StepError::Solve)?;
+        return Ok(Status::Solved(solution));
+    }
+
+    if depth + 1 >= memory.node_limit {
+        // The children of this node could not fit in memory alongside their
+        // ancestors, so this branch is a dead end for this search.
+        memory.forget(id, None);
+        return Ok(Status::Incomplete);
+    }
+
+    tracker.expanded_from(&node);
+    // If this node was expanded before, then only the children that were
+    // pruned get generated again, and they keep their backed-up estimates.
+    let remembered = {
+        let entry = memory.entry_mut(id);
+        let forgotten = std::mem::take(&mut entry.forgotten);
+        let remembered = entry.expanded.then_some(forgotten);
+        entry.expanded = true;
+        remembered
+    };
+
+    let expander = memory.expander.clone();
+    let mut children = memory.entry(id).children;
+    for (index, next) in expander.expand(&node, goal).into_iter().enumerate() {
+        let next = next.map_err(algorithm::StepError::Expansion)?;
+        // The estimate of a child should never be lower than the estimate of
+        // its parent, otherwise the backed-up values could shrink.
+        let mut next_f = next.total_cost_estimate().max(f);
+        if let Some(remembered) = &remembered {
+            match remembered.iter().find(|(i, _)| *i == index) {
+                Some((_, backup)) => next_f = next_f.max(*backup),
+                None => continue,
+            }
+        }
+
+        if depth + 3 > memory.node_limit && !goal.is_satisfied(&next) {
+            // The child would not be able to fit any children of its own in
+            // memory, so it is only worth keeping if it reaches the goal.
+            continue;
+        }
+
+        tracker.expanded_to(&next);
+        memory.insert(next, Some(id), index, depth + 1, next_f);
+        children += 1;
+    }
+
+    if children == 0 {
+        // Nothing is left in memory below this node and it has nothing more to
+        // generate, so it is a dead end.
+        memory.forget(id, None);
+    } else {
+        memory.entry_mut(id).children = children;
+        memory.prune(tracker);
+    }
+
+    return Ok(Status::Incomplete);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star,
+        algorithm::Memory as _,
+        directed::SimpleGraph,
+        motion::r2::{graph_search::make_default_expander, timed_position::LineFollow, Point},
+        planner::make_planner,
+    };
+
+    fn make_test_graph() -> SimpleGraph<Point> {
+        /*
+         * 12--13--14--15
+         *  |   |   |   |
+         *  8---9--10--11
+         *  |   |   |   |
+         *  4---5---6---7
+         *  |   |   |   |
+         *  0---1---2---3
+         */
+        let mut edges = Vec::new();
+        for i in 0..16 {
+            if i % 4 < 3 {
+                edges.extend([(i, i + 1), (i + 1, i)]);
+            }
+
+            if i < 12 {
+                edges.extend([(i, i + 4), (i + 4, i)]);
+            }
+        }
+
+        SimpleGraph::from_iters(
+            (0..16).map(|i| Point::new((i % 4) as f64, (i / 4) as f64)),
+            edges,
+        )
+    }
+
+    /// Counts how many nodes were pruned by the search
+    #[derive(Default)]
+    struct CountPruned(usize);
+    impl<N> Trace<N> for CountPruned {
+        fn expanded_from(&mut self, _: &N) {}
+        fn expanded_to(&mut self, _: &N) {}
+        fn solution_found_from(&mut self, _: &N) {}
+        fn pruned(&mut self, _: &N) {
+            self.0 += 1;
+        }
+    }
+
+    /// A linear congruential generator, so that the random graphs are the
+    /// same for every run of the tests.
+    struct Lcg(u64);
+    impl Lcg {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) % n
+        }
+    }
+
+    fn make_random_graph(rng: &mut Lcg, vertex_count: usize) -> SimpleGraph<Point> {
+        let vertices: Vec<Point> = (0..vertex_count)
+            .map(|_| {
+                Point::new(
+                    rng.below(1000) as f64 / 100.0,
+                    rng.below(1000) as f64 / 100.0,
+                )
+            })
+            .collect();
+
+        let mut edges = Vec::new();
+        for v in 0..vertex_count {
+            for _ in 0..3 {
+                let u = rng.below(vertex_count as u64) as usize;
+                if u != v && vertices[u] != vertices[v] {
+                    edges.extend([(v, u), (u, v)]);
+                }
+            }
+        }
+
+        SimpleGraph::from_iters(vertices, edges)
+    }
+
+    #[test]
+    fn test_bounded_search_matches_a_star() {
+        let expander = Arc::new(make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        ));
+
+        let a_star_cost = match make_planner(expander.clone(), Arc::new(a_star::Algorithm))
+            .plan(&0, 15)
+            .unwrap()
+            .solve()
+            .unwrap()
+        {
+            Status::Solved(solution) => *solution.cost(),
+            _ => panic!("A* failed to find a solution"),
+        };
+        assert_eq!(a_star_cost, 6_000_000_000);
+
+        let node_limit = 12;
+        let planner = make_planner(expander, Arc::new(Algorithm::new(node_limit)));
+        let mut progress = planner.trace(&0, 15, CountPruned::default()).unwrap();
+        for _ in 0..100_000 {
+            match progress.step().unwrap() {
+                Status::Solved(solution) => {
+                    assert_eq!(*solution.cost(), a_star_cost);
+                    // The grid is big enough that the search had to forget
+                    // some nodes along the way.
+                    assert!(progress.trace().0 > 0);
+                    return;
+                }
+                Status::Impossible => panic!("The solution should fit in memory"),
+                Status::Incomplete => {
+                    assert!(progress.memory().node_count() <= node_limit);
+                }
+            }
+        }
+
+        panic!("The search did not finish");
+    }
+
+    #[test]
+    fn test_bounded_search_matches_a_star_on_random_graphs() {
+        let mut rng = Lcg(7);
+        let mut compared = 0;
+        let mut pruned = 0;
+        for _ in 0..40 {
+            let expander = Arc::new(make_default_expander(
+                Arc::new(make_random_graph(&mut rng, 30)),
+                Arc::new(LineFollow::new(1.0).unwrap()),
+            ));
+
+            let (a_star_cost, path_length) =
+                match make_planner(expander.clone(), Arc::new(a_star::Algorithm))
+                    .plan(&0, 29)
+                    .unwrap()
+                    .solve()
+                    .unwrap()
+                {
+                    Status::Solved(solution) => (
+                        *solution.cost(),
+                        solution.motion().as_ref().map(|m| m.len()).unwrap_or(1),
+                    ),
+                    _ => continue,
+                };
+
+            for node_limit in [15, 30] {
+                if path_length > node_limit {
+                    continue;
+                }
+
+                let planner = make_planner(expander.clone(), Arc::new(Algorithm::new(node_limit)));
+                let mut progress = planner.trace(&0, 29, CountPruned::default()).unwrap();
+                let cost = loop {
+                    match progress.step().unwrap() {
+                        Status::Solved(solution) => break *solution.cost(),
+                        Status::Impossible => panic!("The solution should fit in memory"),
+                        Status::Incomplete => {
+                            assert!(progress.memory().node_count() <= node_limit);
+                        }
+                    }
+                };
+
+                assert_eq!(cost, a_star_cost, "limit {node_limit}");
+                compared += 1;
+                pruned += progress.trace().0;
+            }
+        }
+
+        assert!(compared > 20);
+        assert!(pruned > 0);
+    }
+
+    #[test]
+    fn test_solution_that_does_not_fit() {
+        let expander = make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        );
+
+        // The shortest path from 0 to 15 visits 7 vertices, so it cannot fit
+        // within a limit of 5 nodes.
+        let planner = make_planner(Arc::new(expander), Arc::new(Algorithm::new(5)));
+        let mut progress = planner.plan(&0, 15).unwrap();
+        for _ in 0..100_000 {
+            match progress.step().unwrap() {
+                Status::Solved(_) => panic!("The solution should not fit in memory"),
+                Status::Impossible => return,
+                Status::Incomplete => assert!(progress.memory().node_count() <= 5),
+            }
+        }
+
+        panic!("The search did not finish");
+    }
+}
@@ -15,10 +15,34 @@
  *
 */
 
-pub trait Trace<Node>: Default {
+use crate::node::{Informed, PartialKeyed, PathSearch, Timed};
+#[cfg(feature = "trace_json")]
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "trace_json")]
+use thiserror::Error as ThisError;
+
+pub trait Trace<Node> {
     fn expanded_from(&mut self, node: &Node);
     fn expanded_to(&mut self, node: &Node);
     fn solution_found_from(&mut self, node: &Node);
+
+    /// A node was discarded because the closed set already has an equivalent
+    /// node that is at least as good.
+    fn closed_rejected(&mut self, _: &Node) {}
+
+    /// A node was discarded by a constraint.
+    fn constraint_rejected(&mut self, _: &Node) {}
+
+    /// A node was dropped from memory by a memory-bounded algorithm. The node
+    /// might be generated again later.
+    fn pruned(&mut self, _: &Node) {}
+
+    /// The algorithm is beginning a step.
+    fn step_begin(&mut self) {}
+
+    /// The algorithm has finished a step.
+    fn step_end(&mut self) {}
 }
 
 #[derive(Default, Debug)]
@@ -45,4 +69,616 @@ impl<N: std::fmt::Debug> Trace<N> for TerminalTrace {
     fn solution_found_from(&mut self, node: &N) {
         println!("Solution found from {:?}", node);
     }
+
+    fn closed_rejected(&mut self, node: &N) {
+        println!(" x Closed set rejected {:?}", node);
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        println!(" x Constraint rejected {:?}", node);
+    }
+
+    fn pruned(&mut self, node: &N) {
+        println!(" x Pruned {:?}", node);
+    }
+}
+
+/// Send every event to two traces. Tees can be nested to fan out to more
+/// traces.
+#[derive(Default, Debug, Clone)]
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<N, A: Trace<N>, B: Trace<N>> Trace<N> for Tee<A, B> {
+    fn expanded_from(&mut self, node: &N) {
+        self.0.expanded_from(node);
+        self.1.expanded_from(node);
+    }
+
+    fn expanded_to(&mut self, node: &N) {
+        self.0.expanded_to(node);
+        self.1.expanded_to(node);
+    }
+
+    fn solution_found_from(&mut self, node: &N) {
+        self.0.solution_found_from(node);
+        self.1.solution_found_from(node);
+    }
+
+    fn closed_rejected(&mut self, node: &N) {
+        self.0.closed_rejected(node);
+        self.1.closed_rejected(node);
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        self.0.constraint_rejected(node);
+        self.1.constraint_rejected(node);
+    }
+
+    fn pruned(&mut self, node: &N) {
+        self.0.pruned(node);
+        self.1.pruned(node);
+    }
+
+    fn step_begin(&mut self) {
+        self.0.step_begin();
+        self.1.step_begin();
+    }
+
+    fn step_end(&mut self) {
+        self.0.step_end();
+        self.1.step_end();
+    }
+}
+
+/// Only pass along the node events that satisfy a predicate. Step events are
+/// always passed along.
+pub struct FilterTrace<T, F> {
+    trace: T,
+    predicate: F,
+}
+
+impl<T, F> FilterTrace<T, F> {
+    pub fn new(trace: T, predicate: F) -> Self {
+        Self { trace, predicate }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.trace
+    }
+
+    pub fn into_inner(self) -> T {
+        self.trace
+    }
+}
+
+impl<N, T, F> Trace<N> for FilterTrace<T, F>
+where
+    T: Trace<N>,
+    F: FnMut(TraceEventKind, &N) -> bool,
+{
+    fn expanded_from(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::ExpandedFrom, node) {
+            self.trace.expanded_from(node);
+        }
+    }
+
+    fn expanded_to(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::ExpandedTo, node) {
+            self.trace.expanded_to(node);
+        }
+    }
+
+    fn solution_found_from(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::SolutionFoundFrom, node) {
+            self.trace.solution_found_from(node);
+        }
+    }
+
+    fn closed_rejected(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::ClosedRejected, node) {
+            self.trace.closed_rejected(node);
+        }
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::ConstraintRejected, node) {
+            self.trace.constraint_rejected(node);
+        }
+    }
+
+    fn pruned(&mut self, node: &N) {
+        if (self.predicate)(TraceEventKind::Pruned, node) {
+            self.trace.pruned(node);
+        }
+    }
+
+    fn step_begin(&mut self) {
+        self.trace.step_begin();
+    }
+
+    fn step_end(&mut self) {
+        self.trace.step_end();
+    }
+}
+
+/// Only pass along every Nth node event, starting with the first one. Step
+/// events are always passed along. This keeps traces of very large searches
+/// small enough to inspect.
+#[derive(Debug, Clone)]
+pub struct SampleTrace<T> {
+    trace: T,
+    every: usize,
+    count: usize,
+}
+
+impl<T> SampleTrace<T> {
+    /// Pass along one out of every `every` node events. A value of 0 is
+    /// treated as 1.
+    pub fn new(trace: T, every: usize) -> Self {
+        Self {
+            trace,
+            every: every.max(1),
+            count: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.trace
+    }
+
+    pub fn into_inner(self) -> T {
+        self.trace
+    }
+
+    fn sample(&mut self) -> bool {
+        let sampled = self.count.is_multiple_of(self.every);
+        self.count += 1;
+        sampled
+    }
+}
+
+impl<N, T: Trace<N>> Trace<N> for SampleTrace<T> {
+    fn expanded_from(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.expanded_from(node);
+        }
+    }
+
+    fn expanded_to(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.expanded_to(node);
+        }
+    }
+
+    fn solution_found_from(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.solution_found_from(node);
+        }
+    }
+
+    fn closed_rejected(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.closed_rejected(node);
+        }
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.constraint_rejected(node);
+        }
+    }
+
+    fn pruned(&mut self, node: &N) {
+        if self.sample() {
+            self.trace.pruned(node);
+        }
+    }
+
+    fn step_begin(&mut self) {
+        self.trace.step_begin();
+    }
+
+    fn step_end(&mut self) {
+        self.trace.step_end();
+    }
+}
+
+/// A trace that can be shared by several owners, such as a planner and a
+/// [`Constrain`](crate::expander::Constrain) expander that reports the nodes
+/// its constraint rejects. Clones refer to the same trace.
+#[derive(Default, Debug)]
+pub struct SharedTrace<T>(Arc<Mutex<T>>);
+
+impl<T> Clone for SharedTrace<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> SharedTrace<T> {
+    pub fn new(trace: T) -> Self {
+        Self(Arc::new(Mutex::new(trace)))
+    }
+
+    /// Get access to the shared trace. If a thread panicked while using the
+    /// trace, the events it recorded up until then are still available.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<N, T: Trace<N>> Trace<N> for SharedTrace<T> {
+    fn expanded_from(&mut self, node: &N) {
+        self.lock().expanded_from(node);
+    }
+
+    fn expanded_to(&mut self, node: &N) {
+        self.lock().expanded_to(node);
+    }
+
+    fn solution_found_from(&mut self, node: &N) {
+        self.lock().solution_found_from(node);
+    }
+
+    fn closed_rejected(&mut self, node: &N) {
+        self.lock().closed_rejected(node);
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        self.lock().constraint_rejected(node);
+    }
+
+    fn pruned(&mut self, node: &N) {
+        self.lock().pruned(node);
+    }
+
+    fn step_begin(&mut self) {
+        self.lock().step_begin();
+    }
+
+    fn step_end(&mut self) {
+        self.lock().step_end();
+    }
+}
+
+/// The fields of a node that get stored by a [`RecordingTrace`]. This is
+/// implemented for every node that has a key, a cost estimate, a parent, and a
+/// time.
+pub trait TraceFields {
+    type Key;
+    type Cost;
+
+    fn trace_key(&self) -> Option<Self::Key>;
+    fn trace_parent_key(&self) -> Option<Self::Key>;
+    fn trace_cost(&self) -> Self::Cost;
+    fn trace_heuristic(&self) -> Self::Cost;
+    /// Nanoseconds since time zero
+    fn trace_time(&self) -> i64;
+}
+
+impl<N: PartialKeyed + Informed + PathSearch + Timed> TraceFields for N {
+    type Key = N::Key;
+    type Cost = N::Cost;
+
+    fn trace_key(&self) -> Option<Self::Key> {
+        self.partial_key().cloned()
+    }
+
+    fn trace_parent_key(&self) -> Option<Self::Key> {
+        self.parent()
+            .as_ref()
+            .and_then(|parent| parent.partial_key().cloned())
+    }
+
+    fn trace_cost(&self) -> Self::Cost {
+        self.cost()
+    }
+
+    fn trace_heuristic(&self) -> Self::Cost {
+        self.remaining_cost_estimate()
+    }
+
+    fn trace_time(&self) -> i64 {
+        self.time().nanos_since_zero
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum TraceEventKind {
+    ExpandedFrom,
+    ExpandedTo,
+    SolutionFoundFrom,
+    ClosedRejected,
+    ConstraintRejected,
+    Pruned,
+}
+
+/// One event that was observed by a [`RecordingTrace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEvent<K, C> {
+    /// How many nodes had been expanded when this event happened. Nodes that
+    /// are queued while the search is initialized have a step of 0, and the
+    /// nodes that are queued by an expansion share the step of that expansion.
+    pub step: usize,
+    pub kind: TraceEventKind,
+    pub key: Option<K>,
+    pub parent: Option<K>,
+    pub cost: C,
+    pub heuristic: C,
+    /// Nanoseconds since time zero
+    pub time: i64,
+}
+
+/// A trace that records every event of a search so that the search can be
+/// inspected or replayed later. With the `trace_json` feature enabled, the
+/// events can be saved as JSON Lines.
+#[derive(Debug, Clone)]
+pub struct RecordingTrace<K, C> {
+    events: Vec<TraceEvent<K, C>>,
+    step: usize,
+}
+
+impl<K, C> Default for RecordingTrace<K, C> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            step: 0,
+        }
+    }
+}
+
+impl<K, C> RecordingTrace<K, C> {
+    pub fn events(&self) -> &[TraceEvent<K, C>] {
+        &self.events
+    }
+
+    pub fn into_events(self) -> Vec<TraceEvent<K, C>> {
+        self.events
+    }
+
+    fn record<N: TraceFields<Key = K, Cost = C>>(&mut self, kind: TraceEventKind, node: &N) {
+        self.events.push(TraceEvent {
+            step: self.step,
+            kind,
+            key: node.trace_key(),
+            parent: node.trace_parent_key(),
+            cost: node.trace_cost(),
+            heuristic: node.trace_heuristic(),
+            time: node.trace_time(),
+        });
+    }
+}
+
+impl<N: TraceFields> Trace<N> for RecordingTrace<N::Key, N::Cost> {
+    fn expanded_from(&mut self, node: &N) {
+        self.step += 1;
+        self.record(TraceEventKind::ExpandedFrom, node);
+    }
+
+    fn expanded_to(&mut self, node: &N) {
+        self.record(TraceEventKind::ExpandedTo, node);
+    }
+
+    fn solution_found_from(&mut self, node: &N) {
+        self.record(TraceEventKind::SolutionFoundFrom, node);
+    }
+
+    fn closed_rejected(&mut self, node: &N) {
+        self.record(TraceEventKind::ClosedRejected, node);
+    }
+
+    fn constraint_rejected(&mut self, node: &N) {
+        self.record(TraceEventKind::ConstraintRejected, node);
+    }
+
+    fn pruned(&mut self, node: &N) {
+        self.record(TraceEventKind::Pruned, node);
+    }
+}
+
+#[cfg(feature = "trace_json")]
+#[derive(ThisError, Debug)]
+pub enum TraceLogError {
+    #[error("An error occurred while reading or writing a trace log:\n{0}")]
+    Io(#[from] std::io::Error),
+    #[error("An error occurred while parsing a trace log:\n{0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(feature = "trace_json")]
+impl<K, C> RecordingTrace<K, C>
+where
+    K: serde::Serialize + serde::de::DeserializeOwned,
+    C: serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Write the events as JSON Lines, with one event per line.
+    pub fn write_json_lines(&self, mut writer: impl Write) -> Result<(), TraceLogError> {
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, event)?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Read events that were written by [`RecordingTrace::write_json_lines`].
+    pub fn read_json_lines(reader: impl BufRead) -> Result<Self, TraceLogError> {
+        let mut events: Vec<TraceEvent<K, C>> = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            events.push(serde_json::from_str(&line)?);
+        }
+
+        let step = events.last().map(|e| e.step).unwrap_or(0);
+        Ok(Self { events, step })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star,
+        algorithm::Status,
+        directed::SimpleGraph,
+        error::NoError,
+        expander::Constrainable,
+        motion::r2::{
+            graph_search::{make_default_expander, DefaultNode},
+            timed_position::LineFollow,
+            Point,
+        },
+        planner::make_planner,
+    };
+    use std::sync::Arc;
+
+    fn make_test_graph() -> SimpleGraph<Point> {
+        /*
+         * 0-----1-----2
+         *       |
+         *       3
+         */
+        SimpleGraph::from_iters(
+            [
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 0.0),
+                Point::new(2.0, 0.0),
+                Point::new(1.0, -1.0),
+            ],
+            [(0, 1), (1, 0), (1, 2), (2, 1), (1, 3), (3, 1)],
+        )
+    }
+
+    #[test]
+    fn test_recording_trace() {
+        let expander = make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        );
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner.trace(&0, 2, RecordingTrace::default()).unwrap();
+        assert!(matches!(progress.solve().unwrap(), Status::Solved(_)));
+
+        let events = progress.trace().events();
+        assert_eq!(events.first().unwrap().kind, TraceEventKind::ExpandedTo);
+        assert_eq!(events.first().unwrap().step, 0);
+        assert_eq!(events.first().unwrap().key, Some(0));
+        let last = events.last().unwrap();
+        assert_eq!(last.kind, TraceEventKind::SolutionFoundFrom);
+        assert_eq!(last.key, Some(2));
+        assert_eq!(last.parent, Some(1));
+        assert_eq!(last.cost, 2_000_000_000);
+        assert_eq!(last.heuristic, 0);
+
+        for event in events {
+            if event.kind == TraceEventKind::ExpandedTo && event.step > 0 {
+                // Every node that was queued by an expansion must have come
+                // from the node that was expanded at that step.
+                let expanded = events
+                    .iter()
+                    .find(|e| e.step == event.step && e.kind == TraceEventKind::ExpandedFrom)
+                    .unwrap();
+                assert_eq!(event.parent, expanded.key);
+            }
+        }
+
+        #[cfg(feature = "trace_json")]
+        {
+            let mut log = Vec::new();
+            progress.trace().write_json_lines(&mut log).unwrap();
+            let replayed = RecordingTrace::<usize, i64>::read_json_lines(log.as_slice()).unwrap();
+            assert_eq!(replayed.events(), events);
+        }
+    }
+
+    #[derive(Default)]
+    struct StepCounter {
+        begin: usize,
+        end: usize,
+    }
+
+    impl<N> Trace<N> for StepCounter {
+        fn expanded_from(&mut self, _: &N) {}
+        fn expanded_to(&mut self, _: &N) {}
+        fn solution_found_from(&mut self, _: &N) {}
+
+        fn step_begin(&mut self) {
+            self.begin += 1;
+        }
+
+        fn step_end(&mut self) {
+            self.end += 1;
+        }
+    }
+
+    #[test]
+    fn test_trace_combinators() {
+        let shared = SharedTrace::new(RecordingTrace::default());
+        let expander = make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        )
+        .targeted_constrain_fn(
+            |node: Arc<DefaultNode>, _: &usize| -> Result<Option<Arc<DefaultNode>>, NoError> {
+                if node.partial_key() == Some(&3) {
+                    return Ok(None);
+                }
+
+                Ok(Some(node))
+            },
+        )
+        .with_trace(shared.clone());
+
+        let trace = Tee(
+            Tee(shared.clone(), StepCounter::default()),
+            Tee(
+                FilterTrace::new(RecordingTrace::default(), |kind, _: &DefaultNode| {
+                    kind == TraceEventKind::ExpandedFrom
+                }),
+                SampleTrace::new(RecordingTrace::default(), 2),
+            ),
+        );
+
+        let planner = make_planner(Arc::new(expander), Arc::new(a_star::Algorithm));
+        let mut progress = planner.trace(&0, 2, trace).unwrap();
+        assert!(matches!(progress.solve().unwrap(), Status::Solved(_)));
+
+        let Tee(Tee(_, steps), Tee(filtered, sampled)) = progress.trace();
+        let all = shared.lock().events().to_vec();
+        assert!(all
+            .iter()
+            .any(|e| e.kind == TraceEventKind::ConstraintRejected && e.key == Some(3)));
+        // Expanding vertex 1 leads back to vertex 0, which is already closed
+        assert!(all
+            .iter()
+            .any(|e| e.kind == TraceEventKind::ClosedRejected && e.key == Some(0)));
+
+        assert!(steps.begin > 0);
+        assert_eq!(steps.begin, steps.end);
+
+        let expansions = all
+            .iter()
+            .filter(|e| e.kind == TraceEventKind::ExpandedFrom)
+            .count();
+        assert!(expansions > 0);
+        assert_eq!(filtered.inner().events().len(), expansions);
+
+        // Constraint rejections are reported by the expander, so only the
+        // shared trace sees them.
+        let planner_events = all
+            .iter()
+            .filter(|e| e.kind != TraceEventKind::ConstraintRejected)
+            .count();
+        assert_eq!(sampled.inner().events().len(), planner_events.div_ceil(2));
+    }
 }
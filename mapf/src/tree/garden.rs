@@ -27,7 +27,7 @@ use crate::{
 };
 use std::{
     cell::RefCell,
-    collections::hash_map::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 use thiserror::Error as ThisError;
@@ -53,9 +53,58 @@ pub type Error<E, S> = ErrorImpl<
     <E as Solvable>::SolveError,
 >;
 
+impl<I, E, S> From<PoisonedMutex> for ErrorImpl<I, E, S> {
+    fn from(_: PoisonedMutex) -> Self {
+        ErrorImpl::PoisenedMutex
+    }
+}
+
+/// Returned by the cache management functions of [`Garden`] when a thread
+/// panicked while it was using the cache.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("a mutex was poisoned")]
+pub struct PoisonedMutex;
+
+/// Bounds on how much search effort a [`Garden`] keeps cached. When a bound is
+/// exceeded, the least recently used entries are evicted first. A value of
+/// None means the cache can grow without limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// The maximum number of closed nodes, summed over all cached trees.
+    pub max_tree_nodes: Option<usize>,
+    /// The maximum number of cached solutions.
+    pub max_solutions: Option<usize>,
+}
+
+/// A summary of what a [`Garden`] currently has cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// How many search trees are cached.
+    pub trees: usize,
+    /// The number of closed nodes, summed over all cached trees.
+    pub tree_nodes: usize,
+    /// How many solutions are cached, including ones that recorded that the
+    /// goal is unreachable.
+    pub solutions: usize,
+}
+
+/// A snapshot of the solutions cached by a [`Garden`]. With the `serde`
+/// feature enabled this can be saved and later loaded into a garden that
+/// searches the same graph.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedSolutions<K, S> {
+    /// Each entry has a start key, a goal key, and the solution between them,
+    /// or None if the goal cannot be reached from the start.
+    pub solutions: Vec<(K, K, Option<S>)>,
+}
+
 type TreeCache<E> = MutexRefCell<HashMap<KeyOf<NodeOf<E>>, Arc<MutexRefCell<Tree<E>>>>>;
 type SolutionCache<E> =
     MutexRefCell<HashMap<(KeyOf<NodeOf<E>>, KeyOf<NodeOf<E>>), Option<SolutionOf<E>>>>;
+type TreeLru<E> = MutexRefCell<Lru<KeyOf<NodeOf<E>>, Arc<MutexRefCell<Tree<E>>>>>;
+type SolutionLru<E> =
+    MutexRefCell<Lru<(KeyOf<NodeOf<E>>, KeyOf<NodeOf<E>>), Option<SolutionOf<E>>>>;
 type ConnectionMap<E> =
     HashMap<KeyOf<NodeOf<E>>, (Option<Arc<NodeOf<E>>>, Option<Arc<NodeOf<ReverseOf<E>>>>)>;
 
@@ -65,14 +114,128 @@ fn new_tree_entry<E: Aimless<Node: Weighted> + Closable>(
     Arc::new(Mutex::new(RefCell::new(tree)))
 }
 
+/// A map that keeps track of the order in which its entries were used so that
+/// the least recently used entries can be evicted first. Each entry has a
+/// weight, and the map keeps track of the total weight of its entries.
+struct Lru<K, V> {
+    entries: HashMap<K, LruEntry<V>>,
+    order: BTreeMap<u64, K>,
+    clock: u64,
+    weight: usize,
+}
+
+struct LruEntry<V> {
+    value: V,
+    weight: usize,
+    last_used: u64,
+}
+
+impl<K, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            weight: 0,
+        }
+    }
+}
+
+impl<K: node::Key, V> Lru<K, V> {
+    /// Get an entry and mark it as the most recently used.
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.order.insert(self.clock, key.clone());
+        Some(&entry.value)
+    }
+
+    fn insert(&mut self, key: K, value: V, weight: usize) {
+        self.remove(&key);
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.weight += weight;
+        self.entries.insert(
+            key,
+            LruEntry {
+                value,
+                weight,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    fn set_weight(&mut self, key: &K, weight: usize) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.weight = self.weight - entry.weight + weight;
+            entry.weight = weight;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_used);
+        self.weight -= entry.weight;
+        Some(entry.value)
+    }
+
+    /// Evict the least recently used entries until the total weight is no
+    /// more than the limit.
+    fn evict(&mut self, limit: Option<usize>) {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return,
+        };
+
+        while self.weight > limit {
+            let oldest = match self.order.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => return,
+            };
+            self.remove(&oldest);
+        }
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let discard: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| !f(key, &entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in discard {
+            self.remove(&key);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Garden caches the Dijkstra trees that it grows from each start key, as well
+/// as every solution that it finds, so that repeated queries from the same
+/// start can reuse the search effort. Use [`CacheLimits`] to keep the memory
+/// usage of a long-lived garden bounded.
 pub struct Garden<E>
 where
     E: Aimless + Solvable + Closable,
     NodeOf<E>: Weighted + PartialKeyed,
 {
     expander: Arc<E>,
-    trees: TreeCache<E>,
-    solutions: SolutionCache<E>,
+    limits: Mutex<CacheLimits>,
+    trees: TreeLru<E>,
+    solutions: SolutionLru<E>,
 }
 
 impl<E> Garden<E>
@@ -81,13 +244,186 @@ where
     NodeOf<E>: Weighted + PartialKeyed,
 {
     pub fn new(expander: Arc<E>) -> Self {
+        Self::with_limits(expander, CacheLimits::default())
+    }
+
+    pub fn with_limits(expander: Arc<E>, limits: CacheLimits) -> Self {
         Self {
             expander,
+            limits: Mutex::new(limits),
             trees: Default::default(),
             solutions: Default::default(),
         }
     }
 
+    pub fn expander(&self) -> &Arc<E> {
+        &self.expander
+    }
+
+    pub fn limits(&self) -> Result<CacheLimits, PoisonedMutex> {
+        Ok(*self.limits.lock().map_err(|_| PoisonedMutex)?)
+    }
+
+    /// Change the cache limits. Entries will be evicted right away if the
+    /// cache exceeds the new limits.
+    pub fn set_limits(&self, limits: CacheLimits) -> Result<(), PoisonedMutex> {
+        *self.limits.lock().map_err(|_| PoisonedMutex)? = limits;
+        self.trees
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow_mut()
+            .evict(limits.max_tree_nodes);
+        self.solutions
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow_mut()
+            .evict(limits.max_solutions);
+        Ok(())
+    }
+
+    pub fn cache_stats(&self) -> Result<CacheStats, PoisonedMutex> {
+        let (trees, tree_nodes) = {
+            let trees = self.trees.lock().map_err(|_| PoisonedMutex)?;
+            let trees = trees.borrow();
+            (trees.len(), trees.weight)
+        };
+        let solutions = self
+            .solutions
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow()
+            .len();
+        Ok(CacheStats {
+            trees,
+            tree_nodes,
+            solutions,
+        })
+    }
+
+    /// Discard all cached trees and solutions.
+    pub fn clear(&self) -> Result<(), PoisonedMutex> {
+        self.trees
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow_mut()
+            .clear();
+        self.solutions
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow_mut()
+            .clear();
+        Ok(())
+    }
+
+    /// Discard the cached search effort that might be affected by a change to
+    /// the states whose keys satisfy the `affected` predicate. This should be
+    /// called after the costs or connections of those states have changed.
+    ///
+    /// A tree is discarded if it has closed or queued any affected state, along
+    /// with every solution that it produced. Trees that never looked at an
+    /// affected state remain optimal whether the change makes the affected
+    /// states cheaper or more expensive, so they are kept. Solutions whose tree
+    /// is no longer cached (e.g. because it was evicted or the solutions were
+    /// loaded from a file) cannot be checked, so they are discarded too.
+    ///
+    /// Queries that run at the same time as the invalidation might still cache
+    /// results that were computed before the change.
+    pub fn invalidate(
+        &self,
+        mut affected: impl FnMut(&KeyOf<NodeOf<E>>) -> bool,
+    ) -> Result<(), PoisonedMutex> {
+        // Collect the trees before inspecting them so that we do not hold the
+        // cache lock while waiting for a tree that is being grown.
+        let trees: Vec<_> = self
+            .trees
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow()
+            .iter()
+            .map(|(key, tree)| (key.clone(), tree.clone()))
+            .collect();
+
+        let mut stale = HashSet::new();
+        let mut valid = HashSet::new();
+        for (key, tree) in trees {
+            let touched = tree
+                .lock()
+                .map_err(|_| PoisonedMutex)?
+                .borrow()
+                .touches(&mut affected);
+            if touched {
+                stale.insert(key);
+            } else {
+                valid.insert(key);
+            }
+        }
+
+        {
+            let trees = self.trees.lock().map_err(|_| PoisonedMutex)?;
+            let mut trees = trees.borrow_mut();
+            for key in &stale {
+                trees.remove(key);
+            }
+        }
+
+        self.solutions
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow_mut()
+            .retain(|(start, _), _| valid.contains(start));
+
+        Ok(())
+    }
+
+    /// Take a snapshot of the solutions that are currently cached.
+    pub fn save_solutions(
+        &self,
+    ) -> Result<SavedSolutions<KeyOf<NodeOf<E>>, SolutionOf<E>>, PoisonedMutex>
+    where
+        SolutionOf<E>: Clone,
+    {
+        let solutions = self
+            .solutions
+            .lock()
+            .map_err(|_| PoisonedMutex)?
+            .borrow()
+            .iter()
+            .map(|((start, goal), solution)| (start.clone(), goal.clone(), solution.clone()))
+            .collect();
+
+        Ok(SavedSolutions { solutions })
+    }
+
+    /// Add previously saved solutions to the cache. The solutions must have
+    /// been found on the same graph that this garden is searching, otherwise
+    /// the results of future queries will be wrong.
+    pub fn load_solutions(
+        &self,
+        saved: SavedSolutions<KeyOf<NodeOf<E>>, SolutionOf<E>>,
+    ) -> Result<(), PoisonedMutex> {
+        let limit = self.limits()?.max_solutions;
+        let solutions = self.solutions.lock().map_err(|_| PoisonedMutex)?;
+        let mut solutions = solutions.borrow_mut();
+        for (start, goal, solution) in saved.solutions {
+            solutions.insert((start, goal), solution, 1);
+        }
+        solutions.evict(limit);
+        Ok(())
+    }
+
+    fn cache_solution(
+        &self,
+        key: (KeyOf<NodeOf<E>>, KeyOf<NodeOf<E>>),
+        solution: Option<SolutionOf<E>>,
+    ) -> Result<(), PoisonedMutex> {
+        let limit = self.limits()?.max_solutions;
+        let solutions = self.solutions.lock().map_err(|_| PoisonedMutex)?;
+        let mut solutions = solutions.borrow_mut();
+        solutions.insert(key, solution, 1);
+        solutions.evict(limit);
+        Ok(())
+    }
+
     pub fn solve<S>(
         &self,
         from: &S,
@@ -113,7 +449,7 @@ where
                     .solutions
                     .lock()
                     .map_err(|_| ErrorImpl::PoisenedMutex)?
-                    .borrow()
+                    .borrow_mut()
                     .get(&(start_key.clone(), to.clone()))
                 {
                     // The tree from this start point has already been grown towards
@@ -134,59 +470,75 @@ where
                 // This tree from this start point has not been grown towards
                 // this target yet.
                 let tree_arc = {
-                    self.trees
-                        .lock()
-                        .map_err(|_| ErrorImpl::PoisenedMutex)?
-                        .borrow_mut()
-                        .entry(start_key.clone())
-                        .or_insert_with(|| {
-                            new_tree_entry(Tree::new(start.clone(), self.expander.clone()))
-                        })
-                        .clone()
+                    let trees = self.trees.lock().map_err(|_| ErrorImpl::PoisenedMutex)?;
+                    let mut trees = trees.borrow_mut();
+                    if let Some(tree) = trees.get(start_key) {
+                        tree.clone()
+                    } else {
+                        let tree = new_tree_entry(Tree::new(start.clone(), self.expander.clone()));
+                        trees.insert(start_key.clone(), tree.clone(), 0);
+                        tree
+                    }
                 };
 
-                let tree_lock = tree_arc.lock().map_err(|_| ErrorImpl::PoisenedMutex)?;
-                let mut tree = tree_lock.borrow_mut();
-                if let Some(reached_goal) = tree.closed().get(to) {
-                    // This tree has already passed by the target, so we can
-                    // construct a solution for it.
-                    let solution = self
-                        .expander
-                        .make_solution(reached_goal)
-                        .map_err(ErrorImpl::Solve)?;
-                    self.solutions
-                        .lock()
-                        .map_err(|_| ErrorImpl::PoisenedMutex)?
-                        .borrow_mut()
-                        .insert((start_key.clone(), to.clone()), Some(solution.clone()));
-                    best_solution.consider_take(solution);
-                } else {
-                    // This tree has never passed by the taraget, so we need to
-                    // grow it until it reaches.
-                    while !tree.is_exhausted() {
+                let tree_size = {
+                    let tree_lock = tree_arc.lock().map_err(|_| ErrorImpl::PoisenedMutex)?;
+                    let mut tree = tree_lock.borrow_mut();
+                    if let Some(reached_goal) = tree.closed().get(to) {
+                        // This tree has already passed by the target, so we can
+                        // construct a solution for it.
+                        let solution = self
+                            .expander
+                            .make_solution(reached_goal)
+                            .map_err(ErrorImpl::Solve)?;
+                        self.cache_solution(
+                            (start_key.clone(), to.clone()),
+                            Some(solution.clone()),
+                        )?;
+                        best_solution.consider_take(solution);
+                    } else {
+                        // This tree has never passed by the taraget, so we need to
+                        // grow it until it reaches.
                         let mut found_solution = false;
-                        for node in tree.grow() {
-                            let node: Arc<NodeOf<E>> = node.map_err(ErrorImpl::Expansion)?;
-                            if node.partial_key() == Some(to) {
-                                let solution = self
-                                    .expander
-                                    .make_solution(&node)
-                                    .map_err(ErrorImpl::Solve)?;
-                                best_solution.consider(&solution);
-                                self.solutions
-                                    .lock()
-                                    .map_err(|_| ErrorImpl::PoisenedMutex)?
-                                    .borrow_mut()
-                                    .insert((start_key.clone(), to.clone()), Some(solution));
-                                found_solution = true;
+                        while !tree.is_exhausted() {
+                            for node in tree.grow() {
+                                let node: Arc<NodeOf<E>> = node.map_err(ErrorImpl::Expansion)?;
+                                if node.partial_key() == Some(to) {
+                                    let solution = self
+                                        .expander
+                                        .make_solution(&node)
+                                        .map_err(ErrorImpl::Solve)?;
+                                    best_solution.consider(&solution);
+                                    self.cache_solution(
+                                        (start_key.clone(), to.clone()),
+                                        Some(solution),
+                                    )?;
+                                    found_solution = true;
+                                }
+                            }
+
+                            if found_solution {
+                                break;
                             }
                         }
 
-                        if found_solution {
-                            break;
+                        if !found_solution {
+                            // The tree was exhausted without reaching the
+                            // target, so remember that it is unreachable.
+                            self.cache_solution((start_key.clone(), to.clone()), None)?;
                         }
                     }
-                }
+
+                    tree.size()
+                };
+
+                // The tree lock has been released, so now we can update the
+                // size of the tree in the cache and evict old trees if needed.
+                let limit = self.limits()?.max_tree_nodes;
+                let trees = self.trees.lock().map_err(|_| ErrorImpl::PoisenedMutex)?;
+                let mut trees = trees.borrow_mut();
+                trees.set_weight(start_key, tree_size);
+                trees.evict(limit);
             }
         }
 
@@ -527,4 +879,93 @@ mod tests {
         assert!(solution.is_some());
         print!("{solution:?}");
     }
+
+    #[test]
+    fn test_garden_cache_limits() {
+        let expander = Arc::new(make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        ));
+        let unlimited = Garden::new(expander.clone());
+        let limited = Garden::with_limits(
+            expander,
+            CacheLimits {
+                max_tree_nodes: Some(12),
+                max_solutions: Some(3),
+            },
+        );
+
+        for start in 0..5 {
+            let expected = unlimited.solve(&start, &8).unwrap().unwrap();
+            let solution = limited.solve(&start, &8).unwrap().unwrap();
+            assert_eq!(solution.cost(), expected.cost());
+        }
+
+        let stats = limited.cache_stats().unwrap();
+        assert_eq!(stats.solutions, 3);
+        assert!(stats.tree_nodes <= 12);
+        // Every tree needs to close at least 7 nodes to reach vertex 8, so
+        // only the most recently used tree can fit.
+        assert_eq!(stats.trees, 1);
+
+        let stats = unlimited.cache_stats().unwrap();
+        assert_eq!(stats.solutions, 5);
+        assert_eq!(stats.trees, 5);
+
+        limited
+            .set_limits(CacheLimits {
+                max_tree_nodes: Some(0),
+                max_solutions: Some(0),
+            })
+            .unwrap();
+        assert_eq!(limited.cache_stats().unwrap(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_garden_invalidation() {
+        let garden = Garden::new(Arc::new(make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        )));
+
+        // Each of these trees stops as soon as it closes its goal, leaving
+        // vertex 2 in its queue.
+        assert!(garden.solve(&0, &1).unwrap().is_some());
+        assert!(garden.solve(&6, &3).unwrap().is_some());
+
+        garden.invalidate(|v| *v == 8).unwrap();
+        let stats = garden.cache_stats().unwrap();
+        assert_eq!((stats.trees, stats.solutions), (2, 2));
+
+        garden.invalidate(|v| *v == 0).unwrap();
+        let stats = garden.cache_stats().unwrap();
+        assert_eq!((stats.trees, stats.solutions), (1, 1));
+
+        garden.invalidate(|v| *v == 2).unwrap();
+        assert_eq!(garden.cache_stats().unwrap(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_garden_saved_solutions() {
+        let expander = Arc::new(make_default_expander(
+            Arc::new(make_test_graph()),
+            Arc::new(LineFollow::new(1.0).unwrap()),
+        ));
+        let garden = Garden::new(expander.clone());
+        let expected = garden.solve(&0, &8).unwrap().unwrap();
+
+        let saved = garden.save_solutions().unwrap();
+        #[cfg(feature = "serde")]
+        let saved = {
+            let text = serde_json::to_string(&saved).unwrap();
+            serde_json::from_str(&text).unwrap()
+        };
+
+        let restored = Garden::new(expander);
+        restored.load_solutions(saved).unwrap();
+        let solution = restored.solve(&0, &8).unwrap().unwrap();
+        assert_eq!(solution.cost(), expected.cost());
+        // The solution came from the loaded cache, so no tree was grown
+        assert_eq!(restored.cache_stats().unwrap().trees, 0);
+    }
 }
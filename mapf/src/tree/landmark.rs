@@ -0,0 +1,546 @@
+/*
+ * Copyright (C) 2022 Open Source Robotics Foundation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ *
+*/
+
+use super::Tree;
+use crate::{
+    error::NoError,
+    expander::{Aimless, AimlessErrorOf, Closable, InitAimless, InitAimlessErrorOf},
+    heuristic::Heuristic,
+    motion::r2,
+    node::{Cost, Key, Keyed, PartialKeyed, Weighted},
+};
+use std::{collections::HashMap, f64::consts::PI, ops::Sub, sync::Arc};
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum LandmarkErrorImpl<I, E> {
+    #[error("An error occurred while initializing a search from a landmark:\n{0}")]
+    Init(I),
+    #[error("An error occurred while expanding a search from a landmark:\n{0}")]
+    Expansion(E),
+    #[error("The center vertex of a planar landmark selection has no position")]
+    MissingCenter,
+}
+
+// We use this wrapper because otherwise derive(Debug) does not work correctly
+pub type LandmarkError<E, K> = LandmarkErrorImpl<InitAimlessErrorOf<E, K>, AimlessErrorOf<E>>;
+
+/// Precomputed tables for the ALT (A*, Landmarks, Triangle inequality)
+/// heuristic. For a small set of landmark vertices we store the exact cost of
+/// travelling from each landmark to every vertex and from every vertex to each
+/// landmark. The triangle inequality then gives a lower bound on the cost
+/// between any two vertices:
+///
+/// `d(v, t) >= max(d(L, t) - d(L, v), d(v, L) - d(t, L))`
+///
+/// The tables are built with exhaustive [`Tree`] searches, so building them is
+/// expensive for large graphs. With the `serde` feature enabled the tables can
+/// be serialized, which allows them to be built offline and loaded later.
+///
+/// The searches use aimless expansion, so the expanders should be keyed by
+/// graph vertex and their costs should not depend on time, e.g. the r2
+/// [`DefaultExpander`](crate::motion::r2::graph_search::DefaultExpander).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        try_from = "LandmarkData<K, C>",
+        into = "LandmarkData<K, C>",
+        bound(
+            serialize = "K: serde::Serialize, C: serde::Serialize",
+            deserialize = "K: serde::Deserialize<'de>, C: serde::Deserialize<'de>"
+        )
+    )
+)]
+pub struct Landmarks<K: Key, C: Cost> {
+    vertices: Vec<K>,
+    index: HashMap<K, usize>,
+    landmarks: Vec<usize>,
+    /// `from_landmark[i][v]` is the cost from landmark `i` to vertex `v`
+    from_landmark: Vec<Vec<Option<C>>>,
+    /// `to_landmark[i][v]` is the cost from vertex `v` to landmark `i`
+    to_landmark: Vec<Vec<Option<C>>>,
+}
+
+impl<K: Key, C: Cost> Landmarks<K, C> {
+    /// Build landmark tables for an explicit choice of landmarks.
+    ///
+    /// The `forward` expander is used to find the costs from each landmark.
+    /// The `reverse` expander must search the same graph with every edge
+    /// reversed (e.g. using [`SimpleGraph::reverse`](crate::directed::SimpleGraph::reverse)),
+    /// and is used to find the costs to each landmark. For undirected graphs
+    /// the same expander can be passed for both. The reversed search assumes
+    /// that traversing a reversed edge costs the same as traversing the
+    /// original edge.
+    pub fn with_landmarks<E>(
+        forward: Arc<E>,
+        reverse: Arc<E>,
+        landmarks: impl IntoIterator<Item = K>,
+    ) -> Result<Self, LandmarkError<E, K>>
+    where
+        E: InitAimless<K> + Aimless<Node: Weighted<Cost = C> + PartialKeyed<Key = K>> + Closable,
+    {
+        let mut from_landmark = Vec::new();
+        let mut chosen = Vec::new();
+        for landmark in landmarks {
+            from_landmark.push(explore(&forward, &landmark)?);
+            chosen.push(landmark);
+        }
+
+        return Self::finish(reverse, chosen, from_landmark);
+    }
+
+    /// Choose `count` landmarks using farthest-point selection. The first
+    /// landmark is the vertex that is most costly to reach from `seed`, and
+    /// each landmark after that is the vertex whose cost from its nearest
+    /// landmark is largest. Landmarks chosen this way tend to sit on the
+    /// periphery of the graph, which is where they give the tightest bounds.
+    ///
+    /// Fewer than `count` landmarks will be chosen if the graph runs out of
+    /// distinct vertices that are reachable from the seed.
+    pub fn farthest<E>(
+        forward: Arc<E>,
+        reverse: Arc<E>,
+        seed: &K,
+        count: usize,
+    ) -> Result<Self, LandmarkError<E, K>>
+    where
+        E: InitAimless<K> + Aimless<Node: Weighted<Cost = C> + PartialKeyed<Key = K>> + Closable,
+    {
+        // The cost of reaching each vertex from whichever landmark reaches it
+        // most cheaply. Until the first landmark is chosen, the seed stands in.
+        let mut nearest = explore(&forward, seed)?;
+        let mut chosen: Vec<K> = Vec::new();
+        let mut from_landmark = Vec::new();
+        while chosen.len() < count {
+            let next = nearest
+                .iter()
+                .filter(|(key, _)| !chosen.contains(*key))
+                .max_by_key(|(_, cost)| **cost)
+                .map(|(key, _)| key.clone());
+
+            let next = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            let costs = explore(&forward, &next)?;
+            for (key, cost) in &costs {
+                if let Some(previous) = nearest.get_mut(key) {
+                    *previous = (*previous).min(*cost);
+                }
+            }
+
+            chosen.push(next);
+            from_landmark.push(costs);
+        }
+
+        return Self::finish(reverse, chosen, from_landmark);
+    }
+
+    /// Choose up to `count` landmarks using planar selection. The plane around
+    /// `center` is divided into `count` equal sectors and the landmark of each
+    /// sector is the vertex in that sector which is most costly to reach from
+    /// `center`. Sectors that do not contain any reachable vertex are skipped.
+    ///
+    /// The `position` function gives the location of each vertex. Vertices
+    /// without a position are never chosen as landmarks.
+    pub fn planar<E>(
+        forward: Arc<E>,
+        reverse: Arc<E>,
+        center: &K,
+        count: usize,
+        position: impl Fn(&K) -> Option<r2::Position>,
+    ) -> Result<Self, LandmarkError<E, K>>
+    where
+        E: InitAimless<K> + Aimless<Node: Weighted<Cost = C> + PartialKeyed<Key = K>> + Closable,
+    {
+        let origin = position(center).ok_or(LandmarkErrorImpl::MissingCenter)?;
+        let mut sectors: Vec<Option<(K, C)>> = Vec::new();
+        sectors.resize(count, None);
+        if count > 0 {
+            let width = 2.0 * PI / count as f64;
+            for (key, cost) in explore(&forward, center)? {
+                let p = match position(&key) {
+                    Some(p) => p,
+                    None => continue,
+                };
+
+                let dp = p - origin;
+                if dp.norm() == 0.0 {
+                    continue;
+                }
+
+                let angle = dp[1].atan2(dp[0]) + PI;
+                let sector = ((angle / width) as usize).min(count - 1);
+                let best = &mut sectors[sector];
+                if best.as_ref().filter(|(_, c)| *c >= cost).is_none() {
+                    *best = Some((key, cost));
+                }
+            }
+        }
+
+        let chosen: Vec<K> = sectors.into_iter().flatten().map(|(key, _)| key).collect();
+        return Self::with_landmarks(forward, reverse, chosen);
+    }
+
+    fn finish<E>(
+        reverse: Arc<E>,
+        chosen: Vec<K>,
+        from_costs: Vec<HashMap<K, C>>,
+    ) -> Result<Self, LandmarkError<E, K>>
+    where
+        E: InitAimless<K> + Aimless<Node: Weighted<Cost = C> + PartialKeyed<Key = K>> + Closable,
+    {
+        let mut to_costs = Vec::new();
+        for landmark in &chosen {
+            to_costs.push(explore(&reverse, landmark)?);
+        }
+
+        let mut vertices = Vec::new();
+        let mut index = HashMap::new();
+        for key in chosen
+            .iter()
+            .chain(from_costs.iter().flat_map(|c| c.keys()))
+            .chain(to_costs.iter().flat_map(|c| c.keys()))
+        {
+            if !index.contains_key(key) {
+                index.insert(key.clone(), vertices.len());
+                vertices.push(key.clone());
+            }
+        }
+
+        let make_table = |costs: &Vec<HashMap<K, C>>| -> Vec<Vec<Option<C>>> {
+            costs
+                .iter()
+                .map(|c| vertices.iter().map(|key| c.get(key).copied()).collect())
+                .collect()
+        };
+
+        let from_landmark = make_table(&from_costs);
+        let to_landmark = make_table(&to_costs);
+        let landmarks = chosen.iter().map(|key| index[key]).collect();
+        return Ok(Self {
+            vertices,
+            index,
+            landmarks,
+            from_landmark,
+            to_landmark,
+        });
+    }
+
+    /// Iterate over the vertices that were chosen as landmarks.
+    pub fn landmarks(&self) -> impl Iterator<Item = &K> + '_ {
+        self.landmarks.iter().map(|i| &self.vertices[*i])
+    }
+
+    /// The number of vertices covered by the tables.
+    pub fn num_vertices(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Get the exact cost of travelling from the `landmark`-th landmark to a
+    /// vertex. Returns None if the vertex is not reachable from the landmark.
+    pub fn cost_from_landmark(&self, landmark: usize, vertex: &K) -> Option<C> {
+        let v = *self.index.get(vertex)?;
+        self.from_landmark.get(landmark)?[v]
+    }
+
+    /// Get the exact cost of travelling from a vertex to the `landmark`-th
+    /// landmark. Returns None if the landmark is not reachable from the vertex.
+    pub fn cost_to_landmark(&self, landmark: usize, vertex: &K) -> Option<C> {
+        let v = *self.index.get(vertex)?;
+        self.to_landmark.get(landmark)?[v]
+    }
+}
+
+impl<K, C, S, Goal> Heuristic<S, Goal, C> for Landmarks<K, C>
+where
+    K: Key,
+    C: Cost + Sub<Output = C>,
+    S: Into<K> + Clone,
+    Goal: Keyed<Key = K>,
+{
+    type Error = NoError;
+
+    fn estimate_cost(&self, from_state: &S, to_goal: &Goal) -> Result<Option<C>, Self::Error> {
+        let from: K = from_state.clone().into();
+        let (v, t) = match (self.index.get(&from), self.index.get(to_goal.key())) {
+            (Some(v), Some(t)) => (*v, *t),
+            // The tables have no information about this vertex
+            _ => return Ok(Some(C::zero())),
+        };
+
+        let mut estimate = C::zero();
+        for (from_l, to_l) in self.from_landmark.iter().zip(&self.to_landmark) {
+            // d(L, t) <= d(L, v) + d(v, t)
+            match (from_l[v], from_l[t]) {
+                (Some(lv), Some(lt)) => {
+                    if lt > lv {
+                        estimate = estimate.max(lt - lv);
+                    }
+                }
+                // The landmark reaches v but not t, so v cannot reach t
+                (Some(_), None) => return Ok(None),
+                _ => {}
+            }
+
+            // d(v, L) <= d(v, t) + d(t, L)
+            match (to_l[v], to_l[t]) {
+                (Some(vl), Some(tl)) => {
+                    if vl > tl {
+                        estimate = estimate.max(vl - tl);
+                    }
+                }
+                // t reaches the landmark but v does not, so v cannot reach t
+                (None, Some(_)) => return Ok(None),
+                _ => {}
+            }
+        }
+
+        return Ok(Some(estimate));
+    }
+}
+
+/// Find the exact cost of reaching every vertex that can be reached from the
+/// given start vertex.
+fn explore<E, K, C>(expander: &Arc<E>, from: &K) -> Result<HashMap<K, C>, LandmarkError<E, K>>
+where
+    K: Key,
+    C: Cost,
+    E: InitAimless<K> + Aimless<Node: Weighted<Cost = C> + PartialKeyed<Key = K>> + Closable,
+{
+    let mut costs = HashMap::new();
+    for start in expander.aimless_start(from) {
+        let start = start.map_err(LandmarkErrorImpl::Init)?;
+        let mut tree = Tree::new(start, expander.clone());
+        while !tree.is_exhausted() {
+            for node in tree.grow() {
+                let node = node.map_err(LandmarkErrorImpl::Expansion)?;
+                if let Some(key) = node.partial_key() {
+                    let cost = node.cost();
+                    let entry = costs.entry(key.clone()).or_insert(cost);
+                    *entry = (*entry).min(cost);
+                }
+            }
+        }
+    }
+
+    return Ok(costs);
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LandmarkData<K, C> {
+    vertices: Vec<K>,
+    landmarks: Vec<usize>,
+    from_landmark: Vec<Vec<Option<C>>>,
+    to_landmark: Vec<Vec<Option<C>>>,
+}
+
+#[cfg(feature = "serde")]
+impl<K: Key, C: Cost> TryFrom<LandmarkData<K, C>> for Landmarks<K, C> {
+    type Error = &'static str;
+    fn try_from(data: LandmarkData<K, C>) -> Result<Self, Self::Error> {
+        let n = data.vertices.len();
+        if data.landmarks.iter().any(|i| *i >= n) {
+            return Err("landmark index is out of range of the vertices");
+        }
+
+        if data.from_landmark.len() != data.landmarks.len()
+            || data.to_landmark.len() != data.landmarks.len()
+        {
+            return Err("the number of cost tables must match the number of landmarks");
+        }
+
+        if data
+            .from_landmark
+            .iter()
+            .chain(&data.to_landmark)
+            .any(|table| table.len() != n)
+        {
+            return Err("every cost table must have one entry per vertex");
+        }
+
+        let index: HashMap<K, usize> = data
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.clone(), i))
+            .collect();
+        if index.len() != n {
+            return Err("the vertices must not contain duplicates");
+        }
+
+        return Ok(Self {
+            vertices: data.vertices,
+            index,
+            landmarks: data.landmarks,
+            from_landmark: data.from_landmark,
+            to_landmark: data.to_landmark,
+        });
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: Key, C: Cost> From<Landmarks<K, C>> for LandmarkData<K, C> {
+    fn from(landmarks: Landmarks<K, C>) -> Self {
+        Self {
+            vertices: landmarks.vertices,
+            landmarks: landmarks.landmarks,
+            from_landmark: landmarks.from_landmark,
+            to_landmark: landmarks.to_landmark,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        directed::SimpleGraph,
+        motion::r2::{
+            graph_search::{make_default_expander, DefaultExpander},
+            timed_position::LineFollow,
+            Point,
+        },
+    };
+
+    fn make_test_graph() -> SimpleGraph<Point> {
+        /*
+         * 0-----1-----2-----3
+         *           /       |
+         *         /         |
+         *       4-----5     6
+         *             |
+         *             |
+         *             7-----8
+         *                    \
+         *                     9
+         *
+         * The edge from 8 to 9 is one-way.
+         */
+
+        let vertices = vec![
+            Point::new(0.0, 0.0),  // 0
+            Point::new(1.0, 0.0),  // 1
+            Point::new(2.0, 0.0),  // 2
+            Point::new(3.0, 0.0),  // 3
+            Point::new(1.0, -1.0), // 4
+            Point::new(2.0, -1.0), // 5
+            Point::new(3.0, -1.0), // 6
+            Point::new(2.0, -2.0), // 7
+            Point::new(3.0, -2.0), // 8
+            Point::new(4.0, -3.0), // 9
+        ];
+
+        let mut edges = Vec::<Vec<usize>>::new();
+        edges.resize(vertices.len(), Vec::new());
+        let mut add_bidir_edge = |v0: usize, v1: usize| {
+            edges[v0].push(v1);
+            edges[v1].push(v0);
+        };
+        add_bidir_edge(0, 1);
+        add_bidir_edge(1, 2);
+        add_bidir_edge(2, 3);
+        add_bidir_edge(2, 4);
+        add_bidir_edge(3, 6);
+        add_bidir_edge(4, 5);
+        add_bidir_edge(5, 7);
+        add_bidir_edge(7, 8);
+        edges[8].push(9);
+
+        return SimpleGraph::new(vertices, edges);
+    }
+
+    fn make_expanders(graph: SimpleGraph<Point>) -> (Arc<DefaultExpander>, Arc<DefaultExpander>) {
+        let extrapolator = Arc::new(LineFollow::new(1.0).unwrap());
+        let reverse = Arc::new(graph.reverse());
+        (
+            Arc::new(make_default_expander(Arc::new(graph), extrapolator.clone())),
+            Arc::new(make_default_expander(reverse, extrapolator)),
+        )
+    }
+
+    fn check_estimates(landmarks: &Landmarks<usize, i64>, forward: &Arc<DefaultExpander>) {
+        for v in 0..10 {
+            let exact = explore(forward, &v).unwrap();
+            for t in 0..10 {
+                if let Some(cost) = exact.get(&t) {
+                    let estimate = landmarks.estimate_cost(&v, &t).unwrap();
+                    assert!(estimate.unwrap() <= *cost);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_farthest_landmarks() {
+        let (forward, reverse) = make_expanders(make_test_graph());
+        let landmarks = Landmarks::farthest(forward.clone(), reverse, &0, 3).unwrap();
+        assert_eq!(landmarks.landmarks().count(), 3);
+        assert_eq!(landmarks.num_vertices(), 10);
+        // Vertex 9 is the most costly vertex to reach from the seed
+        assert_eq!(landmarks.landmarks().next(), Some(&9));
+        check_estimates(&landmarks, &forward);
+
+        // Nothing can be reached from vertex 9
+        assert_eq!(landmarks.estimate_cost(&9usize, &0usize).unwrap(), None);
+        // The tables should give a useful bound between distant vertices
+        assert!(landmarks.estimate_cost(&0usize, &8usize).unwrap().unwrap() > 0);
+        assert_eq!(landmarks.estimate_cost(&4usize, &4usize).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_planar_landmarks() {
+        let graph = make_test_graph();
+        let positions = graph.vertices.clone();
+        let (forward, reverse) = make_expanders(graph);
+        let landmarks = Landmarks::planar(forward.clone(), reverse, &5, 4, |v| {
+            positions.get(*v).copied()
+        })
+        .unwrap();
+        assert!(landmarks.landmarks().count() > 1);
+        check_estimates(&landmarks, &forward);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_landmark_serialization() {
+        let (forward, reverse) = make_expanders(make_test_graph());
+        let landmarks = Landmarks::farthest(forward.clone(), reverse, &0, 2).unwrap();
+        let text = serde_json::to_string(&landmarks).unwrap();
+        let parsed: Landmarks<usize, i64> = serde_json::from_str(&text).unwrap();
+        assert_eq!(
+            parsed.landmarks().collect::<Vec<_>>(),
+            landmarks.landmarks().collect::<Vec<_>>()
+        );
+        for v in 0..10usize {
+            for t in 0..10usize {
+                assert_eq!(
+                    parsed.estimate_cost(&v, &t).unwrap(),
+                    landmarks.estimate_cost(&v, &t).unwrap(),
+                );
+            }
+        }
+
+        let truncated =
+            r#"{"vertices":[0,1],"landmarks":[0],"from_landmark":[[0]],"to_landmark":[[0,1]]}"#;
+        assert!(serde_json::from_str::<Landmarks<usize, i64>>(truncated).is_err());
+    }
+}
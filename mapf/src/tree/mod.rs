@@ -20,3 +20,6 @@ pub use tree::Tree;
 
 pub mod garden;
 pub use garden::{BidirGarden, Garden};
+
+pub mod landmark;
+pub use landmark::Landmarks;
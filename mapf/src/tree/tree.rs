@@ -16,7 +16,7 @@
 */
 
 use crate::expander::{Aimless, Closable};
-use crate::node::{ClosedSet, CostCmp, Weighted};
+use crate::node::{ClosedSet, CostCmp, PartialKeyed, Weighted};
 use num::Zero;
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
@@ -31,6 +31,7 @@ pub struct Tree<E: Aimless<Node: Weighted> + Closable> {
     closed_set: <E as Closable>::ClosedSet,
     queue: BinaryHeap<Reverse<CostCmp<E::Node>>>,
     expander: Arc<E>,
+    size: usize,
 }
 
 impl<E: Aimless<Node: Weighted> + Closable> Tree<E> {
@@ -41,6 +42,7 @@ impl<E: Aimless<Node: Weighted> + Closable> Tree<E> {
             closed_set: Default::default(),
             queue,
             expander,
+            size: 0,
         }
     }
 
@@ -71,6 +73,34 @@ impl<E: Aimless<Node: Weighted> + Closable> Tree<E> {
     pub fn is_exhausted(&self) -> bool {
         self.queue.is_empty()
     }
+
+    /// The number of nodes that this tree has closed so far.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl<E: Aimless<Node: Weighted + PartialKeyed> + Closable> Tree<E> {
+    /// Check whether any node that has been closed or queued by this tree has
+    /// a key that satisfies the `affected` predicate. If this returns false
+    /// then the tree has never looked at any affected states, so its closed
+    /// set remains optimal even if the costs of those states change.
+    pub fn touches(
+        &self,
+        mut affected: impl FnMut(&<E::Node as PartialKeyed>::Key) -> bool,
+    ) -> bool {
+        let closed = self.closed_set.iter().into_iter();
+        let queued = self.queue.iter().map(|n| &n.0 .0);
+        for node in closed.chain(queued) {
+            if let Some(key) = node.partial_key() {
+                if affected(key) {
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
 }
 
 pub struct Growth<'a, E: Aimless<Node: Weighted> + Closable> {
@@ -89,6 +119,7 @@ impl<'a, N: Weighted, E: Aimless<Node = N> + Closable> Iterator for Growth<'a, E
                 if top.0 .0.cost().eq(&self.expected_cost) {
                     if let Some(top) = self.tree.queue.pop().map(|n| n.0 .0) {
                         if self.tree.closed_set.close(&top).accepted() {
+                            self.tree.size += 1;
                             for child in self.tree.expander.aimless_expand(&top) {
                                 match child {
                                     Ok(child) => {